@@ -0,0 +1,3 @@
+-- Add up migration script here
+alter table user_account
+    add column if not exists password_hash varchar(255);
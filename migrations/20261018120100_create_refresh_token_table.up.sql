@@ -0,0 +1,14 @@
+-- Add up migration script here
+create table if not exists refresh_token
+(
+    id                int primary key generated always as identity,
+    user_id           int          not null references user_account (id) on delete cascade,
+    family_id         uuid         not null,
+    token_hash        varchar(64)  not null unique,
+    expires_timestamp timestamp    not null,
+    used_timestamp    timestamp,
+    revoked_timestamp timestamp,
+    created_timestamp timestamp    not null default now()
+);
+
+create index if not exists refresh_token_family_id_idx on refresh_token (family_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop table if exists user_session_revocation;
+drop table if exists revoked_token;
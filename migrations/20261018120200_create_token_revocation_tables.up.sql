@@ -0,0 +1,14 @@
+-- Add up migration script here
+create table if not exists revoked_token
+(
+    jti               uuid primary key,
+    user_id           int       not null references user_account (id) on delete cascade,
+    expires_timestamp timestamp not null,
+    revoked_timestamp timestamp not null default now()
+);
+
+create table if not exists user_session_revocation
+(
+    user_id           int primary key references user_account (id) on delete cascade,
+    revoked_timestamp timestamp not null
+);
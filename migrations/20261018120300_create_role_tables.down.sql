@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop table if exists user_role;
+drop table if exists role_permission;
+drop table if exists permission;
+drop table if exists role;
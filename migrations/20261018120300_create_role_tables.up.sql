@@ -0,0 +1,46 @@
+-- Add up migration script here
+create table if not exists role
+(
+    id   int primary key generated always as identity,
+    name varchar(64) not null unique
+);
+
+create table if not exists permission
+(
+    id   int primary key generated always as identity,
+    name varchar(64) not null unique
+);
+
+create table if not exists role_permission
+(
+    role_id       int not null references role (id) on delete cascade,
+    permission_id int not null references permission (id) on delete cascade,
+    primary key (role_id, permission_id)
+);
+
+create table if not exists user_role
+(
+    user_id int not null references user_account (id) on delete cascade,
+    role_id int not null references role (id) on delete cascade,
+    primary key (user_id, role_id)
+);
+
+insert into role (name)
+values ('admin'),
+       ('user')
+on conflict do nothing;
+
+insert into permission (name)
+values ('user:read'),
+       ('user:create'),
+       ('user:update'),
+       ('user:delete')
+on conflict do nothing;
+
+insert into role_permission (role_id, permission_id)
+select r.id, p.id
+from role r
+         cross join permission p
+where r.name = 'admin'
+   or (r.name = 'user' and p.name = 'user:read')
+on conflict do nothing;
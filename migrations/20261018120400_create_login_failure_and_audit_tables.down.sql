@@ -0,0 +1,7 @@
+-- Add down migration script here
+delete
+from permission
+where name = 'user:unlock';
+
+drop table if exists audit_log;
+drop table if exists login_failure;
@@ -0,0 +1,34 @@
+-- Add up migration script here
+create table if not exists login_failure
+(
+    scope                  varchar(16)  not null,
+    subject                varchar(255) not null,
+    failures               int          not null default 0,
+    last_failure_timestamp timestamp    not null,
+    locked_until           timestamp,
+    primary key (scope, subject)
+);
+
+create table if not exists audit_log
+(
+    id                int primary key generated always as identity,
+    action            varchar(64)  not null,
+    subject           varchar(255) not null,
+    actor             varchar(255),
+    detail            text,
+    created_timestamp timestamp    not null default now()
+);
+
+create index if not exists audit_log_subject_idx on audit_log (subject);
+
+insert into permission (name)
+values ('user:unlock')
+on conflict do nothing;
+
+insert into role_permission (role_id, permission_id)
+select r.id, p.id
+from role r
+         cross join permission p
+where r.name = 'admin'
+  and p.name = 'user:unlock'
+on conflict do nothing;
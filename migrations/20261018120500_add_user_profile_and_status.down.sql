@@ -0,0 +1,10 @@
+-- Add down migration script here
+drop index if exists user_account_email_key;
+
+alter table user_account
+    drop column if exists deleted_at,
+    drop column if exists status,
+    drop column if exists display_name,
+    drop column if exists email;
+
+drop type if exists user_status;
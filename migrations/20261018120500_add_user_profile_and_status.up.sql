@@ -0,0 +1,20 @@
+-- Add up migration script here
+do
+$$
+    begin
+        create type user_status as enum ('pending', 'active', 'suspended', 'deleted');
+    exception
+        when duplicate_object then null;
+    end
+$$;
+
+alter table user_account
+    add column if not exists email        varchar(255),
+    add column if not exists display_name varchar(255),
+    add column if not exists status       user_status not null default 'active',
+    add column if not exists deleted_at   timestamp;
+
+-- deleted users give up their email, so it can be used to register again
+create unique index if not exists user_account_email_key
+    on user_account (lower(email))
+    where deleted_at is null;
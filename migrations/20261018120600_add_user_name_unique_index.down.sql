@@ -0,0 +1,2 @@
+-- Add down migration script here
+drop index if exists user_account_user_name_key;
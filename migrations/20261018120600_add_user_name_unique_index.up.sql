@@ -0,0 +1,26 @@
+-- Add up migration script here
+-- user names are unique regardless of case, so `Foo` and `foo` can't both log in; deleted users
+-- give up their name like they give up their email. Existing duplicates can't be merged safely, so
+-- they must be renamed or deleted before the index is created.
+do
+$$
+    declare
+        duplicates text;
+    begin
+        select string_agg(format('%s (ids %s)', user_name, ids), ', ')
+        into duplicates
+        from (select lower(user_name) as user_name, string_agg(id::text, ', ' order by id) as ids
+              from user_account
+              where deleted_at is null
+              group by lower(user_name)
+              having count(*) > 1) as duplicate;
+
+        if duplicates is not null then
+            raise exception 'User names must be unique regardless of case, rename or delete the users sharing a name: %', duplicates;
+        end if;
+    end
+$$;
+
+create unique index if not exists user_account_user_name_key
+    on user_account (lower(user_name))
+    where deleted_at is null;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+alter table user_account
+    drop column if exists version;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- bumped on every change, backing the ETag of user resources
+alter table user_account
+    add column if not exists version integer not null default 1;
@@ -0,0 +1,16 @@
+-- Add down migration script here
+delete
+from revoked_token
+where user_id not in (select id from user_account);
+
+delete
+from user_session_revocation
+where user_id not in (select id from user_account);
+
+alter table revoked_token
+    add constraint revoked_token_user_id_fkey
+        foreign key (user_id) references user_account (id) on delete cascade;
+
+alter table user_session_revocation
+    add constraint user_session_revocation_user_id_fkey
+        foreign key (user_id) references user_account (id) on delete cascade;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- revocations must outlive a purged user until its tokens expire, so they no longer cascade
+alter table revoked_token
+    drop constraint if exists revoked_token_user_id_fkey;
+
+alter table user_session_revocation
+    drop constraint if exists user_session_revocation_user_id_fkey;
@@ -1,21 +1,386 @@
-use jsonwebtoken::{DecodingKey, EncodingKey};
-use std::sync::LazyLock;
+use crate::config::settings::AuthSettings;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simple_asn1::ASN1Block;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
 
-pub static KEYS: LazyLock<Keys> = LazyLock::new(|| {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET");
-    Keys::new(secret.as_bytes())
-});
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("No signing keys configured, set either JWT_KEYS_FILE or JWT_SECRET")]
+    NotConfigured,
+    #[error("Unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid key manifest: {0}")]
+    InvalidManifest(String),
+    #[error("Invalid key {0}: {1}")]
+    InvalidKey(String, String),
+    #[error("Key {0} uses unsupported algorithm {1:?}, expected RS256 or EdDSA")]
+    UnsupportedAlgorithm(String, Algorithm),
+}
 
-pub struct Keys {
-    pub encoding: EncodingKey,
+/// A single entry of the `JWT_KEYS_FILE` manifest. Keys sign new tokens between `active_from` and
+/// `retire_at`, and keep verifying tokens for as long as they stay in the manifest. Keys without a
+/// `private_key` can only be used for verification.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub public_key: PathBuf,
+    pub private_key: Option<PathBuf>,
+    pub active_from: Option<DateTime<Utc>>,
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+pub struct Key {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding: Option<EncodingKey>,
     pub decoding: DecodingKey,
+    pub active_from: Option<DateTime<Utc>>,
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+impl Key {
+    pub fn can_sign_at(&self, time: &DateTime<Utc>) -> bool {
+        self.encoding.is_some()
+            && self.active_from.is_none_or(|from| from <= *time)
+            && self.retire_at.is_none_or(|retire| retire > *time)
+    }
+
+    fn from_config(config: &KeyConfig, base: &Path) -> Result<Self, KeyError> {
+        let invalid = |e: jsonwebtoken::errors::Error| KeyError::InvalidKey(config.kid.clone(), e.to_string());
+        let public_key = read_pem(&base.join(&config.public_key))?;
+        let private_key = config
+            .private_key
+            .as_ref()
+            .map(|path| read_pem(&base.join(path)))
+            .transpose()?;
+        let (encoding, decoding) = match config.algorithm {
+            Algorithm::RS256 => (
+                private_key
+                    .map(|pem| EncodingKey::from_rsa_pem(&pem))
+                    .transpose()
+                    .map_err(invalid)?,
+                DecodingKey::from_rsa_pem(&public_key).map_err(invalid)?,
+            ),
+            Algorithm::EdDSA => (
+                private_key
+                    .map(|pem| EncodingKey::from_ed_pem(&pem))
+                    .transpose()
+                    .map_err(invalid)?,
+                DecodingKey::from_ed_pem(&public_key).map_err(invalid)?,
+            ),
+            algorithm => return Err(KeyError::UnsupportedAlgorithm(config.kid.clone(), algorithm)),
+        };
+
+        Ok(Self {
+            kid: config.kid.clone(),
+            algorithm: config.algorithm,
+            encoding,
+            decoding,
+            active_from: config.active_from,
+            retire_at: config.retire_at,
+        })
+    }
+
+    fn jwk(&self) -> Option<Jwk> {
+        let algorithm = match self.algorithm {
+            Algorithm::RS256 => {
+                let (n, e) = rsa_components(self.decoding.as_bytes())?;
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(n),
+                    e: URL_SAFE_NO_PAD.encode(e),
+                })
+            }
+            Algorithm::EdDSA => AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(self.decoding.as_bytes()),
+            }),
+            _ => return None,
+        };
+        let key_algorithm = match self.algorithm {
+            Algorithm::RS256 => KeyAlgorithm::RS256,
+            _ => KeyAlgorithm::EdDSA,
+        };
+
+        Some(Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(self.kid.clone()),
+                ..Default::default()
+            },
+            algorithm,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Keys {
+    keys: Arc<Vec<Key>>,
 }
 
 impl Keys {
-    pub fn new(secret: &[u8]) -> Self {
-        Self {
-            encoding: EncodingKey::from_secret(secret),
+    const SECRET_KID: &'static str = "default";
+
+    pub fn from_settings(settings: &AuthSettings) -> Result<Self, KeyError> {
+        if let Some(path) = &settings.keys_file {
+            return Self::from_manifest(path);
+        }
+
+        let secret = settings.secret.as_ref().ok_or(KeyError::NotConfigured)?;
+        warn!("Signing tokens with JWT_SECRET, other services won't be able to verify them");
+
+        Ok(Self::from_secret(secret.as_bytes()))
+    }
+
+    /// Loads the keys listed in a JSON manifest, resolving key paths relative to the manifest.
+    pub fn from_manifest(path: &Path) -> Result<Self, KeyError> {
+        let manifest = std::fs::read(path).map_err(|e| KeyError::Io(path.to_path_buf(), e))?;
+        let configs: Vec<KeyConfig> = serde_json::from_slice(&manifest)
+            .map_err(|e| KeyError::InvalidManifest(e.to_string()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        Self::from_configs(&configs, base)
+    }
+
+    pub fn from_configs(configs: &[KeyConfig], base: &Path) -> Result<Self, KeyError> {
+        let keys = configs
+            .iter()
+            .map(|config| Key::from_config(config, base))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keys = Self { keys: Arc::new(keys) };
+
+        match keys.signing_key() {
+            Some(key) => info!("Signing tokens with key {}", key.kid),
+            None => warn!("No signing key is currently active, token creation will fail"),
+        }
+
+        Ok(keys)
+    }
+
+    pub fn from_secret(secret: &[u8]) -> Self {
+        let key = Key {
+            kid: Self::SECRET_KID.to_string(),
+            algorithm: Algorithm::HS256,
+            encoding: Some(EncodingKey::from_secret(secret)),
             decoding: DecodingKey::from_secret(secret),
+            active_from: None,
+            retire_at: None,
+        };
+
+        Self { keys: Arc::new(vec![key]) }
+    }
+
+    /// The key new tokens are signed with, being the most recently activated key that hasn't
+    /// been retired yet.
+    pub fn signing_key(&self) -> Option<&Key> {
+        let now = Utc::now();
+
+        self.keys
+            .iter()
+            .filter(|key| key.can_sign_at(&now))
+            .max_by_key(|key| key.active_from)
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let key = self
+            .signing_key()
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat)?;
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+
+        encode(&header, claims, key.encoding.as_ref().unwrap())
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(Self::SECRET_KID);
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.kid == kid && key.algorithm == header.alg)
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidSignature)?;
+
+        decode(token, &key.decoding, &Validation::new(key.algorithm))
+    }
+
+    /// The public half of every asymmetric key, including retired and upcoming keys.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(Key::jwk).collect(),
+        }
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, KeyError> {
+    std::fs::read(path).map_err(|e| KeyError::Io(path.to_path_buf(), e))
+}
+
+/// Reads the modulus and exponent out of a DER encoded PKCS#1 RSA public key.
+fn rsa_components(der: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let blocks = simple_asn1::from_der(der).ok()?;
+    let Some(ASN1Block::Sequence(_, entries)) = blocks.first() else {
+        return None;
+    };
+
+    match entries.as_slice() {
+        [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e), ..] => {
+            Some((n.to_bytes_be().1, e.to_bytes_be().1))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::auth::JwtClaims;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    const TEST_KEYS: &str = "src/config/test_keys";
+
+    fn config(kid: &str, algorithm: Algorithm, name: &str) -> KeyConfig {
+        KeyConfig {
+            kid: kid.to_string(),
+            algorithm,
+            public_key: PathBuf::from(format!("{name}.pub.pem")),
+            private_key: Some(PathBuf::from(format!("{name}.pem"))),
+            active_from: None,
+            retire_at: None,
+        }
+    }
+
+    fn claims() -> JwtClaims {
+        JwtClaims {
+            sub: "1".to_string(),
+            exp: crate::util::now_epoch() + 60,
+            iat: crate::util::now_epoch(),
+            iat_us: None,
+            jti: Uuid::new_v4(),
+            sid: Uuid::new_v4(),
+            roles: vec![],
+        }
+    }
+
+    fn keys(configs: &[KeyConfig]) -> Keys {
+        Keys::from_configs(configs, Path::new(TEST_KEYS)).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for keys in [
+            keys(&[config("rsa", Algorithm::RS256, "rsa")]),
+            keys(&[config("ed", Algorithm::EdDSA, "ed25519")]),
+            Keys::from_secret(b"secret"),
+        ] {
+            let claims = claims();
+            let token = keys.encode(&claims).unwrap();
+            let decoded = keys.decode::<JwtClaims>(&token).unwrap();
+
+            assert_eq!(decoded.claims.jti, claims.jti);
+        }
+    }
+
+    #[test]
+    fn test_signs_with_newest_active_key() {
+        let now = Utc::now();
+        let keys = keys(&[
+            KeyConfig {
+                active_from: Some(now - Duration::days(30)),
+                retire_at: Some(now - Duration::days(1)),
+                ..config("retired", Algorithm::RS256, "rsa")
+            },
+            KeyConfig {
+                active_from: Some(now - Duration::days(7)),
+                ..config("current", Algorithm::EdDSA, "ed25519")
+            },
+            KeyConfig {
+                active_from: Some(now + Duration::days(7)),
+                ..config("upcoming", Algorithm::RS256, "rsa")
+            },
+        ]);
+        let token = keys.encode(&claims()).unwrap();
+
+        assert_eq!(keys.signing_key().unwrap().kid, "current");
+        assert_eq!(decode_header(&token).unwrap().kid.unwrap(), "current");
+    }
+
+    #[test]
+    fn test_retired_key_still_verifies() {
+        let old = keys(&[config("old", Algorithm::RS256, "rsa")]);
+        let token = old.encode(&claims()).unwrap();
+        let keys = keys(&[
+            KeyConfig {
+                retire_at: Some(Utc::now() - Duration::days(1)),
+                private_key: None,
+                ..config("old", Algorithm::RS256, "rsa")
+            },
+            config("new", Algorithm::EdDSA, "ed25519"),
+        ]);
+
+        assert!(keys.decode::<JwtClaims>(&token).is_ok());
+        assert_eq!(keys.signing_key().unwrap().kid, "new");
+    }
+
+    #[test]
+    fn test_rejects_unknown_kid() {
+        let token = keys(&[config("foo", Algorithm::EdDSA, "ed25519")])
+            .encode(&claims())
+            .unwrap();
+        let keys = keys(&[config("bar", Algorithm::EdDSA, "ed25519")]);
+
+        assert!(keys.decode::<JwtClaims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_algorithm() {
+        let res = Keys::from_configs(&[config("foo", Algorithm::ES256, "rsa")], Path::new(TEST_KEYS));
+
+        assert!(matches!(res, Err(KeyError::UnsupportedAlgorithm(..))));
+    }
+
+    #[test]
+    fn test_jwks() {
+        let keys = keys(&[
+            config("rsa", Algorithm::RS256, "rsa"),
+            config("ed", Algorithm::EdDSA, "ed25519"),
+        ]);
+        let jwks = keys.jwks();
+
+        assert_eq!(jwks.keys.len(), 2);
+        assert!(Keys::from_secret(b"secret").jwks().keys.is_empty());
+
+        for kid in ["rsa", "ed"] {
+            let token = keys
+                .keys
+                .iter()
+                .find(|key| key.kid == kid)
+                .map(|key| {
+                    let mut header = Header::new(key.algorithm);
+                    header.kid = Some(kid.to_string());
+                    encode(&header, &claims(), key.encoding.as_ref().unwrap()).unwrap()
+                })
+                .unwrap();
+            let jwk = jwks.find(kid).unwrap();
+            let decoding = DecodingKey::from_jwk(jwk).unwrap();
+            let algorithm = decode_header(&token).unwrap().alg;
+
+            assert!(decode::<JwtClaims>(&token, &decoding, &Validation::new(algorithm)).is_ok());
         }
     }
 }
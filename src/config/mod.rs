@@ -1,24 +1,38 @@
 pub mod authentication;
 pub mod openapi;
+pub mod settings;
+pub mod shutdown;
 
+use crate::config::authentication::Keys;
 use crate::config::openapi::OpenApiSpec;
-use crate::middleware::auth_layer;
+use crate::config::settings::{LogFormat, LogSettings, ServerSettings, Settings};
+use crate::middleware::{
+    auth_layer, client_ip_layer, error_layer, metrics_layer, protected_rate_limit,
+    public_rate_limit, request_span, route_not_found, RATELIMIT_LIMIT, RATELIMIT_REMAINING,
+    RATELIMIT_RESET, X_REQUEST_ID,
+};
 use crate::state::AppState;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, RETRY_AFTER};
 use axum::http::Method;
 use axum::{middleware, Router};
 use log::debug;
 use sqlx::PgPool;
-use tower_http::cors::{AllowMethods, AllowOrigin, CorsLayer};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_swagger_ui::SwaggerUi;
 
-pub fn get_tracing() {
-    let format = tracing_subscriber::fmt::layer();
+/// Installs the global subscriber, which also receives the records of the `log` macros.
+pub fn get_tracing(settings: &LogSettings) {
+    let format = format_layer(settings.format, std::io::stdout);
     let filter = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("rust_playground=error,tower_http=warn"))
         .unwrap();
@@ -31,21 +45,59 @@ pub fn get_tracing() {
     debug!("Initializing logger with settings: {}", filter);
 }
 
-fn get_cors() -> CorsLayer {
+fn format_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer);
+
+    match format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
+fn get_cors(settings: &ServerSettings) -> CorsLayer {
+    let origins = settings
+        .cors_origins
+        .iter()
+        .filter_map(|origin| origin.parse().ok())
+        .collect::<Vec<_>>();
+
     CorsLayer::new()
-        .allow_origin(AllowOrigin::exact("http://localhost:3000".parse().unwrap()))
+        .allow_origin(AllowOrigin::list(origins))
         .allow_methods(AllowMethods::list(
-            vec![Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            vec![Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
         )
+        .allow_headers(AllowHeaders::list(
+            vec![AUTHORIZATION, CONTENT_TYPE, IF_MATCH, IF_NONE_MATCH, X_REQUEST_ID])
+        )
+        .expose_headers(ExposeHeaders::list(vec![
+            ETAG,
+            X_REQUEST_ID,
+            RATELIMIT_LIMIT,
+            RATELIMIT_REMAINING,
+            RATELIMIT_RESET,
+            RETRY_AFTER,
+        ]))
 }
 
 fn get_swagger(
     protected_api: utoipa::openapi::OpenApi,
     public_api: utoipa::openapi::OpenApi,
+    open_api: utoipa::openapi::OpenApi,
 ) -> SwaggerUi {
     let api = OpenApiSpec::openapi()
         .merge_from(protected_api)
-        .merge_from(public_api);
+        .merge_from(public_api)
+        .merge_from(open_api);
     let swagger = SwaggerUi::new("/swagger-ui")
         .url("/api.json", api)
         .config(utoipa_swagger_ui::Config::default().persist_authorization(true));
@@ -54,28 +106,194 @@ fn get_swagger(
 }
 
 pub async fn app(
+    settings: Settings,
     pool: PgPool,
+    keys: Keys,
     protected_routers: Vec<OpenApiRouter<AppState>>,
     public_routers: Vec<OpenApiRouter<AppState>>,
+    open_routers: Vec<OpenApiRouter<AppState>>,
 ) -> Router {
+    let state = AppState::new(settings, pool, keys).await;
+
+    router(state, protected_routers, public_routers, open_routers)
+}
+
+/// Builds the app from routers requiring a token, public routers limited per client IP, and open
+/// routers, such as health and metrics, that are neither authenticated nor rate limited.
+pub fn router(
+    state: AppState,
+    protected_routers: Vec<OpenApiRouter<AppState>>,
+    public_routers: Vec<OpenApiRouter<AppState>>,
+    open_routers: Vec<OpenApiRouter<AppState>>,
+) -> Router {
+    let cors = get_cors(&state.settings.server);
+    let metrics = state.metrics.clone();
     let (protected_router, protected_api) = protected_routers
         .into_iter()
         .fold(OpenApiRouter::new(), OpenApiRouter::merge)
-        .layer(middleware::from_fn(auth_layer))
+        .layer(middleware::from_fn_with_state(state.clone(), protected_rate_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), auth_layer))
         .split_for_parts();
     let (public_router, public_api) = public_routers
         .into_iter()
         .fold(OpenApiRouter::new(), OpenApiRouter::merge)
+        .layer(middleware::from_fn_with_state(state.clone(), public_rate_limit))
         .split_for_parts();
-    let swagger = get_swagger(protected_api, public_api);
-    let state = AppState::new(pool).await;
-    let cors = get_cors();
+    let (open_router, open_api) = open_routers
+        .into_iter()
+        .fold(OpenApiRouter::new(), OpenApiRouter::merge)
+        .split_for_parts();
+    let swagger = get_swagger(protected_api, public_api, open_api);
+    let client_ip = middleware::from_fn_with_state(state.clone(), client_ip_layer);
 
     Router::new()
         .merge(protected_router)
         .merge(public_router)
+        .merge(open_router)
         .merge(swagger)
+        .fallback(route_not_found)
         .with_state(state)
+        .layer(client_ip)
+        .layer(middleware::from_fn(error_layer))
         .layer(cors)
-        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn_with_state(metrics, metrics_layer))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(PropagateRequestIdLayer::new(X_REQUEST_ID))
+        .layer(SetRequestIdLayer::new(X_REQUEST_ID, MakeRequestUuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header::{
+        ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_EXPOSE_HEADERS,
+        ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    };
+    use axum::http::Request;
+    use axum::routing::{delete, get, patch};
+    use serde_json::Value;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::Empty;
+    use tower::util::ServiceExt;
+    use tracing_log::LogTracer;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let _ = LogTracer::init();
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(format_layer(LogFormat::Json, move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "request",
+                route = "/user/{id}",
+                request_id = "abc-123",
+                user_id = Empty,
+            );
+            let _guard = span.enter();
+
+            span.record("user_id", "42");
+            log::info!("Retrieving user with id: 7");
+        });
+
+        let output = buffer.0.lock().unwrap();
+        let event: Value = serde_json::from_slice(&output).unwrap();
+
+        assert!(event["timestamp"].is_string());
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["target"], module_path!());
+        assert_eq!(event["message"], "Retrieving user with id: 7");
+        assert_eq!(event["span"]["route"], "/user/{id}");
+        assert_eq!(event["span"]["request_id"], "abc-123");
+        assert_eq!(event["span"]["user_id"], "42");
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let settings = ServerSettings {
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            ..ServerSettings::default()
+        };
+        let app = Router::new()
+            .route("/user/{id}", patch(|| async {}))
+            .layer(get_cors(&settings));
+        let req = Request::options("/user/1")
+            .header(ORIGIN, "http://localhost:3000")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "PATCH")
+            .header(
+                ACCESS_CONTROL_REQUEST_HEADERS,
+                "authorization,content-type,if-match,if-none-match,x-request-id",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let header = |name| res.headers().get(name).unwrap().to_str().unwrap();
+
+        assert!(header(ACCESS_CONTROL_ALLOW_METHODS).contains("PATCH"));
+        assert_eq!(
+            header(ACCESS_CONTROL_ALLOW_HEADERS),
+            "authorization,content-type,if-match,if-none-match,x-request-id"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_conditional_delete() {
+        let settings = ServerSettings {
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            ..ServerSettings::default()
+        };
+        let app = Router::new()
+            .route("/user/{id}", delete(|| async {}))
+            .layer(get_cors(&settings));
+        let req = Request::options("/user/1")
+            .header(ORIGIN, "http://localhost:3000")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, "authorization,if-match")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let header = |name| res.headers().get(name).unwrap().to_str().unwrap();
+
+        assert!(header(ACCESS_CONTROL_ALLOW_METHODS).contains("DELETE"));
+        assert!(header(ACCESS_CONTROL_ALLOW_HEADERS).contains("authorization"));
+        assert!(header(ACCESS_CONTROL_ALLOW_HEADERS).contains("if-match"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_exposes_headers() {
+        let settings = ServerSettings {
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            ..ServerSettings::default()
+        };
+        let app = Router::new()
+            .route("/user/{id}", get(|| async {}))
+            .layer(get_cors(&settings));
+        let req = Request::get("/user/1")
+            .header(ORIGIN, "http://localhost:3000")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(
+            res.headers()[ACCESS_CONTROL_EXPOSE_HEADERS],
+            "etag,x-request-id,ratelimit-limit,ratelimit-remaining,ratelimit-reset,retry-after"
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::model::api_response::{ApiError, ProblemDetails};
 use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
 use utoipa::{Modify, OpenApi};
 
@@ -6,6 +7,7 @@ use utoipa::{Modify, OpenApi};
     info(
         title = "Web Service",
     ),
+    components(schemas(ApiError, ProblemDetails)),
     modifiers(&AuthorizationAddon),
     security(("Jwt" = [])),
 )]
@@ -0,0 +1,533 @@
+use axum::http::HeaderValue;
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::fmt::{Debug, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+const DEFAULT_SETTINGS_FILE: &str = "config.toml";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Unable to read settings file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Unable to parse settings file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Invalid settings:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Application settings, read from a TOML file and then overridden by environment variables and
+/// command line flags, in that order.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub auth: AuthSettings,
+    pub log: LogSettings,
+    pub rate_limit: RateLimitSettings,
+    pub lockout: LockoutSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub address: SocketAddr,
+    pub cors_origins: Vec<String>,
+    pub shutdown_timeout_seconds: u64,
+    /// Proxies whose `X-Forwarded-For` header is used to find the client IP.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub url: Option<String>,
+    pub max_connections: u32,
+    /// Keeps every entity in memory instead of connecting to `url`, for demos and local
+    /// development. Nothing survives a restart.
+    pub in_memory: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    pub access_token_ttl_seconds: u32,
+    pub refresh_token_ttl_days: i64,
+    pub keys_file: Option<PathBuf>,
+    pub secret: Option<String>,
+    pub admin_user_name: Option<String>,
+    pub admin_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// Policy of the public routes open to abuse, such as login, applied per client IP.
+    pub public: RateLimitPolicy,
+    /// Policy of protected routes, applied per authenticated user.
+    pub protected: RateLimitPolicy,
+}
+
+/// Token bucket holding up to `capacity` requests, refilled by `refill_per_second` tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    pub capacity: u32,
+    pub refill_per_second: f64,
+}
+
+/// Throttling of failed logins. Each failure of a user name doubles the delay before the next
+/// attempt is accepted, and the account is locked once `max_failures` is reached. Failures from one
+/// IP across user names lock that IP after `ip_max_failures`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutSettings {
+    pub max_failures: u32,
+    pub ip_max_failures: u32,
+    pub lock_seconds: u64,
+    /// Delay after the first failure, zero disables the back-off.
+    pub backoff_base_seconds: u64,
+    pub max_backoff_seconds: u64,
+    /// Failures older than this are forgotten.
+    pub failure_window_seconds: u64,
+}
+
+/// Output format of log events. `json` writes one object per line, including the fields of the
+/// request span each event was logged in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Json,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            shutdown_timeout_seconds: 30,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_connections: 5,
+            in_memory: false,
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            public: RateLimitPolicy {
+                capacity: 30,
+                refill_per_second: 0.5,
+            },
+            protected: RateLimitPolicy {
+                capacity: 100,
+                refill_per_second: 10.0,
+            },
+        }
+    }
+}
+
+impl Default for LockoutSettings {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            ip_max_failures: 20,
+            lock_seconds: 15 * 60,
+            backoff_base_seconds: 1,
+            max_backoff_seconds: 30,
+            failure_window_seconds: 15 * 60,
+        }
+    }
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            access_token_ttl_seconds: 15 * 60,
+            refresh_token_ttl_days: 14,
+            keys_file: None,
+            secret: None,
+            admin_user_name: None,
+            admin_password: None,
+        }
+    }
+}
+
+impl Debug for AuthSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+
+        f.debug_struct("AuthSettings")
+            .field("access_token_ttl_seconds", &self.access_token_ttl_seconds)
+            .field("refresh_token_ttl_days", &self.refresh_token_ttl_days)
+            .field("keys_file", &self.keys_file)
+            .field("secret", &redacted(&self.secret))
+            .field("admin_user_name", &self.admin_user_name)
+            .field("admin_password", &redacted(&self.admin_password))
+            .finish()
+    }
+}
+
+/// Command line flags, each of which can also be set through the listed environment variable.
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path of the TOML settings file, defaults to `config.toml` when present
+    #[arg(long, env = "APP_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address the server listens on
+    #[arg(long, env = "SERVER_ADDRESS")]
+    pub address: Option<SocketAddr>,
+    /// Origins allowed to make cross-origin requests, comma separated
+    #[arg(long, env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    /// Seconds to wait for in-flight requests on shutdown
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECONDS")]
+    pub shutdown_timeout_seconds: Option<u64>,
+    /// Addresses of the proxies trusted to set X-Forwarded-For, comma separated
+    #[arg(long, env = "TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Option<Vec<IpAddr>>,
+    /// Postgres connection URL
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    pub database_url: Option<String>,
+    /// Maximum number of pooled database connections
+    #[arg(long, env = "DATABASE_MAX_CONNECTIONS")]
+    pub database_max_connections: Option<u32>,
+    /// Whether to keep every entity in memory instead of using the database
+    #[arg(long, env = "DATABASE_IN_MEMORY")]
+    pub database_in_memory: Option<bool>,
+    /// Lifetime of access tokens in seconds
+    #[arg(long, env = "ACCESS_TOKEN_TTL_SECONDS")]
+    pub access_token_ttl_seconds: Option<u32>,
+    /// Lifetime of refresh tokens in days
+    #[arg(long, env = "REFRESH_TOKEN_TTL_DAYS")]
+    pub refresh_token_ttl_days: Option<i64>,
+    /// JSON manifest of the keys used to sign tokens
+    #[arg(long, env = "JWT_KEYS_FILE")]
+    pub jwt_keys_file: Option<PathBuf>,
+    /// HS256 secret used when no key manifest is configured
+    #[arg(long, env = "JWT_SECRET", hide_env_values = true)]
+    pub jwt_secret: Option<String>,
+    /// User name of the admin account created on startup
+    #[arg(long, env = "ADMIN_USER_NAME")]
+    pub admin_user_name: Option<String>,
+    /// Password of the admin account created on startup
+    #[arg(long, env = "ADMIN_PASSWORD", hide_env_values = true)]
+    pub admin_password: Option<String>,
+    /// Format of log output
+    #[arg(long, env = "LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Whether requests are rate limited
+    #[arg(long, env = "RATE_LIMIT_ENABLED")]
+    pub rate_limit_enabled: Option<bool>,
+    /// Failed logins after which an account is locked
+    #[arg(long, env = "LOCKOUT_MAX_FAILURES")]
+    pub lockout_max_failures: Option<u32>,
+    /// Seconds an account stays locked
+    #[arg(long, env = "LOCKOUT_SECONDS")]
+    pub lockout_seconds: Option<u64>,
+}
+
+impl Cli {
+    fn apply(self, settings: &mut Settings) {
+        fn set<T>(target: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *target = value;
+            }
+        }
+
+        set(&mut settings.server.address, self.address);
+        set(&mut settings.server.cors_origins, self.cors_origins);
+        set(&mut settings.server.shutdown_timeout_seconds, self.shutdown_timeout_seconds);
+        set(&mut settings.server.trusted_proxies, self.trusted_proxies);
+        set(&mut settings.database.max_connections, self.database_max_connections);
+        set(&mut settings.database.in_memory, self.database_in_memory);
+        set(&mut settings.auth.access_token_ttl_seconds, self.access_token_ttl_seconds);
+        set(&mut settings.auth.refresh_token_ttl_days, self.refresh_token_ttl_days);
+        set(&mut settings.log.format, self.log_format);
+        set(&mut settings.rate_limit.enabled, self.rate_limit_enabled);
+        set(&mut settings.lockout.max_failures, self.lockout_max_failures);
+        set(&mut settings.lockout.lock_seconds, self.lockout_seconds);
+
+        settings.database.url = self.database_url.or(settings.database.url.take());
+        settings.auth.keys_file = self.jwt_keys_file.or(settings.auth.keys_file.take());
+        settings.auth.secret = self.jwt_secret.or(settings.auth.secret.take());
+        settings.auth.admin_user_name = self.admin_user_name.or(settings.auth.admin_user_name.take());
+        settings.auth.admin_password = self.admin_password.or(settings.auth.admin_password.take());
+    }
+}
+
+impl Settings {
+    /// Loads the settings for this process from its settings file, environment and arguments.
+    pub fn load() -> Result<Self, SettingsError> {
+        Self::load_from(Cli::parse())
+    }
+
+    pub fn load_from(cli: Cli) -> Result<Self, SettingsError> {
+        let mut settings = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_SETTINGS_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_SETTINGS_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        cli.apply(&mut settings);
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, SettingsError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| SettingsError::Io(path.to_path_buf(), e))?;
+
+        toml::from_str(&contents).map_err(|e| SettingsError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut errors = Vec::new();
+
+        match self.database.url.as_deref() {
+            _ if self.database.in_memory => {}
+            None | Some("") => errors.push("database.url (DATABASE_URL) must be set".to_string()),
+            Some(url) if !url.starts_with("postgres://") && !url.starts_with("postgresql://") => {
+                errors.push("database.url (DATABASE_URL) must be a postgres:// URL".to_string());
+            }
+            _ => {}
+        }
+
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections must be at least 1".to_string());
+        }
+
+        for origin in &self.server.cors_origins {
+            let is_http = origin.starts_with("http://") || origin.starts_with("https://");
+
+            if !is_http || HeaderValue::from_str(origin).is_err() {
+                errors.push(format!("server.cors_origins contains an invalid origin: {origin:?}"));
+            }
+        }
+
+        if self.auth.access_token_ttl_seconds == 0 {
+            errors.push("auth.access_token_ttl_seconds must be greater than 0".to_string());
+        }
+
+        if self.auth.refresh_token_ttl_days <= 0 {
+            errors.push("auth.refresh_token_ttl_days must be greater than 0".to_string());
+        }
+
+        match (&self.auth.keys_file, &self.auth.secret) {
+            (Some(path), _) if !path.is_file() => {
+                errors.push(format!("auth.keys_file (JWT_KEYS_FILE) {} does not exist", path.display()));
+            }
+            (None, None) => {
+                errors.push("either auth.keys_file (JWT_KEYS_FILE) or auth.secret (JWT_SECRET) must be set".to_string());
+            }
+            _ => {}
+        }
+
+        if self.auth.admin_user_name.is_some() != self.auth.admin_password.is_some() {
+            errors.push("auth.admin_user_name and auth.admin_password must be set together".to_string());
+        }
+
+        for (group, policy) in [
+            ("public", &self.rate_limit.public),
+            ("protected", &self.rate_limit.protected),
+        ] {
+            if policy.capacity == 0 {
+                errors.push(format!("rate_limit.{group}.capacity must be at least 1"));
+            }
+
+            if !policy.refill_per_second.is_finite() || policy.refill_per_second <= 0.0 {
+                errors.push(format!("rate_limit.{group}.refill_per_second must be greater than 0"));
+            }
+        }
+
+        if self.lockout.max_failures == 0 || self.lockout.ip_max_failures == 0 {
+            errors.push("lockout.max_failures and lockout.ip_max_failures must be at least 1".to_string());
+        }
+
+        if self.lockout.lock_seconds == 0 || self.lockout.failure_window_seconds == 0 {
+            errors.push("lockout.lock_seconds and lockout.failure_window_seconds must be greater than 0".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+impl Settings {
+    /// Valid settings for tests, signing tokens with a fixed secret.
+    pub(crate) fn test() -> Self {
+        let mut settings = Self::default();
+        settings.database.url = Some("postgres://localhost/test".to_string());
+        settings.auth.secret = Some("secret".to_string());
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TOML: &str = r#"
+        [server]
+        address = "127.0.0.1:8080"
+        shutdown_timeout_seconds = 5
+        trusted_proxies = ["10.0.0.1"]
+
+        [database]
+        url = "postgres://localhost/app"
+        max_connections = 10
+
+        [auth]
+        secret = "secret"
+        access_token_ttl_seconds = 60
+
+        [log]
+        format = "compact"
+
+        [rate_limit.public]
+        capacity = 5
+        refill_per_second = 0.1
+    "#;
+
+    fn settings_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_defaults() {
+        let settings = Settings::default();
+
+        assert_eq!(settings.server.address.to_string(), "0.0.0.0:3000");
+        assert_eq!(settings.server.cors_origins, vec!["http://localhost:3000"]);
+        assert_eq!(settings.database.max_connections, 5);
+        assert_eq!(settings.auth.access_token_ttl_seconds, 900);
+        assert_eq!(settings.auth.refresh_token_ttl_days, 14);
+        assert_eq!(settings.log.format, LogFormat::Pretty);
+    }
+
+    #[test]
+    fn test_file_then_flags() {
+        let file = settings_file(TOML);
+        let path = file.path().to_str().unwrap();
+        let cli = Cli::try_parse_from([
+            "web-service",
+            "--config",
+            path,
+            "--address",
+            "127.0.0.1:9090",
+            "--access-token-ttl-seconds",
+            "120",
+            "--log-format",
+            "json",
+            "--rate-limit-enabled",
+            "false",
+        ])
+        .unwrap();
+        let settings = Settings::load_from(cli).unwrap();
+
+        assert_eq!(settings.server.address.to_string(), "127.0.0.1:9090");
+        assert_eq!(settings.server.shutdown_timeout(), Duration::from_secs(5));
+        assert_eq!(settings.server.trusted_proxies, vec![IpAddr::from([10, 0, 0, 1])]);
+        assert_eq!(settings.database.max_connections, 10);
+        assert_eq!(settings.auth.access_token_ttl_seconds, 120);
+        assert_eq!(settings.auth.refresh_token_ttl_days, 14);
+        assert_eq!(settings.log.format, LogFormat::Json);
+        assert!(!settings.rate_limit.enabled);
+        assert_eq!(settings.rate_limit.public.capacity, 5);
+        assert_eq!(settings.rate_limit.protected.capacity, 100);
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let file = settings_file("[server]\nport = 3000\n");
+        let res = Settings::from_file(file.path());
+
+        assert!(matches!(res, Err(SettingsError::Parse(_, _))));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let res = Settings::from_file(Path::new("missing.toml"));
+
+        assert!(matches!(res, Err(SettingsError::Io(_, _))));
+    }
+
+    #[test]
+    fn test_validation_lists_every_error() {
+        let mut settings = Settings::default();
+        settings.database.max_connections = 0;
+        settings.server.cors_origins = vec!["localhost".to_string()];
+        settings.auth.admin_user_name = Some("admin".to_string());
+        settings.rate_limit.protected.refill_per_second = 0.0;
+
+        let Err(SettingsError::Invalid(errors)) = settings.validate() else {
+            panic!("Settings should be invalid");
+        };
+
+        assert_eq!(errors.len(), 6);
+        assert!(errors[0].contains("DATABASE_URL"));
+        assert!(errors[1].contains("max_connections"));
+        assert!(errors[2].contains("\"localhost\""));
+        assert!(errors[3].contains("JWT_SECRET"));
+        assert!(errors[4].contains("admin_password"));
+        assert!(errors[5].contains("rate_limit.protected.refill_per_second"));
+    }
+
+    #[test]
+    fn test_in_memory_without_url() {
+        let mut settings = Settings::test();
+        settings.database.url = None;
+
+        assert!(settings.validate().is_err());
+
+        settings.database.in_memory = true;
+
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_test_settings_are_valid() {
+        assert!(Settings::test().validate().is_ok());
+    }
+}
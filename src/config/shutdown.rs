@@ -0,0 +1,240 @@
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use log::{info, warn};
+use std::fmt::{Display, Formatter};
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+/// Counts requests passing through the app, so shutdown can report what it had to wait for.
+#[derive(Clone, Default)]
+pub struct RequestTracker {
+    in_flight: Arc<AtomicU64>,
+    served: Arc<AtomicU64>,
+}
+
+struct InFlightGuard(RequestTracker);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl RequestTracker {
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn served(&self) -> u64 {
+        self.served.load(Ordering::SeqCst)
+    }
+
+    async fn track(State(tracker): State<RequestTracker>, request: Request, next: Next) -> Response {
+        tracker.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlightGuard(tracker.clone());
+        let response = next.run(request).await;
+
+        tracker.served.fetch_add(1, Ordering::SeqCst);
+        response
+    }
+}
+
+#[derive(Debug)]
+pub struct ShutdownSummary {
+    pub signal: &'static str,
+    pub requests_served: u64,
+    pub drained: bool,
+    pub abandoned_requests: u64,
+    pub drain_duration: Duration,
+}
+
+impl Display for ShutdownSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "shutdown on {} after serving {} requests, drained in {:?}",
+            self.signal, self.requests_served, self.drain_duration
+        )?;
+
+        if !self.drained {
+            write!(f, ", abandoned {} in-flight requests", self.abandoned_requests)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves with the name of the first SIGINT or SIGTERM received by the process.
+pub async fn shutdown_signal() -> &'static str {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install SIGINT handler");
+        "SIGINT"
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+        "SIGTERM"
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<&'static str>();
+
+    tokio::select! {
+        signal = ctrl_c => signal,
+        signal = terminate => signal,
+    }
+}
+
+/// Serves `app` until `signal` resolves, then stops accepting connections and waits up to
+/// `drain_timeout` for in-flight requests to finish before giving up on them.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    signal: impl Future<Output = &'static str> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<ShutdownSummary> {
+    let tracker = RequestTracker::default();
+    let app = app.layer(middleware::from_fn_with_state(tracker.clone(), RequestTracker::track));
+    let (signal_tx, mut signal_rx) = watch::channel(None);
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            let signal = signal.await;
+            info!("Received {signal}, no longer accepting connections");
+            let _ = signal_tx.send(Some(signal));
+        })
+        .into_future();
+    tokio::pin!(server);
+
+    let signal = tokio::select! {
+        res = &mut server => {
+            res?;
+            return Ok(summary("server exit", &tracker, Instant::now(), true));
+        }
+        signal = signal_rx.wait_for(Option::is_some) => signal.ok().and_then(|s| *s).unwrap_or("unknown"),
+    };
+
+    let started = Instant::now();
+    info!(
+        "Draining {} in-flight requests, waiting at most {drain_timeout:?}",
+        tracker.in_flight()
+    );
+
+    let drained = match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(res) => {
+            res?;
+            true
+        }
+        Err(_) => {
+            warn!("Drain timeout elapsed with {} requests in flight", tracker.in_flight());
+            false
+        }
+    };
+
+    Ok(summary(signal, &tracker, started, drained))
+}
+
+fn summary(
+    signal: &'static str,
+    tracker: &RequestTracker,
+    started: Instant,
+    drained: bool,
+) -> ShutdownSummary {
+    ShutdownSummary {
+        signal,
+        requests_served: tracker.served(),
+        drained,
+        abandoned_requests: if drained { 0 } else { tracker.in_flight() },
+        drain_duration: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+
+    async fn slow(duration: Duration) -> &'static str {
+        tokio::time::sleep(duration).await;
+        "done"
+    }
+
+    async fn start(
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (
+        String,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<ShutdownSummary>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let app = Router::new().route("/slow", get(move || slow(delay)));
+        let (tx, rx) = oneshot::channel();
+        let signal = async move {
+            let _ = rx.await;
+            "TEST"
+        };
+        let handle = tokio::spawn(serve(listener, app, signal, drain_timeout));
+
+        (addr, tx, handle)
+    }
+
+    async fn request(addr: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+
+        response
+    }
+
+    #[tokio::test]
+    async fn test_drains_in_flight_requests() {
+        let (addr, tx, handle) = start(Duration::from_millis(300), Duration::from_secs(5)).await;
+        let client = tokio::spawn(async move { request(&addr).await });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(()).unwrap();
+
+        let summary = handle.await.unwrap().unwrap();
+
+        assert!(client.await.unwrap().ends_with("done"));
+        assert_eq!(summary.signal, "TEST");
+        assert!(summary.drained);
+        assert_eq!(summary.requests_served, 1);
+    }
+
+    #[tokio::test]
+    async fn test_abandons_requests_after_timeout() {
+        let (addr, tx, handle) = start(Duration::from_secs(10), Duration::from_millis(100)).await;
+        let _client = tokio::spawn(async move { request(&addr).await });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(()).unwrap();
+
+        let summary = handle.await.unwrap().unwrap();
+
+        assert!(!summary.drained);
+        assert_eq!(summary.abandoned_requests, 1);
+        assert_eq!(summary.requests_served, 0);
+    }
+}
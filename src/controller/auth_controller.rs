@@ -1,10 +1,14 @@
 use crate::model::api_response::{ApiError, ApiResponse, AsApiResponse};
-use crate::model::auth::{JwtClaims, LoginDto};
+use crate::config::authentication::Keys;
+use crate::middleware::{AuthUser, ClientIp};
+use crate::model::auth::{LoginDto, RefreshDto};
 use crate::model::auth_error::AuthError;
-use crate::services::{AuthBody, AuthService};
+use crate::model::user::UserStatus;
+use crate::services::{AuthBody, AuthService, Metrics};
 use crate::state::AppState;
 use axum::extract::State;
-use axum::{Extension, Json};
+use axum::Json;
+use jsonwebtoken::jwk::JwkSet;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use utoipa_axum::router::OpenApiRouter;
@@ -15,11 +19,18 @@ const AUTH_TAG: &str = "Authorization";
 pub fn get_routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(login))
+        .routes(routes!(refresh))
+}
+
+pub fn get_open_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(jwks))
 }
 
 pub fn get_protected_routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(get_info))
+        .routes(routes!(logout))
+        .routes(routes!(logout_all))
 }
 
 #[utoipa::path(
@@ -34,26 +45,109 @@ pub fn get_protected_routes() -> OpenApiRouter<AppState> {
 )]
 async fn login(
     State(state): State<AuthService>,
+    State(metrics): State<Metrics>,
+    ClientIp(ip): ClientIp,
     Json(payload): Json<LoginDto>,
 ) -> ApiResponse<AuthBody> {
     if payload.user_name.is_empty() || payload.password.is_empty() {
+        metrics.record_login(false);
         return Err(AuthError::MissingCredentials).as_api_response_ok();
     }
 
-    if payload.user_name != "foo" || payload.password != "bar" {
-        return Err(AuthError::WrongCredentials).as_api_response_ok();
+    let user = match state.authenticate(&payload, ip).await {
+        Ok(user) => user,
+        Err(e) => {
+            metrics.record_login(false);
+            return Err(e).as_api_response_ok();
+        }
+    };
+    let tokens = state.generate_tokens(user.id.unwrap_or_default()).await;
+
+    metrics.record_login(tokens.is_ok());
+    tokens.as_api_response_ok()
+}
+
+#[utoipa::path(
+    post,
+    path = "/token/refresh",
+    responses(
+        (status = OK, description = "Exchange a refresh token for a new token pair", body = AuthBody),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    tag = AUTH_TAG,
+    security(),
+)]
+async fn refresh(
+    State(state): State<AuthService>,
+    Json(payload): Json<RefreshDto>,
+) -> ApiResponse<AuthBody> {
+    if payload.refresh_token.is_empty() {
+        return Err(AuthError::MissingCredentials).as_api_response_ok();
     }
 
     state
-        .generate_tokens(payload.user_name.as_str())
+        .refresh_tokens(&payload.refresh_token)
+        .await
+        .as_api_response_ok()
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = OK, description = "Public keys used to verify access tokens", body = Object),
+    ),
+    tag = AUTH_TAG,
+    security(),
+)]
+async fn jwks(State(keys): State<Keys>) -> Json<JwkSet> {
+    Json(keys.jwks())
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    responses(
+        (status = OK, description = "Revoke the current session"),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    tag = AUTH_TAG,
+)]
+async fn logout(
+    State(state): State<AuthService>,
+    auth_user: AuthUser,
+) -> ApiResponse<()> {
+    state
+        .logout(&auth_user.claims)
+        .await
+        .as_api_response_ok()
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout-all",
+    responses(
+        (status = OK, description = "Revoke every session for the current user"),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    tag = AUTH_TAG,
+)]
+async fn logout_all(
+    State(state): State<AuthService>,
+    auth_user: AuthUser,
+) -> ApiResponse<()> {
+    state
+        .logout_all(&auth_user.claims)
+        .await
         .as_api_response_ok()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GetUserInfo {
     pub username: String,
-    pub email: String,
-    pub info: String,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub status: UserStatus,
 }
 
 #[utoipa::path(
@@ -65,16 +159,469 @@ pub struct GetUserInfo {
     ),
     tag = AUTH_TAG,
 )]
-async fn get_info(
-    Extension(claims): Extension<JwtClaims>,
-) -> ApiResponse<GetUserInfo> {
-    let res = Ok::<_, AuthError>(
-        GetUserInfo {
-            username: claims.sub,
-            email: "foo@foo.com".to_string(),
-            info: "Hello there!".to_string(),
-        }
-    );
+async fn get_info(auth_user: AuthUser) -> ApiResponse<GetUserInfo> {
+    let res = auth_user.user().await.map(|user| GetUserInfo {
+        username: user.user_name.clone().unwrap_or_default(),
+        email: user.email.clone(),
+        display_name: user.display_name.clone(),
+        status: user.status.unwrap_or_default(),
+    });
 
     res.as_api_response_ok()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::config::authentication::Keys;
+    use crate::config::settings::{LockoutSettings, RateLimitPolicy, Settings};
+    use crate::model::patch::PatchDocument;
+    use crate::model::user::{User, UserDto};
+    use crate::repository::repository_traits::WriteRepository;
+    use crate::repository::{UserNameRepository, UserRepository};
+    use crate::util::password::hash_password;
+    use axum::body::Body;
+    use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use tower::util::ServiceExt;
+
+    async fn post(app: &Router, uri: &str, body: String) -> axum::response::Response {
+        let req = Request::post(uri)
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    async fn create_user(pool: &PgPool, user_name: &str, password: &str) {
+        let user = User {
+            password_hash: Some(hash_password(password).unwrap()),
+            ..User::new(user_name)
+        };
+        UserRepository::new(pool).create(&user).await.unwrap();
+    }
+
+    async fn login(app: &Router, user_name: &str, password: &str) -> axum::response::Response {
+        let login = LoginDto {
+            user_name: user_name.to_string(),
+            password: password.to_string(),
+        };
+        post(app, "/login", serde_json::to_string(&login).unwrap()).await
+    }
+
+    async fn refresh(app: &Router, refresh_token: &str) -> axum::response::Response {
+        let refresh = RefreshDto {
+            refresh_token: refresh_token.to_string(),
+        };
+        post(app, "/token/refresh", serde_json::to_string(&refresh).unwrap()).await
+    }
+
+    async fn send_with_token(app: &Router, req: Request<Body>, token: &str) -> axum::response::Response {
+        let mut req = req;
+        req.headers_mut()
+            .insert(AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    async fn get_info(app: &Router, token: &str) -> axum::response::Response {
+        let req = Request::get("/get-user-info").body(Body::empty()).unwrap();
+        send_with_token(app, req, token).await
+    }
+
+    async fn logout(app: &Router, uri: &str, token: &str) -> axum::response::Response {
+        let req = Request::post(uri).body(Body::empty()).unwrap();
+        send_with_token(app, req, token).await
+    }
+
+    #[inline]
+    async fn unwrap_res(res: axum::response::Response) -> Value {
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn app(pool: PgPool) -> Router {
+        app_with_settings(pool, Settings::test()).await
+    }
+
+    async fn app_with_settings(pool: PgPool, settings: Settings) -> Router {
+        let protected_routes = vec![get_protected_routes()];
+        let routes = vec![get_routes()];
+        let open_routes = vec![get_open_routes()];
+        let keys = Keys::from_secret(b"secret");
+
+        config::app(settings, pool, keys, protected_routes, routes, open_routes).await
+    }
+
+    fn rate_limited(policy: RateLimitPolicy) -> Settings {
+        let mut settings = Settings::test();
+        settings.rate_limit.public = policy;
+        settings.rate_limit.protected = policy;
+        settings
+    }
+
+    #[sqlx::test]
+    async fn test_login(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let res = login(&app, "foo", "bar").await;
+
+        assert!(res.status().is_success());
+
+        let body = unwrap_res(res).await;
+
+        assert_eq!(body["token_type"], "Bearer");
+        assert!(body["access_token"].is_string());
+        assert!(body["refresh_token"].is_string());
+        assert!(body["expires_in"].as_u64().unwrap() > 0);
+    }
+
+    #[sqlx::test]
+    async fn test_login_wrong_password(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let res = login(&app, "foo", "baz").await;
+
+        assert!(res.status().is_client_error());
+        assert_eq!(unwrap_res(res).await["code"], "WrongCredentials");
+    }
+
+    #[sqlx::test]
+    async fn test_login_missing_user(pool: PgPool) {
+        let app = app(pool).await;
+        let res = login(&app, "foo", "bar").await;
+
+        assert!(res.status().is_client_error());
+        assert_eq!(unwrap_res(res).await["code"], "WrongCredentials");
+    }
+
+    #[sqlx::test]
+    async fn test_login_backs_off_after_failure(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        login(&app, "foo", "baz").await;
+        let res = login(&app, "foo", "bar").await;
+
+        assert_eq!(res.status(), StatusCode::LOCKED);
+        assert_eq!(unwrap_res(res).await["code"], "AccountLocked");
+    }
+
+    #[sqlx::test]
+    async fn test_login_locked_after_failures(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let mut settings = Settings::test();
+        settings.lockout = LockoutSettings {
+            max_failures: 2,
+            backoff_base_seconds: 0,
+            ..LockoutSettings::default()
+        };
+        let app = app_with_settings(pool, settings).await;
+
+        let res = login(&app, "foo", "baz").await;
+
+        assert_eq!(unwrap_res(res).await["code"], "WrongCredentials");
+
+        let res = login(&app, "foo", "baz").await;
+
+        assert_eq!(res.status(), StatusCode::LOCKED);
+        assert!(unwrap_res(res).await["message"].as_str().unwrap().contains("locked until"));
+        assert_eq!(login(&app, "foo", "bar").await.status(), StatusCode::LOCKED);
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_rotates_tokens(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let first = body["refresh_token"].as_str().unwrap();
+        let res = refresh(&app, first).await;
+
+        assert!(res.status().is_success());
+
+        let body = unwrap_res(res).await;
+        let second = body["refresh_token"].as_str().unwrap();
+
+        assert_ne!(first, second);
+        assert!(refresh(&app, second).await.status().is_success());
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_reuse_revokes_family(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let first = body["refresh_token"].as_str().unwrap();
+        let body = unwrap_res(refresh(&app, first).await).await;
+        let second = body["refresh_token"].as_str().unwrap();
+        let res = refresh(&app, first).await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(unwrap_res(res).await["code"], "InvalidToken");
+        assert_eq!(refresh(&app, second).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_database_down(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool.clone()).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = body["refresh_token"].as_str().unwrap();
+
+        pool.close().await;
+
+        let res = refresh(&app, token).await;
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(unwrap_res(res).await["code"], "ServiceUnavailable");
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_inactive_user(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        create_user(&pool, "baz", "bar").await;
+        let repo = UserRepository::new(&pool);
+        let app = app(pool).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let suspended = body["refresh_token"].as_str().unwrap();
+        let body = unwrap_res(login(&app, "baz", "bar").await).await;
+        let deleted = body["refresh_token"].as_str().unwrap();
+        let foo = repo.find_by_user_name("foo").await.unwrap();
+        let baz = repo.find_by_user_name("baz").await.unwrap();
+
+        repo.update(&User {
+            status: Some(UserStatus::Suspended),
+            ..foo
+        })
+        .await
+        .unwrap();
+        repo.delete_by_id(&baz.id.unwrap()).await.unwrap();
+
+        assert_eq!(refresh(&app, suspended).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&app, deleted).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_unknown_token(pool: PgPool) {
+        let app = app(pool).await;
+        let res = refresh(&app, "foo").await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_get_info(pool: PgPool) {
+        let user = User {
+            password_hash: Some(hash_password("bar").unwrap()),
+            email: Some("foo@example.com".to_string()),
+            display_name: Some("Foo".to_string()),
+            ..User::new("foo")
+        };
+        UserRepository::new(&pool).create(&user).await.unwrap();
+        let app = app(pool).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let res = get_info(&app, body["access_token"].as_str().unwrap()).await;
+
+        assert!(res.status().is_success());
+
+        let body = unwrap_res(res).await;
+
+        assert_eq!(body["username"], "foo");
+        assert_eq!(body["email"], "foo@example.com");
+        assert_eq!(body["display_name"], "Foo");
+        assert_eq!(body["status"], "active");
+    }
+
+    #[sqlx::test]
+    async fn test_login_inactive_user(pool: PgPool) {
+        let user = User {
+            password_hash: Some(hash_password("bar").unwrap()),
+            status: Some(UserStatus::Suspended),
+            ..User::new("foo")
+        };
+        UserRepository::new(&pool).create(&user).await.unwrap();
+        let app = app(pool).await;
+
+        let res = login(&app, "foo", "bar").await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(unwrap_res(res).await["code"], "AccountDisabled");
+    }
+
+    #[sqlx::test]
+    async fn test_login_rate_limited(pool: PgPool) {
+        let policy = RateLimitPolicy {
+            capacity: 2,
+            refill_per_second: 0.01,
+        };
+        let app = app_with_settings(pool, rate_limited(policy)).await;
+
+        let res = login(&app, "foo", "bar").await;
+
+        assert_eq!(res.headers()["ratelimit-limit"], "2");
+        assert_eq!(res.headers()["ratelimit-remaining"], "1");
+        assert_eq!(unwrap_res(res).await["code"], "WrongCredentials");
+
+        login(&app, "foo", "bar").await;
+        let res = login(&app, "foo", "bar").await;
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "100");
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+        assert_eq!(res.headers()["ratelimit-reset"], "200");
+        assert_eq!(unwrap_res(res).await["code"], "TooManyRequests");
+    }
+
+    #[sqlx::test]
+    async fn test_protected_routes_rate_limited_per_user(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        create_user(&pool, "baz", "bar").await;
+        let policy = RateLimitPolicy {
+            capacity: 3,
+            refill_per_second: 0.01,
+        };
+        let app = app_with_settings(pool, rate_limited(policy)).await;
+
+        let foo = unwrap_res(login(&app, "foo", "bar").await).await;
+        let foo = foo["access_token"].as_str().unwrap();
+        let baz = unwrap_res(login(&app, "baz", "bar").await).await;
+        let baz = baz["access_token"].as_str().unwrap();
+
+        for _ in 0..3 {
+            assert!(get_info(&app, foo).await.status().is_success());
+        }
+
+        assert_eq!(get_info(&app, foo).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(get_info(&app, baz).await.status().is_success());
+    }
+
+    #[sqlx::test]
+    async fn test_logout(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let first = unwrap_res(login(&app, "foo", "bar").await).await;
+        let second = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = first["access_token"].as_str().unwrap();
+
+        assert!(logout(&app, "/logout", token).await.status().is_success());
+        assert_eq!(get_info(&app, token).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            refresh(&app, first["refresh_token"].as_str().unwrap()).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let token = second["access_token"].as_str().unwrap();
+
+        assert!(get_info(&app, token).await.status().is_success());
+    }
+
+    #[sqlx::test]
+    async fn test_logout_all(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let first = unwrap_res(login(&app, "foo", "bar").await).await;
+        let second = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = first["access_token"].as_str().unwrap();
+
+        assert!(logout(&app, "/logout-all", token).await.status().is_success());
+
+        for body in [first, second] {
+            let token = body["access_token"].as_str().unwrap();
+            let refresh_token = body["refresh_token"].as_str().unwrap();
+
+            assert_eq!(get_info(&app, token).await.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(refresh(&app, refresh_token).await.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_login_after_logout_all(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = body["access_token"].as_str().unwrap();
+
+        assert!(logout(&app, "/logout-all", token).await.status().is_success());
+
+        // logging in again right away, most likely within the same second
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = body["access_token"].as_str().unwrap();
+
+        assert!(get_info(&app, token).await.status().is_success());
+    }
+
+    #[sqlx::test]
+    async fn test_logout_database_down(pool: PgPool) {
+        create_user(&pool, "foo", "bar").await;
+        let app = app(pool.clone()).await;
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = body["access_token"].as_str().unwrap();
+
+        pool.close().await;
+
+        assert_eq!(logout(&app, "/logout", token).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(logout(&app, "/logout-all", token).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_session() {
+        let mut settings = Settings::test();
+        settings.database.in_memory = true;
+        settings.auth.admin_user_name = Some("foo".to_string());
+        settings.auth.admin_password = Some("bar".to_string());
+
+        let state = AppState::in_memory(settings, Keys::from_secret(b"secret")).await;
+        let app = config::router(state, vec![get_protected_routes()], vec![get_routes()], vec![]);
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let first = body["refresh_token"].as_str().unwrap();
+        let body = unwrap_res(refresh(&app, first).await).await;
+        let second = body["refresh_token"].as_str().unwrap();
+        let token = body["access_token"].as_str().unwrap();
+
+        // reusing a refresh token revokes its whole family
+        assert_eq!(refresh(&app, first).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&app, second).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(unwrap_res(get_info(&app, token).await).await["username"], "foo");
+        assert!(logout(&app, "/logout", token).await.status().is_success());
+        assert_eq!(get_info(&app, token).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_suspended_user_loses_access() {
+        let state = AppState::in_memory(Settings::test(), Keys::from_secret(b"secret")).await;
+        let manager = state.users_api.user_manager.clone();
+        let app = config::router(state, vec![get_protected_routes()], vec![get_routes()], vec![]);
+        let user = manager
+            .create_user(&UserDto {
+                user_name: Some("foo".to_string()),
+                password: Some("bar".to_string()),
+                ..UserDto::default()
+            })
+            .await
+            .unwrap();
+
+        let body = unwrap_res(login(&app, "foo", "bar").await).await;
+        let token = body["access_token"].as_str().unwrap();
+        assert!(get_info(&app, token).await.status().is_success());
+
+        let patch = PatchDocument::Merge(json!({"status": "suspended"}));
+        manager.patch_user(&user.id.unwrap(), &patch, None).await.unwrap();
+
+        assert_eq!(get_info(&app, token).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::model::health::{HealthReport, HealthStatus};
+use crate::services::HealthService;
+use crate::state::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+const HEALTH_TAG: &str = "Health";
+
+pub fn get_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(live))
+        .routes(routes!(ready))
+        .routes(routes!(health))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProbeStatus {
+    pub status: HealthStatus,
+}
+
+fn status_code(status: HealthStatus) -> StatusCode {
+    match status {
+        HealthStatus::Up => StatusCode::OK,
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = OK, description = "The process is running", body = ProbeStatus),
+    ),
+    tag = HEALTH_TAG,
+    security(),
+)]
+async fn live() -> Json<ProbeStatus> {
+    Json(ProbeStatus { status: HealthStatus::Up })
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = OK, description = "The service can handle requests", body = ProbeStatus),
+        (status = SERVICE_UNAVAILABLE, description = "A dependency is unavailable", body = ProbeStatus),
+    ),
+    tag = HEALTH_TAG,
+    security(),
+)]
+async fn ready(State(health_service): State<HealthService>) -> (StatusCode, Json<ProbeStatus>) {
+    let status = health_service.check().await.status;
+
+    (status_code(status), Json(ProbeStatus { status }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = OK, description = "Every check passed", body = HealthReport),
+        (status = SERVICE_UNAVAILABLE, description = "At least one check failed", body = HealthReport),
+    ),
+    tag = HEALTH_TAG,
+    security(),
+)]
+async fn health(State(health_service): State<HealthService>) -> (StatusCode, Json<HealthReport>) {
+    let report = health_service.check().await;
+
+    (status_code(report.status), Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::config::authentication::Keys;
+    use crate::config::settings::{RateLimitPolicy, Settings};
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use tower::util::ServiceExt;
+
+    async fn app(pool: PgPool) -> Router {
+        app_with_settings(pool, Settings::test()).await
+    }
+
+    async fn app_with_settings(pool: PgPool, settings: Settings) -> Router {
+        let keys = Keys::from_secret(b"secret");
+
+        config::app(settings, pool, keys, vec![], vec![], vec![get_routes()]).await
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn test_live(pool: PgPool) {
+        let app = app(pool).await;
+        let (status, body) = get(&app, "/health/live").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "up");
+    }
+
+    #[sqlx::test]
+    async fn test_ready(pool: PgPool) {
+        let app = app(pool).await;
+        let (status, body) = get(&app, "/health/ready").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "up");
+    }
+
+    #[sqlx::test]
+    async fn test_not_ready(pool: PgPool) {
+        let app = app(pool.clone()).await;
+        pool.close().await;
+
+        let (status, body) = get(&app, "/health/ready").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "down");
+    }
+
+    #[sqlx::test]
+    async fn test_health(pool: PgPool) {
+        let app = app(pool).await;
+        let (status, body) = get(&app, "/health").await;
+        let checks = body["checks"].as_array().unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|c| c["status"] == "up" && c["latency_ms"].is_number()));
+    }
+
+    #[sqlx::test]
+    async fn test_probes_not_rate_limited(pool: PgPool) {
+        let mut settings = Settings::test();
+        settings.rate_limit.public = RateLimitPolicy {
+            capacity: 1,
+            refill_per_second: 0.01,
+        };
+        let app = app_with_settings(pool, settings).await;
+
+        for _ in 0..3 {
+            assert_eq!(get(&app, "/health/live").await.0, StatusCode::OK);
+        }
+    }
+}
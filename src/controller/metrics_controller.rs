@@ -0,0 +1,87 @@
+use crate::services::Metrics;
+use crate::state::AppState;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use sqlx::PgPool;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+const METRICS_TAG: &str = "Metrics";
+
+pub fn get_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(metrics))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = OK, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    ),
+    tag = METRICS_TAG,
+    security(),
+)]
+async fn metrics(
+    State(metrics): State<Metrics>,
+    State(pool): State<Option<PgPool>>,
+) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics.render(pool.as_ref()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::config::authentication::Keys;
+    use crate::config::settings::Settings;
+    use crate::controller::{auth_controller, user_controller};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use tower::util::ServiceExt;
+
+    async fn app(pool: PgPool) -> Router {
+        config::app(
+            Settings::test(),
+            pool,
+            Keys::from_secret(b"secret"),
+            vec![user_controller::get_routes()],
+            vec![auth_controller::get_routes()],
+            vec![get_routes()],
+        )
+        .await
+    }
+
+    async fn send(app: &Router, req: Request<Body>) -> (StatusCode, String) {
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn test_metrics(pool: PgPool) {
+        let app = app(pool).await;
+        let login = Request::post("/login")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"user_name":"foo","password":"bar"}"#))
+            .unwrap();
+
+        send(&app, login).await;
+        send(&app, Request::get("/user/1").body(Body::empty()).unwrap()).await;
+
+        let (status, body) = send(&app, Request::get("/metrics").body(Body::empty()).unwrap()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#"http_requests_total{method="POST",path="/login",status="400"} 1"#));
+        assert!(body.contains(r#"http_requests_total{method="GET",path="/user/{id}",status="401"} 1"#));
+        assert!(body.contains(r#"auth_logins_total{outcome="failure"} 1"#));
+        assert!(body.contains("db_pool_connections_active"));
+    }
+}
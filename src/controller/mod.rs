@@ -1,2 +1,4 @@
 pub mod user_controller;
-pub mod auth_controller;
\ No newline at end of file
+pub mod auth_controller;
+pub mod health_controller;
+pub mod metrics_controller;
\ No newline at end of file
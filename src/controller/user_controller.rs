@@ -1,22 +1,46 @@
+use crate::manager::UserError;
+use crate::middleware::{
+    AuthUser, IfMatch, IfNoneMatch, RequirePermission, ValidatedJson, ValidatedQuery,
+};
 use crate::model::api_response::{ApiError, ApiResponse, AsApiResponse};
+use crate::model::auth::permission::{UserCreate, UserDelete, UserRead, UserUnlock, UserUpdate};
 use crate::model::auth_error::AuthError;
-use crate::model::user::UserDto;
+use crate::model::etag::{etag, EntityTags};
+use crate::model::page::{Page, PageQuery};
+use crate::model::patch::PatchDocument;
+use crate::model::user::{
+    AvailabilityQuery, BulkUserRequest, BulkUserResponse, DeleteQuery, UserDto,
+    UserNameAvailability, UserQuery,
+};
+use crate::services::{AccessControl, LoginThrottle};
 use crate::state::{AppState, UsersApi};
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::header::ETAG;
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use log::warn;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
 const USER_TAG: &str = "User";
 
+#[allow(deprecated)]
 pub fn get_routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(create_user))
         .routes(routes!(get_user))
         .routes(routes!(update_user))
+        .routes(routes!(replace_user))
+        .routes(routes!(patch_user))
         .routes(routes!(delete_user))
         .routes(routes!(get_users))
+        .routes(routes!(bulk_users))
+        .routes(routes!(unlock_user))
+}
+
+pub fn get_public_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(get_user_name_availability))
 }
 
 #[utoipa::path(
@@ -25,13 +49,16 @@ pub fn get_routes() -> OpenApiRouter<AppState> {
     request_body = UserDto,
     responses(
         (status = 201, description = "Create new user", body = UserDto),
+        (status = 422, description = "Invalid fields in the request body", body = ApiError),
         (status = "default", body = AuthError),
     ),
     tag = USER_TAG,
+    security(("Jwt" = ["user:create"])),
 )]
 async fn create_user(
+    _: RequirePermission<UserCreate>,
     State(UsersApi { user_manager, .. }): State<UsersApi>,
-    Json(payload): Json<UserDto>,
+    ValidatedJson(payload): ValidatedJson<UserDto>,
 ) -> ApiResponse<UserDto> {
     user_manager
         .create_user(&payload)
@@ -45,13 +72,17 @@ async fn create_user(
     request_body = UserDto,
     responses(
         (status = OK, description = "Update existing user", body = UserDto),
+        (status = 422, description = "Invalid fields in the request body", body = ApiError),
         (status = "default", description = "General API Error", body = ApiError),
     ),
     tag = USER_TAG,
+    security(("Jwt" = ["user:update"])),
 )]
+#[deprecated = "use PUT /user/{id} instead"]
 async fn update_user(
+    _: RequirePermission<UserUpdate>,
     State(UsersApi { user_manager, .. }): State<UsersApi>,
-    Json(payload): Json<UserDto>,
+    ValidatedJson(payload): ValidatedJson<UserDto>,
 ) -> ApiResponse<UserDto> {
     user_manager
         .update_user(&payload)
@@ -59,72 +90,288 @@ async fn update_user(
         .as_api_response_ok()
 }
 
+#[utoipa::path(
+    put,
+    path = "/user/{id}",
+    request_body = UserDto,
+    responses(
+        (status = OK, description = "Replace an existing user", body = UserDto,
+            headers(("ETag" = String, description = "Version of the user"))),
+        (status = 412, description = "The user no longer matches If-Match", body = ApiError),
+        (status = 422, description = "Invalid fields in the request body", body = ApiError),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    params(
+        ("id" = i32, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only replace the user if its ETag is listed"),
+    ),
+    tag = USER_TAG,
+    security(("Jwt" = ["user:update"])),
+)]
+async fn replace_user(
+    _: RequirePermission<UserUpdate>,
+    State(UsersApi { user_manager, .. }): State<UsersApi>,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(payload): ValidatedJson<UserDto>,
+) -> Response {
+    let result = user_manager
+        .replace_user(&id, &payload, if_match.as_ref())
+        .await;
+
+    tagged_user(result, None)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/user/{id}",
+    request_body(
+        description = "Fields to change as a JSON merge patch, or a list of JSON patch operations",
+        content(
+            (UserDto = "application/merge-patch+json"),
+            ("application/json-patch+json", example = json!([{"op": "replace", "path": "/display_name", "value": "Foo"}])),
+        ),
+    ),
+    responses(
+        (status = OK, description = "Partially update an existing user", body = UserDto,
+            headers(("ETag" = String, description = "Version of the user"))),
+        (status = 412, description = "The user no longer matches If-Match, or changed while being patched", body = ApiError),
+        (status = 415, description = "Unsupported patch format", body = ApiError),
+        (status = 422, description = "Patch can't be applied or produces an invalid user", body = ApiError),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    params(
+        ("id" = i32, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only patch the user if its ETag is listed"),
+    ),
+    tag = USER_TAG,
+    security(("Jwt" = ["user:update"])),
+)]
+async fn patch_user(
+    _: RequirePermission<UserUpdate>,
+    State(UsersApi { user_manager, .. }): State<UsersApi>,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    patch: PatchDocument,
+) -> Response {
+    let result = user_manager
+        .patch_user(&id, &patch, if_match.as_ref())
+        .await;
+
+    tagged_user(result, None)
+}
+
 #[utoipa::path(
     get,
     path = "/user/{id}",
     responses(
-        (status = OK, description = "Find user by user ID", body = UserDto),
+        (status = OK, description = "Find user by user ID", body = UserDto,
+            headers(("ETag" = String, description = "Version of the user"))),
+        (status = NOT_MODIFIED, description = "The user still matches If-None-Match"),
         (status = "default", description = "General API Error", body = ApiError),
     ),
     params(
-        ("id" = i32, Path, description = "User ID")
+        ("id" = i32, Path, description = "User ID"),
+        ("If-None-Match" = Option<String>, Header, description = "ETags of the user the client already has"),
+        UserQuery,
     ),
     tag = USER_TAG,
+    security(("Jwt" = ["user:read"])),
 )]
 async fn get_user(
+    _: RequirePermission<UserRead>,
     State(UsersApi { user_manager, .. }): State<UsersApi>,
     Path(id): Path<i32>,
-) -> ApiResponse<UserDto> {
-    user_manager
-        .get_user(&id)
-        .await
-        .as_api_response_ok()
+    IfNoneMatch(if_none_match): IfNoneMatch,
+    Query(query): Query<UserQuery>,
+) -> Response {
+    let result = user_manager
+        .get_user(&id, query.include_deleted.unwrap_or_default())
+        .await;
+
+    tagged_user(result, if_none_match.as_ref())
 }
 
 #[utoipa::path(
     get,
     path = "/users",
+    params(PageQuery),
     responses(
-        (status = CREATED, description = "Retrieve all users", body = Vec<UserDto>),
+        (status = OK, description = "Retrieve a page of users", body = Page<UserDto>),
         (status = "default", description = "General API Error", body = ApiError),
     ),
     tag = USER_TAG,
+    security(("Jwt" = ["user:read"])),
 )]
 async fn get_users(
+    _: RequirePermission<UserRead>,
+    State(UsersApi { user_manager, .. }): State<UsersApi>,
+    Query(query): Query<PageQuery>,
+) -> ApiResponse<Page<UserDto>> {
+    user_manager
+        .get_users(&query)
+        .await
+        .as_api_response_ok()
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/bulk",
+    request_body = BulkUserRequest,
+    responses(
+        (status = OK, description = "Every operation was applied", body = BulkUserResponse),
+        (status = MULTI_STATUS, description = "Some operations failed, see their results", body = BulkUserResponse),
+        (status = 422, description = "Invalid request body", body = ApiError),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    tag = USER_TAG,
+    security(("Jwt" = ["user:create", "user:update", "user:delete"])),
+)]
+async fn bulk_users(
+    auth_user: AuthUser,
     State(UsersApi { user_manager, .. }): State<UsersApi>,
-) -> (StatusCode, Json<Vec<UserDto>>) {
-    (StatusCode::OK, Json(user_manager.get_users().await))
+    State(access_control): State<AccessControl>,
+    ValidatedJson(request): ValidatedJson<BulkUserRequest>,
+) -> Response {
+    let roles = &auth_user.claims.roles;
+
+    for operation in &request.operations {
+        let allowed = match access_control.has_permission(roles, operation.permission()).await {
+            Ok(allowed) => allowed,
+            Err(e) => return e.into_response(),
+        };
+
+        if !allowed {
+            warn!("User {} is missing permission {}", auth_user.claims.sub, operation.permission());
+            return AuthError::Forbidden.into_response();
+        }
+    }
+
+    match user_manager.bulk_users(&request).await {
+        Ok(response) if response.failed > 0 => (StatusCode::MULTI_STATUS, Json(response)).into_response(),
+        result => result.as_api_response_ok().into_response(),
+    }
 }
 
 #[utoipa::path(
     delete,
     path = "/user/{id}",
     responses(
-        (status = OK, description = "Delete a user by user ID", body = Vec<UserDto>),
+        (status = OK, description = "Delete a user by user ID"),
+        (status = 412, description = "The user no longer matches If-Match", body = ApiError),
         (status = "default", description = "General API Error", body = ApiError),
     ),
     params(
-        ("id" = i32, Path, description = "User ID")
+        ("id" = i32, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only delete the user if its ETag is listed"),
+        DeleteQuery,
     ),
     tag = USER_TAG,
+    security(("Jwt" = ["user:delete"])),
 )]
 async fn delete_user(
+    _: RequirePermission<UserDelete>,
+    State(UsersApi { user_manager, .. }): State<UsersApi>,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    Query(query): Query<DeleteQuery>,
+) -> ApiResponse<()> {
+    user_manager
+        .delete_user(&id, query.permanent.unwrap_or_default(), if_match.as_ref())
+        .await
+        .as_api_response_ok()
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/{id}/unlock",
+    responses(
+        (status = OK, description = "Lift the login lockout of a user"),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    tag = USER_TAG,
+    security(("Jwt" = ["user:unlock"])),
+)]
+async fn unlock_user(
+    _: RequirePermission<UserUnlock>,
+    auth_user: AuthUser,
     State(UsersApi { user_manager, .. }): State<UsersApi>,
+    State(login_throttle): State<LoginThrottle>,
     Path(id): Path<i32>,
-) -> (StatusCode, Json<()>) {
-    (user_manager.delete_user(&id).await, Json(()))
+) -> ApiResponse<()> {
+    let user = match user_manager.get_user(&id, false).await {
+        Ok(user) => user,
+        Err(e) => return Err(e).as_api_response_ok(),
+    };
+
+    login_throttle
+        .unlock(&user.user_name.unwrap_or_default(), &auth_user.claims.sub)
+        .await
+        .map(|_| ())
+        .map_err(|_| AuthError::ServiceUnavailable)
+        .as_api_response_ok()
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/availability",
+    params(AvailabilityQuery),
+    responses(
+        (status = OK, description = "Check whether a user name can be used to sign up", body = UserNameAvailability),
+        (status = 422, description = "Invalid user name", body = ApiError),
+        (status = "default", description = "General API Error", body = ApiError),
+    ),
+    tag = USER_TAG,
+    security(),
+)]
+async fn get_user_name_availability(
+    State(UsersApi { user_manager, .. }): State<UsersApi>,
+    ValidatedQuery(query): ValidatedQuery<AvailabilityQuery>,
+) -> ApiResponse<UserNameAvailability> {
+    user_manager
+        .get_user_name_availability(&query.user_name)
+        .await
+        .as_api_response_ok()
+}
+
+/// Renders the user with its version as `ETag`, or `304 Not Modified` when `if_none_match` lists
+/// that version.
+fn tagged_user(result: Result<UserDto, UserError>, if_none_match: Option<&EntityTags>) -> Response {
+    match result.as_ref().ok().and_then(|user| user.version) {
+        Some(version) if if_none_match.is_some_and(|tags| tags.matches(version)) => {
+            (StatusCode::NOT_MODIFIED, [(ETAG, etag(version))]).into_response()
+        }
+        Some(version) => ([(ETAG, etag(version))], result.as_api_response_ok()).into_response(),
+        None => result.as_api_response_ok().into_response(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config;
+    use crate::config::authentication::Keys;
+    use crate::config::settings::Settings;
+    use crate::controller::auth_controller;
+    use crate::model::auth::{LoginDto, LoginScope};
+    use crate::model::user::{User, UserStatus};
+    use crate::repository::repository_traits::WriteRepository;
+    use crate::repository::{
+        LoginFailureRepository, LoginFailureStore, RoleRepository, UserRepository,
+        UserRoleRepository,
+    };
+    use crate::util::password::hash_password;
+    use uuid::Uuid;
     use axum::body::Body;
-    use axum::http::header::CONTENT_TYPE;
+    use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
     use axum::http::Request;
-    use axum::Router;
+    use axum::{middleware, Router};
+    use chrono::{Duration, Utc};
     use http_body_util::BodyExt;
-    use serde_json::Value;
+    use serde_json::{json, Value};
     use sqlx::PgPool;
     use tower::util::ServiceExt;
 
@@ -160,7 +407,11 @@ mod tests {
     }
 
     async fn get_all_users(app: &Router) -> axum::response::Response {
-        let req = Request::get("/users").body(Body::empty()).unwrap();
+        get_users_page(app, "").await
+    }
+
+    async fn get_users_page(app: &Router, query: &str) -> axum::response::Response {
+        let req = Request::get(format!("/users?{query}")).body(Body::empty()).unwrap();
         app.clone().oneshot(req).await.unwrap()
     }
 
@@ -184,6 +435,8 @@ mod tests {
         UserDto {
             id: None,
             user_name: Some(user_name.to_string()),
+            password: Some("password".to_string()),
+            ..UserDto::default()
         }
     }
 
@@ -191,13 +444,77 @@ mod tests {
         UserDto {
             id: Some(id),
             user_name: Some(user_name.to_string()),
+            password: None,
+            ..UserDto::default()
         }
     }
 
-    async fn app(pool: PgPool) -> Router {
+    async fn login(app: &Router, user_name: &str, password: &str) -> String {
+        let login = LoginDto {
+            user_name: user_name.to_string(),
+            password: password.to_string(),
+        };
+        let req = Request::post("/login")
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_string(&login).unwrap()))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        unwrap_ok(res).await["access_token"].as_str().unwrap().to_string()
+    }
+
+    fn with_token(app: Router, token: String) -> Router {
+        app.layer(middleware::map_request(move |mut req: Request<Body>| {
+            let header = format!("Bearer {token}").parse().unwrap();
+            async move {
+                req.headers_mut().insert(AUTHORIZATION, header);
+                req
+            }
+        }))
+    }
+
+    async fn unauthenticated_app(pool: PgPool) -> Router {
+        let admin = User {
+            password_hash: Some(hash_password("admin").unwrap()),
+            ..User::new("admin")
+        };
+
+        // tests building several apps on the same pool only create the admin once
+        if let Ok(admin) = UserRepository::new(&pool).create(&admin).await {
+            RoleRepository::new(&pool)
+                .assign_role(&admin.id.unwrap(), "admin")
+                .await
+                .unwrap();
+        }
+
         let routes = vec![get_routes()];
+        let public_routes = vec![auth_controller::get_routes(), get_public_routes()];
+
+        let keys = Keys::from_secret(b"secret");
 
-        config::app(pool, vec![], routes).await
+        config::app(Settings::test(), pool, keys, routes, public_routes, vec![]).await
+    }
+
+    async fn app(pool: PgPool) -> Router {
+        let app = unauthenticated_app(pool).await;
+        let token = login(&app, "admin", "admin").await;
+
+        with_token(app, token)
+    }
+
+    /// App on in-memory repositories, logged in as the bootstrapped admin.
+    async fn in_memory_app() -> Router {
+        let mut settings = Settings::test();
+        settings.database.in_memory = true;
+        settings.auth.admin_user_name = Some("admin".to_string());
+        settings.auth.admin_password = Some("admin".to_string());
+
+        let state = AppState::in_memory(settings, Keys::from_secret(b"secret")).await;
+        let public_routes = vec![auth_controller::get_routes(), get_public_routes()];
+        let app = config::router(state, vec![get_routes()], public_routes, vec![]);
+        let token = login(&app, "admin", "admin").await;
+
+        with_token(app, token)
     }
 
     #[sqlx::test]
@@ -212,12 +529,90 @@ mod tests {
 
         assert!(body.get("id").is_some());
         assert_eq!(body["user_name"], "foo");
+        assert!(body.get("password").is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_without_password(pool: PgPool) {
+        let app = app(pool).await;
+        let user = UserDto {
+            password: None,
+            ..user("foo")
+        };
+        let res = create_user(&app, user).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = unwrap_err(res).await;
+
+        assert_eq!(body["code"], "ValidationFailed");
+        assert_eq!(body["errors"][0]["field"], "password");
+        assert_eq!(body["errors"][0]["code"], "required");
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_with_invalid_fields(pool: PgPool) {
+        let app = app(pool).await;
+        let invalid = UserDto {
+            user_name: Some("x".repeat(256)),
+            password: Some("short".to_string()),
+            email: Some("foo".to_string()),
+            ..UserDto::default()
+        };
+        let res = create_user(&app, invalid).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = unwrap_err(res).await;
+        let fields: Vec<_> = body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(fields, ["email", "password", "user_name"]);
+
+        let empty_name = UserDto {
+            user_name: Some(String::new()),
+            ..user("foo")
+        };
+        let res = create_user(&app, empty_name).await;
+        let body = unwrap_err(res).await;
+
+        assert_eq!(body["errors"][0]["field"], "user_name");
+        assert_eq!(body["errors"][0]["code"], "length");
+
+        let invalid_profile = UserDto {
+            display_name: Some("  ".to_string()),
+            status: Some(UserStatus::Deleted),
+            ..user("foo")
+        };
+        let res = create_user(&app, invalid_profile).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = unwrap_err(res).await;
+
+        assert_eq!(body["errors"][0]["field"], "display_name");
+        assert_eq!(body["errors"][0]["code"], "blank");
+        assert_eq!(body["errors"][1]["field"], "status");
+        assert_eq!(body["errors"][1]["code"], "deleted");
+
+        for email in ["foo@", "@example.com", "foo@bar@example.com", "foo @example.com"] {
+            let res = create_user(&app, UserDto { email: Some(email.to_string()), ..user("foo") }).await;
+
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{email}");
+        }
     }
 
     #[sqlx::test]
     async fn test_create_existing_user(pool: PgPool) {
         let app = app(pool).await;
-        let user = existing_user(1, "foo");
+        let user = UserDto {
+            password: Some("password".to_string()),
+            ..existing_user(1, "foo")
+        };
         let res = create_user(&app, user).await;
 
         assert!(res.status().is_client_error());
@@ -258,6 +653,54 @@ mod tests {
         assert_eq!(body["code"], "NotFound");
     }
 
+    #[sqlx::test]
+    async fn test_get_missing_user_as_problem(pool: PgPool) {
+        let app = app(pool).await;
+        let req = Request::get("/user/23423423")
+            .header(ACCEPT, "application/problem+json")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[CONTENT_TYPE], "application/problem+json");
+
+        let body = unwrap_err(res).await;
+
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["code"], "NotFound");
+        assert_eq!(body["instance"], "/user/23423423");
+    }
+
+    #[sqlx::test]
+    async fn test_request_id(pool: PgPool) {
+        let app = app(pool).await;
+        let res = get_user(&app, 23423423).await;
+        let request_id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+
+        assert!(Uuid::parse_str(&request_id).is_ok());
+        assert_eq!(unwrap_err(res).await["request_id"], request_id);
+
+        let req = Request::get("/user/23423423")
+            .header("x-request-id", "client-id")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.headers()["x-request-id"], "client-id");
+        assert_eq!(unwrap_err(res).await["request_id"], "client-id");
+    }
+
+    #[sqlx::test]
+    async fn test_get_user_invalid_id(pool: PgPool) {
+        let app = app(pool).await;
+        let req = Request::get("/user/abc").body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(unwrap_err(res).await["code"], "BadRequest");
+    }
+
     #[sqlx::test]
     async fn test_get_all_users(pool: PgPool) {
         let users: Vec<UserDto> = vec!["foo123", "bar123", "baz123"]
@@ -275,11 +718,54 @@ mod tests {
         assert!(res.status().is_success());
 
         let body = unwrap_res(res).await.clone();
-        let body = body.as_array().unwrap();
+        let items = body["items"].as_array().unwrap();
 
-        assert!(body.iter().any(|u| u["user_name"] == "foo123"));
-        assert!(body.iter().any(|u| u["user_name"] == "bar123"));
-        assert!(body.iter().any(|u| u["user_name"] == "baz123"));
+        assert!(items.iter().any(|u| u["user_name"] == "foo123"));
+        assert!(items.iter().any(|u| u["user_name"] == "bar123"));
+        assert!(items.iter().any(|u| u["user_name"] == "baz123"));
+        assert!(body["next_cursor"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn test_get_users_paginated(pool: PgPool) {
+        let app = app(pool).await;
+
+        for name in ["foo1", "foo2", "foo3", "bar1"] {
+            create_user(&app, user(name)).await;
+        }
+
+        let res = get_users_page(&app, "limit=2&sort=-id&user_name=foo&include_total=true").await;
+
+        assert!(res.status().is_success());
+
+        let body = unwrap_res(res).await;
+
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["items"][0]["user_name"], "foo3");
+        assert_eq!(body["items"][1]["user_name"], "foo2");
+
+        let cursor = body["next_cursor"].as_str().unwrap();
+        let res = get_users_page(&app, &format!("limit=2&sort=-id&user_name=foo&cursor={cursor}")).await;
+        let body = unwrap_res(res).await;
+
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert_eq!(body["items"][0]["user_name"], "foo1");
+        assert!(body["next_cursor"].is_null());
+        assert!(body.get("total").is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_get_users_invalid_query(pool: PgPool) {
+        let app = app(pool).await;
+
+        let res = get_users_page(&app, "sort=password").await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = get_users_page(&app, "cursor=foo").await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(unwrap_err(res).await["code"], "InvalidPageRequest");
     }
 
     #[sqlx::test]
@@ -317,6 +803,250 @@ mod tests {
         assert_eq!(body["code"], "MissingId");
     }
 
+    #[sqlx::test]
+    async fn test_replace_user(pool: PgPool) {
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+        let replace = |id: i32, user: UserDto| {
+            let app = app.clone();
+            async move {
+                let req = Request::put(format!("/user/{id}"))
+                    .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(&user).unwrap()))
+                    .unwrap();
+                app.oneshot(req).await.unwrap()
+            }
+        };
+
+        let res = replace(id, UserDto { id: None, ..existing_user(id, "bar") }).await;
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["id"], id);
+        assert_eq!(body["user_name"], "bar");
+
+        let res = replace(id, existing_user(id + 1, "baz")).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(unwrap_err(res).await["code"], "InvalidField");
+
+        let res = replace(id, UserDto::default()).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(unwrap_err(res).await["errors"][0]["field"], "user_name");
+
+        let res = replace(23423423, existing_user(23423423, "baz")).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn patch_user(
+        app: &Router,
+        id: i32,
+        content_type: &str,
+        patch: Value,
+    ) -> axum::response::Response {
+        let req = Request::patch(format!("/user/{id}"))
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(patch.to_string()))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_merge_patch_user(pool: PgPool) {
+        let app = app(pool).await;
+        let user = UserDto {
+            email: Some("foo@example.com".to_string()),
+            display_name: Some("Foo".to_string()),
+            ..user("foo")
+        };
+        let body = unwrap_ok(create_user(&app, user).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        let patch = json!({"display_name": "Bar", "email": null});
+        let res = patch_user(&app, id, "application/merge-patch+json", patch).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["user_name"], "foo");
+        assert_eq!(body["display_name"], "Bar");
+        assert!(body["email"].is_null());
+
+        let patch = json!({"password": "new password"});
+        let res = patch_user(&app, id, "application/merge-patch+json", patch).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!login(&app, "foo", "new password").await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_merge_patch_user_invalid(pool: PgPool) {
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        let patch = json!({"user_name": null, "email": "foo"});
+        let res = patch_user(&app, id, "application/merge-patch+json", patch).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = unwrap_err(res).await;
+
+        assert_eq!(body["code"], "ValidationFailed");
+        assert_eq!(body["errors"][0]["field"], "email");
+        assert_eq!(body["errors"][1]["field"], "user_name");
+        assert_eq!(body["errors"][1]["code"], "required");
+
+        let res = patch_user(&app, id, "application/merge-patch+json", json!({"id": 5})).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = patch_user(&app, 23423423, "application/merge-patch+json", json!({})).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = patch_user(&app, id, "application/json", json!({})).await;
+
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(res.headers()["accept-patch"].to_str().unwrap().contains("application/merge-patch+json"));
+    }
+
+    #[sqlx::test]
+    async fn test_json_patch_user(pool: PgPool) {
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        let patch = json!([
+            {"op": "test", "path": "/user_name", "value": "foo"},
+            {"op": "replace", "path": "/display_name", "value": "Foo"},
+        ]);
+        let res = patch_user(&app, id, "application/json-patch+json", patch).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(unwrap_ok(res).await["display_name"], "Foo");
+
+        let patch = json!([{"op": "test", "path": "/user_name", "value": "bar"}]);
+        let res = patch_user(&app, id, "application/json-patch+json", patch).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(unwrap_err(res).await["code"], "InvalidPatch");
+    }
+
+    async fn bulk_users(app: &Router, request: Value) -> axum::response::Response {
+        let req = Request::post("/users/bulk")
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(request.to_string()))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_bulk_users(pool: PgPool) {
+        let app = app(pool).await;
+        let id = unwrap_ok(create_user(&app, user("foo")).await).await["id"].as_i64().unwrap();
+        let request = json!({
+            "operations": [
+                {"op": "create", "user": {"user_name": "bar", "password": "password"}},
+                {"op": "update", "user": {"id": id, "user_name": "foo", "display_name": "Foo"}},
+            ],
+        });
+        let res = bulk_users(&app, request).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["succeeded"], 2);
+        assert_eq!(body["results"][0]["status"], 201);
+        assert_eq!(body["results"][1]["user"]["display_name"], "Foo");
+        assert!(!login(&app, "bar", "password").await.is_empty());
+
+        let request = json!({
+            "mode": "best_effort",
+            "operations": [
+                {"op": "create", "user": {"user_name": "Bar", "password": "password"}},
+                {"op": "create", "user": {"user_name": "baz", "password": "password"}},
+                {"op": "delete", "id": 23423423},
+            ],
+        });
+        let res = bulk_users(&app, request).await;
+
+        assert_eq!(res.status(), StatusCode::MULTI_STATUS);
+
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["failed"], 2);
+        assert_eq!(body["results"][0]["error"]["code"], "UserNameTaken");
+        assert_eq!(body["results"][1]["status"], 201);
+        assert_eq!(body["results"][2]["status"], 404);
+
+        let res = bulk_users(&app, json!({"operations": []})).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[sqlx::test]
+    async fn test_etag_and_preconditions(pool: PgPool) {
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        let res = get_user(&app, id).await;
+
+        assert_eq!(res.headers()["etag"], "\"1\"");
+
+        let req = Request::get(format!("/user/{id}"))
+            .header("if-none-match", "W/\"1\"")
+            .body(Body::empty())
+            .unwrap();
+        let res = send(req).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()["etag"], "\"1\"");
+
+        let req = Request::patch(format!("/user/{id}"))
+            .header(CONTENT_TYPE, "application/merge-patch+json")
+            .header("if-match", "\"1\"")
+            .body(Body::from(json!({"display_name": "Foo"}).to_string()))
+            .unwrap();
+        let res = send(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["etag"], "\"2\"");
+
+        let req = Request::put(format!("/user/{id}"))
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header("if-match", "\"1\"")
+            .body(Body::from(serde_json::to_string(&existing_user(id, "bar")).unwrap()))
+            .unwrap();
+        let res = send(req).await;
+
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(unwrap_err(res).await["code"], "VersionConflict");
+
+        let req = Request::delete(format!("/user/{id}"))
+            .header("if-match", "\"1\"")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(send(req).await.status(), StatusCode::PRECONDITION_FAILED);
+
+        let req = Request::delete(format!("/user/{id}"))
+            .header("if-match", "\"2\"")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(send(req).await.status(), StatusCode::OK);
+    }
+
     #[sqlx::test]
     async fn test_delete_user(pool: PgPool) {
         let app = app(pool).await;
@@ -330,6 +1060,197 @@ mod tests {
         let res = delete_user(&app, id).await;
 
         assert!(res.status().is_success());
+        assert_eq!(get_user(&app, id).await.status(), StatusCode::NOT_FOUND);
+
+        let req = Request::get(format!("/user/{id}?include_deleted=true"))
+            .body(Body::empty())
+            .unwrap();
+        let body = unwrap_ok(app.clone().oneshot(req).await.unwrap()).await;
+
+        assert_eq!(body["status"], "deleted");
+        assert!(body["deleted_at"].is_string());
+
+        let res = get_all_users(&app).await;
+        let body = unwrap_res(res).await;
+
+        assert!(!body["items"].as_array().unwrap().iter().any(|u| u["id"] == id));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_crud() {
+        let app = in_memory_app().await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        assert_eq!(create_user(&app, user("FOO")).await.status(), StatusCode::CONFLICT);
+        assert_eq!(unwrap_ok(get_user(&app, id).await).await["user_name"], "foo");
+
+        let res = update_user(&app, existing_user(id, "bar")).await;
+
+        assert!(res.status().is_success());
+        assert_eq!(unwrap_ok(res).await["version"], 2);
+        assert!(delete_user(&app, id).await.status().is_success());
+        assert_eq!(get_user(&app, id).await.status(), StatusCode::NOT_FOUND);
+
+        let body = unwrap_res(get_all_users(&app).await).await;
+        let items = body["items"].as_array().unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["user_name"], "admin");
+    }
+
+    #[sqlx::test]
+    async fn test_permanently_delete_user(pool: PgPool) {
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        delete_user(&app, id).await;
+
+        let req = Request::delete(format!("/user/{id}?permanent=true"))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert!(res.status().is_success());
+
+        let req = Request::get(format!("/user/{id}?include_deleted=true"))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn test_permanently_deleted_user_loses_access(pool: PgPool) {
+        let base = unauthenticated_app(pool.clone()).await;
+        let token = login(&base, "admin", "admin").await;
+        let app = with_token(base.clone(), token);
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap() as i32;
+
+        RoleRepository::new(&pool).assign_role(&id, "admin").await.unwrap();
+
+        let token = login(&base, "foo", "password").await;
+        let foo_app = with_token(base, token);
+
+        assert_eq!(get_all_users(&foo_app).await.status(), StatusCode::OK);
+
+        let req = Request::delete(format!("/user/{id}?permanent=true"))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert!(res.status().is_success());
+        assert_eq!(get_all_users(&foo_app).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_with_profile(pool: PgPool) {
+        let app = app(pool).await;
+        let res = create_user(
+            &app,
+            UserDto {
+                email: Some("foo@example.com".to_string()),
+                display_name: Some("Foo".to_string()),
+                ..user("foo")
+            },
+        )
+        .await;
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["email"], "foo@example.com");
+        assert_eq!(body["display_name"], "Foo");
+        assert_eq!(body["status"], "active");
+
+        let res = create_user(
+            &app,
+            UserDto {
+                email: Some("FOO@example.com".to_string()),
+                ..user("bar")
+            },
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = create_user(
+            &app,
+            UserDto {
+                status: Some(UserStatus::Deleted),
+                ..user("baz")
+            },
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(unwrap_err(res).await["code"], "ValidationFailed");
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_with_taken_name(pool: PgPool) {
+        let app = app(pool).await;
+
+        assert!(create_user(&app, user("foo")).await.status().is_success());
+
+        let res = create_user(&app, user("FOO")).await;
+
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(unwrap_err(res).await["code"], "UserNameTaken");
+    }
+
+    #[sqlx::test]
+    async fn test_user_name_availability(pool: PgPool) {
+        let app = unauthenticated_app(pool).await;
+        let availability = |query: &'static str| {
+            let app = app.clone();
+            async move {
+                let req = Request::get(format!("/user/availability?{query}"))
+                    .body(Body::empty())
+                    .unwrap();
+                app.oneshot(req).await.unwrap()
+            }
+        };
+
+        let res = availability("user_name=Admin").await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = unwrap_ok(res).await;
+
+        assert_eq!(body["user_name"], "Admin");
+        assert_eq!(body["available"], false);
+        assert_eq!(unwrap_ok(availability("user_name=foo").await).await["available"], true);
+
+        let res = availability("user_name=foo%20bar").await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(unwrap_err(res).await["errors"][0]["field"], "user_name");
+        assert_eq!(availability("").await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn test_unlock_user(pool: PgPool) {
+        let failures = LoginFailureRepository::new(&pool);
+        let app = app(pool).await;
+        let body = unwrap_ok(create_user(&app, user("foo")).await).await;
+        let id = body["id"].as_i64().unwrap();
+        let now = Utc::now().naive_utc();
+
+        failures.record_failure(LoginScope::User, "foo", now, now).await.unwrap();
+        failures.lock(LoginScope::User, "foo", now + Duration::minutes(15)).await.unwrap();
+
+        let req = Request::post(format!("/user/{id}/unlock")).body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert!(res.status().is_success());
+        assert!(failures.find(LoginScope::User, "foo").await.unwrap().is_none());
+
+        let req = Request::post("/user/23423423/unlock").body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
 
     #[sqlx::test]
@@ -343,4 +1264,40 @@ mod tests {
 
         assert!(res.status().is_client_error());
     }
+
+    #[sqlx::test]
+    async fn test_missing_token(pool: PgPool) {
+        let app = unauthenticated_app(pool).await;
+        let res = get_all_users(&app).await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(unwrap_err(res).await["code"], "MissingToken");
+    }
+
+    #[sqlx::test]
+    async fn test_missing_permission(pool: PgPool) {
+        let admin_app = app(pool.clone()).await;
+        let res = create_user(&admin_app, user("foo")).await;
+        let id = unwrap_ok(res).await["id"].as_i64().unwrap() as i32;
+        let app = unauthenticated_app(pool).await;
+        let token = login(&app, "foo", "password").await;
+        let app = with_token(app, token);
+
+        assert!(get_user(&app, id).await.status().is_success());
+        assert!(get_all_users(&app).await.status().is_success());
+
+        let res = delete_user(&app, id).await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(unwrap_err(res).await["code"], "Forbidden");
+
+        let res = create_user(&app, user("bar")).await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = bulk_users(&app, json!({"operations": [{"op": "delete", "id": id}]})).await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
 }
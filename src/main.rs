@@ -8,6 +8,9 @@ mod repository;
 mod controller;
 mod middleware;
 
+use crate::config::authentication::Keys;
+use crate::config::settings::Settings;
+use crate::config::shutdown;
 use crate::state::AppState;
 use log::info;
 use tokio::net::TcpListener;
@@ -17,7 +20,6 @@ use tokio::net::TcpListener;
 #[deny(clippy::all, clippy::pedantic)]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
-    config::get_tracing();
 
     let routes = vec![
         controller::user_controller::get_routes(),
@@ -25,13 +27,52 @@ async fn main() -> std::io::Result<()> {
     ];
     let public_routes = vec![
         controller::auth_controller::get_routes(),
+        controller::user_controller::get_public_routes(),
     ];
-    let pool = AppState::get_pool().await?;
-    let app = config::app(pool, routes, public_routes).await;
-    let listener = TcpListener::bind("0.0.0.0:3000").await?;
+    let open_routes = vec![
+        controller::auth_controller::get_open_routes(),
+        controller::health_controller::get_routes(),
+        controller::metrics_controller::get_routes(),
+    ];
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    config::get_tracing(&settings.log);
+    let keys = Keys::from_settings(&settings.auth).map_err(std::io::Error::other)?;
+    let pool = if settings.database.in_memory {
+        None
+    } else {
+        Some(AppState::get_pool(&settings.database).await?)
+    };
+    let address = settings.server.address;
+    let drain_timeout = settings.server.shutdown_timeout();
+    let app = if let Some(pool) = pool.clone() {
+        config::app(settings, pool, keys, routes, public_routes, open_routes).await
+    } else {
+        let state = AppState::in_memory(settings, keys).await;
+
+        config::router(state, routes, public_routes, open_routes)
+    };
+    let listener = TcpListener::bind(address).await?;
     let addr = listener.local_addr()?;
 
     info!("Serving app on {addr}");
 
-    axum::serve(listener, app.into_make_service()).await
+    let summary = shutdown::serve(
+        listener,
+        app,
+        shutdown::shutdown_signal(),
+        drain_timeout,
+    )
+    .await?;
+
+    if let Some(pool) = pool {
+        info!("Closing database connections...");
+        pool.close().await;
+    }
+
+    info!("Completed {summary}");
+
+    Ok(())
 }
@@ -1,16 +1,38 @@
-use crate::model::api_response::{ApiError, AsApiError, ResponseError};
-use crate::model::user::{User, UserDto};
-use crate::repository::repository_traits::ArcRepository;
+use crate::model::api_response::{ApiError, AsApiError, FieldError, ResponseError};
+use crate::model::audit::AuditEntry;
+use crate::model::etag::EntityTags;
+use crate::model::page::{Page, PageQuery, PageRequest};
+use crate::model::patch::PatchDocument;
+use crate::model::user::{
+    BulkItemResult, BulkMode, BulkOperation, BulkUserRequest, BulkUserResponse, User, UserDto,
+    UserNameAvailability, UserStatus,
+};
+use crate::repository::repository_traits::{ArcRepository, Batch, BatchOutcome};
+use crate::model::auth::LoginScope;
+use crate::repository::{
+    ArcLoginFailureStore, ArcRefreshTokenStore, ArcUnitOfWorkFactory, ArcUserNameRepository,
+    BoxUnitOfWork, RepositoryError, RepositoryResult, UnitOfWork, USER_NAME_KEY,
+};
+use crate::services::{user_subject, Metrics, RevocationStore};
+use crate::util::password::hash_password_blocking;
+use crate::util::validation::{validate, Operation};
 use crate::util::AsDtoEnabled;
 use axum::http::StatusCode;
-use log::{error, info};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use utoipa::ToSchema;
 
 #[derive(Clone)]
 pub struct UserManager {
     user_repository: ArcRepository<User, i32>,
+    user_name_repository: ArcUserNameRepository,
+    refresh_token_repository: ArcRefreshTokenStore,
+    login_failure_repository: ArcLoginFailureStore,
+    unit_of_work: ArcUnitOfWorkFactory,
+    revocation_store: RevocationStore,
+    metrics: Metrics,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, ToSchema, Error)]
@@ -22,11 +44,44 @@ pub enum UserError {
     #[error("No user ID provided by request")]
     MissingId,
 
+    #[error("No password provided by request")]
+    MissingPassword,
+
+    #[error("Invalid {0}: {1}")]
+    InvalidField(String, String),
+
+    #[error("The user is invalid")]
+    ValidationFailed(Vec<FieldError>),
+
+    #[error("Unable to apply patch: {0}")]
+    InvalidPatch(String),
+
     #[error("User ID {0} does not exist")]
     NotFound(i32),
 
+    #[error("User ID {0} has been modified since it was read, fetch it again before retrying")]
+    VersionConflict(i32),
+
+    #[error("Invalid page request: {0}")]
+    InvalidPageRequest(String),
+
+    #[error("User name {0} is already taken")]
+    UserNameTaken(String),
+
+    #[error("User already exists, {0}")]
+    AlreadyExists(String),
+
+    #[error("User request references a missing entity, {0}")]
+    InvalidReference(String),
+
+    #[error("User service is temporarily unavailable, please try again later")]
+    ServiceUnavailable,
+
     #[error("User request failed: {0}")]
     FailedRequest(String),
+
+    #[error("Not applied, {0}")]
+    BatchAborted(String),
 }
 
 impl ResponseError for UserError {
@@ -36,20 +91,135 @@ impl ResponseError for UserError {
                 self.as_api_error(StatusCode::BAD_REQUEST, "CannotCreateExistingUser"),
             UserError::MissingId =>
                 self.as_api_error(StatusCode::BAD_REQUEST, "MissingId"),
+            UserError::MissingPassword =>
+                self.as_api_error(StatusCode::BAD_REQUEST, "MissingPassword"),
+            UserError::InvalidField(_, _) =>
+                self.as_api_error(StatusCode::BAD_REQUEST, "InvalidField"),
+            UserError::ValidationFailed(errors) => {
+                let (status, mut error) =
+                    self.as_api_error(StatusCode::UNPROCESSABLE_ENTITY, "ValidationFailed");
+                error.errors = errors.clone();
+                (status, error)
+            }
+            UserError::InvalidPatch(_) =>
+                self.as_api_error(StatusCode::UNPROCESSABLE_ENTITY, "InvalidPatch"),
             UserError::NotFound(_) =>
                 self.as_api_error(StatusCode::NOT_FOUND, "NotFound"),
+            UserError::VersionConflict(_) =>
+                self.as_api_error(StatusCode::PRECONDITION_FAILED, "VersionConflict"),
+            UserError::InvalidPageRequest(_) =>
+                self.as_api_error(StatusCode::BAD_REQUEST, "InvalidPageRequest"),
+            UserError::UserNameTaken(_) =>
+                self.as_api_error(StatusCode::CONFLICT, "UserNameTaken"),
+            UserError::AlreadyExists(_) =>
+                self.as_api_error(StatusCode::CONFLICT, "AlreadyExists"),
+            UserError::InvalidReference(_) =>
+                self.as_api_error(StatusCode::CONFLICT, "InvalidReference"),
+            UserError::ServiceUnavailable =>
+                self.as_api_error(StatusCode::SERVICE_UNAVAILABLE, "ServiceUnavailable"),
             UserError::FailedRequest(_) =>
                 self.as_api_error(StatusCode::INTERNAL_SERVER_ERROR, "FailedRequest"),
+            UserError::BatchAborted(_) =>
+                self.as_api_error(StatusCode::FAILED_DEPENDENCY, "BatchAborted"),
         }
     }
 }
 
 impl UserManager {
-    pub fn new(user_repository: ArcRepository<User, i32>) -> Self {
-        Self { user_repository }
+    const DEFAULT_ROLE: &'static str = "user";
+
+    pub fn new(
+        user_repository: ArcRepository<User, i32>,
+        user_name_repository: ArcUserNameRepository,
+        refresh_token_repository: ArcRefreshTokenStore,
+        login_failure_repository: ArcLoginFailureStore,
+        unit_of_work: ArcUnitOfWorkFactory,
+        revocation_store: RevocationStore,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            user_repository,
+            user_name_repository,
+            refresh_token_repository,
+            login_failure_repository,
+            unit_of_work,
+            revocation_store,
+            metrics,
+        }
     }
 
     pub async fn create_user(&self, payload: &UserDto) -> Result<UserDto, UserError> {
+        self.record("create", self.create(payload).await)
+    }
+
+    pub async fn update_user(&self, payload: &UserDto) -> Result<UserDto, UserError> {
+        self.record("update", self.update(payload, None).await)
+    }
+
+    /// Replaces the user `id` with `payload`, whose own `id` may be left out. With `if_match`, the
+    /// user must still be at one of the listed versions.
+    pub async fn replace_user(
+        &self,
+        id: &i32,
+        payload: &UserDto,
+        if_match: Option<&EntityTags>,
+    ) -> Result<UserDto, UserError> {
+        self.record("update", self.replace(id, payload, if_match).await)
+    }
+
+    /// Applies `patch` to the current state of the user `id` and saves the result if it is valid and
+    /// the user didn't change in the meantime.
+    pub async fn patch_user(
+        &self,
+        id: &i32,
+        patch: &PatchDocument,
+        if_match: Option<&EntityTags>,
+    ) -> Result<UserDto, UserError> {
+        self.record("patch", self.patch(id, patch, if_match).await)
+    }
+
+    pub async fn get_user(&self, id: &i32, include_deleted: bool) -> Result<UserDto, UserError> {
+        self.record("get", self.get(id, include_deleted).await)
+    }
+
+    pub async fn get_users(&self, query: &PageQuery) -> Result<Page<UserDto>, UserError> {
+        self.record("list", self.list(query).await)
+    }
+
+    pub async fn get_user_name_availability(
+        &self,
+        user_name: &str,
+    ) -> Result<UserNameAvailability, UserError> {
+        self.record("availability", self.availability(user_name).await)
+    }
+
+    /// Soft deletes the user, or removes it for good when `permanent` is set.
+    pub async fn delete_user(
+        &self,
+        id: &i32,
+        permanent: bool,
+        if_match: Option<&EntityTags>,
+    ) -> Result<(), UserError> {
+        self.record("delete", self.delete(id, permanent, if_match).await)
+    }
+
+    /// Applies a batch of creates, updates and deletes, either all in one transaction or, in
+    /// best-effort mode, every operation that can be applied.
+    pub async fn bulk_users(&self, request: &BulkUserRequest) -> Result<BulkUserResponse, UserError> {
+        self.record("bulk", self.bulk(request).await)
+    }
+
+    fn record<T>(&self, operation: &str, result: Result<T, UserError>) -> Result<T, UserError> {
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_api_err_response().1.code,
+        };
+
+        self.metrics.record_user_operation(operation, &outcome);
+        result
+    }
+
+    async fn create(&self, payload: &UserDto) -> Result<UserDto, UserError> {
         if let Some(v) = payload.id {
             error!("Unable to create new user with existing id {v}");
             return Err(UserError::CannotCreateExistingUser(v));
@@ -63,16 +233,63 @@ impl UserManager {
                 .unwrap_or_else(|| "Unknown".to_string())
         );
 
-        let user = User::from_dto(payload);
+        let Some(password) = payload.password.as_deref().filter(|p| !p.is_empty()) else {
+            error!("Unable to create new user without a password");
+            return Err(UserError::MissingPassword);
+        };
+
+        let mut user = User::from_dto(payload);
+        user.password_hash = Some(Self::hash(password).await?);
+
+        Ok(self.insert(&user, payload).await?.as_dto())
+    }
+
+    /// Creates the user along with its default role and an audit entry, all or nothing.
+    async fn insert(&self, user: &User, payload: &UserDto) -> Result<User, UserError> {
+        let work = self
+            .unit_of_work
+            .begin()
+            .await
+            .map_err(|e| Self::map_error(e, None))?;
+        let result = Self::insert_with(work.as_ref(), user).await;
 
-        self.user_repository
-            .create(&user)
+        Self::finish(work, result)
             .await
-            .map(|u| u.as_dto())
-            .ok_or_else(|| UserError::FailedRequest("Failed to create user".to_string()))
+            .map_err(|e| Self::map_write_error(e, payload))
     }
 
-    pub async fn update_user(&self, payload: &UserDto) -> Result<UserDto, UserError> {
+    async fn insert_with(work: &(dyn UnitOfWork + Send + Sync), user: &User) -> RepositoryResult<User> {
+        let user = work.users().create(user).await?;
+
+        if let Some(id) = user.id {
+            work.roles().assign_role(&id, Self::DEFAULT_ROLE).await?;
+            work.audit().record(&Self::created_entry(&user, id)).await?;
+        }
+
+        Ok(user)
+    }
+
+    fn created_entry(user: &User, id: i32) -> AuditEntry {
+        let subject = user.user_name.as_deref().unwrap_or_default().to_lowercase();
+
+        AuditEntry::new("user_created", &subject, None, Some(format!("user {id}")))
+    }
+
+    /// Commits the unit of work if `result` is a success and rolls it back otherwise.
+    async fn finish<T>(work: BoxUnitOfWork, result: RepositoryResult<T>) -> RepositoryResult<T> {
+        match result {
+            Ok(value) => work.commit().await.map(|()| value),
+            Err(e) => {
+                if let Err(rollback_error) = work.rollback().await {
+                    error!("Unable to roll back user changes: {rollback_error}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Saves `payload`, only if the stored user is still at `expected_version` when one is given.
+    async fn update(&self, payload: &UserDto, expected_version: Option<i32>) -> Result<UserDto, UserError> {
         if payload.id.is_none() {
             error!("Unable to update a user without an existing id");
             return Err(UserError::MissingId);
@@ -80,105 +297,783 @@ impl UserManager {
 
         info!("Updating existing user with id: {}", payload.id.unwrap());
 
-        let user = User::from_dto(payload);
+        let mut user = User::from_dto(payload);
+        user.version = expected_version;
 
-        self.user_repository
-            .update(&user)
-            .await
-            .map(|u| u.as_dto())
-            .ok_or_else(|| UserError::FailedRequest("Failed to update user".to_string()))
+        if let Some(password) = payload.password.as_deref() {
+            if password.is_empty() {
+                error!("Unable to update user {} to an empty password", payload.id.unwrap());
+                return Err(UserError::MissingPassword);
+            }
+
+            info!("Updating password for user with id: {}", payload.id.unwrap());
+            user.password_hash = Some(Self::hash(password).await?);
+        }
+
+        match self.user_repository.update(&user).await {
+            Ok(user) => {
+                self.end_sessions_if_inactive(&user).await?;
+                Ok(user.as_dto())
+            }
+            Err(RepositoryError::NotFound) => {
+                Err(Self::missed_write(payload.id.unwrap(), expected_version))
+            }
+            Err(e) => Err(Self::map_write_error(e, payload)),
+        }
     }
 
-    pub async fn get_user(&self, id: &i32) -> Result<UserDto, UserError> {
-        info!("Retrieving user with id: {id}");
+    /// Maps a write of user `id` that matched no row. With a `version`, the user was read just
+    /// before, so it changed in between rather than went missing.
+    fn missed_write(id: i32, version: Option<i32>) -> UserError {
+        if version.is_none() {
+            error!("User {id} does not exist");
+            return UserError::NotFound(id);
+        }
+
+        error!("User {id} was modified concurrently");
+        UserError::VersionConflict(id)
+    }
+
+    /// Returns the version of `user` if it is one of the `if_match` tags.
+    fn check_version(user: &User, if_match: Option<&EntityTags>) -> Result<Option<i32>, UserError> {
+        let Some(tags) = if_match else {
+            return Ok(None);
+        };
+
+        match user.version {
+            Some(version) if tags.matches(version) => Ok(Some(version)),
+            _ => {
+                let id = user.id.unwrap_or_default();
+                error!("User {id} is at version {:?}, expected {tags:?}", user.version);
+                Err(UserError::VersionConflict(id))
+            }
+        }
+    }
+
+    async fn replace(
+        &self,
+        id: &i32,
+        payload: &UserDto,
+        if_match: Option<&EntityTags>,
+    ) -> Result<UserDto, UserError> {
+        if payload.id.is_some_and(|payload_id| payload_id != *id) {
+            error!("Unable to update user {id} with a payload for user {:?}", payload.id);
+            return Err(UserError::InvalidField("id".to_string(), "does not match the path".to_string()));
+        }
+
+        let payload = UserDto {
+            id: Some(*id),
+            ..payload.clone()
+        };
+        let expected_version = match if_match {
+            Some(_) => {
+                let user = self
+                    .user_repository
+                    .find_by_id(id)
+                    .await
+                    .map_err(|e| Self::map_error(e, Some(id)))?;
 
-        self.user_repository
+                Self::check_version(&user, if_match)?
+            }
+            None => None,
+        };
+
+        self.update(&payload, expected_version).await
+    }
+
+    async fn patch(
+        &self,
+        id: &i32,
+        patch: &PatchDocument,
+        if_match: Option<&EntityTags>,
+    ) -> Result<UserDto, UserError> {
+        info!("Patching user with id: {id}");
+
+        let user = self
+            .user_repository
             .find_by_id(id)
             .await
-            .map(|u| u.as_dto())
-            .ok_or_else(|| UserError::NotFound(*id))
+            .map_err(|e| Self::map_error(e, Some(id)))?;
+        Self::check_version(&user, if_match)?;
+
+        let mut document = serde_json::to_value(user.as_dto())
+            .map_err(|e| UserError::FailedRequest(e.to_string()))?;
+
+        patch.apply(&mut document).map_err(UserError::InvalidPatch)?;
+
+        let payload = UserDto::deserialize(&document)
+            .map_err(|e| UserError::InvalidPatch(e.to_string()))?;
+
+        if payload.id != Some(*id) {
+            return Err(UserError::InvalidField("id".to_string(), "cannot be changed".to_string()));
+        }
+
+        let errors = validate(&document, &payload, Operation::Update);
+
+        if !errors.is_empty() {
+            error!("Rejecting patch of user {id} with {} invalid fields", errors.len());
+            return Err(UserError::ValidationFailed(errors));
+        }
+
+        // the patch was applied to this version, so it must not have changed since
+        self.update(&payload, user.version).await
+    }
+
+    async fn get(&self, id: &i32, include_deleted: bool) -> Result<UserDto, UserError> {
+        info!("Retrieving user with id: {id}");
+
+        let user = if include_deleted {
+            self.user_repository.find_by_id_including_deleted(id).await
+        } else {
+            self.user_repository.find_by_id(id).await
+        };
+
+        user.map(|u| u.as_dto())
+            .map_err(|e| Self::map_error(e, Some(id)))
     }
 
-    pub async fn get_users(&self) -> Vec<UserDto> {
-        let users = self.user_repository.find_all().await;
+    async fn list(&self, query: &PageQuery) -> Result<Page<UserDto>, UserError> {
+        let request = PageRequest::from_query(query)
+            .map_err(|e| UserError::InvalidPageRequest(e.to_string()))?;
+        let page = self
+            .user_repository
+            .find_page(&request)
+            .await
+            .map_err(|e| Self::map_error(e, None))?;
 
-        info!("Retrieving {} users", users.len());
+        info!("Retrieving {} users", page.items.len());
 
-        users.iter().map(AsDtoEnabled::as_dto).collect()
+        Ok(page.map(AsDtoEnabled::as_dto))
     }
 
-    pub async fn delete_user(&self, id: &i32) -> StatusCode {
-        info!("Deleting user with id: {id}");
+    async fn availability(&self, user_name: &str) -> Result<UserNameAvailability, UserError> {
+        let available = match self.user_name_repository.find_by_user_name(user_name).await {
+            Ok(_) => false,
+            Err(RepositoryError::NotFound) => true,
+            Err(e) => return Err(Self::map_error(e, None)),
+        };
+
+        Ok(UserNameAvailability {
+            user_name: user_name.to_string(),
+            available,
+        })
+    }
+
+    async fn delete(
+        &self,
+        id: &i32,
+        permanent: bool,
+        if_match: Option<&EntityTags>,
+    ) -> Result<(), UserError> {
+        let user = if permanent {
+            self.user_repository.find_by_id_including_deleted(id).await
+        } else {
+            self.user_repository.find_by_id(id).await
+        };
+        let user = user.map_err(|e| Self::map_error(e, Some(id)))?;
+
+        let version = Self::check_version(&user, if_match)?;
 
-        let res = self.user_repository.delete_by_id(id).await;
+        let res = if permanent {
+            // revoke first, the revocations must be in place by the time the row is gone
+            self.end_sessions(*id).await?;
+
+            info!("Permanently deleting user with id: {id}");
+            self.user_repository.purge_by_id_at_version(id, version).await
+        } else {
+            info!("Deleting user with id: {id}");
+            self.user_repository.delete_by_id_at_version(id, version).await
+        };
+        let res = res.map_err(|e| Self::map_error(e, Some(id)))?;
 
         match res {
-            0 => StatusCode::NOT_FOUND,
-            _ => StatusCode::OK,
+            0 => Err(Self::missed_write(*id, version)),
+            _ => {
+                if !permanent {
+                    self.end_sessions(*id).await?;
+                }
+                self.clear_failed_logins(&user).await;
+                Ok(())
+            }
         }
     }
+
+    /// Revokes the access and refresh tokens of the user, so that it loses access right away.
+    /// Fails with `UserError::ServiceUnavailable` if they can't be revoked.
+    async fn end_sessions(&self, id: i32) -> Result<(), UserError> {
+        // the store logs its own failures
+        self.revocation_store
+            .revoke_user(id)
+            .await
+            .map_err(|_| UserError::ServiceUnavailable)?;
+
+        match self.refresh_token_repository.revoke_all_for_user(id).await {
+            Ok(0) => Ok(()),
+            Ok(revoked) => {
+                info!("Revoked {revoked} refresh tokens of user {id}");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Unable to revoke the refresh tokens of user {id}: {e}");
+                Err(UserError::ServiceUnavailable)
+            }
+        }
+    }
+
+    /// Forgets the failed logins of a deleted user, so that a user later taking its name doesn't
+    /// inherit its lockout.
+    async fn clear_failed_logins(&self, user: &User) {
+        let Some(user_name) = &user.user_name else {
+            return;
+        };
+
+        if let Err(e) = self
+            .login_failure_repository
+            .clear(LoginScope::User, &user_subject(user_name))
+            .await
+        {
+            error!("Unable to clear failed logins of deleted user {user_name}: {e}");
+        }
+    }
+
+    async fn end_sessions_if_inactive(&self, user: &User) -> Result<(), UserError> {
+        if let Some(id) = user.id
+            && (user.deleted_at.is_some() || user.status.unwrap_or_default() != UserStatus::Active)
+        {
+            self.end_sessions(id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn bulk(&self, request: &BulkUserRequest) -> Result<BulkUserResponse, UserError> {
+        info!("Applying {} bulk user operations", request.operations.len());
+
+        let mut prepared = Vec::with_capacity(request.operations.len());
+
+        for operation in &request.operations {
+            prepared.push(Self::prepare(operation).await);
+        }
+
+        let targets = self.check_targets(&mut prepared).await?;
+
+        let mut results = match request.mode {
+            BulkMode::Transactional if prepared.iter().any(Result::is_err) => {
+                error!("Aborting bulk request with invalid operations");
+                abort(prepared, "another operation of the batch is invalid")
+            }
+            BulkMode::Transactional => match self.write_batch(&prepared).await {
+                Ok(results) => results,
+                Err(e @ (RepositoryError::Connection(_) | RepositoryError::Timeout)) => {
+                    return Err(Self::map_error(e, None));
+                }
+                Err(e) => {
+                    let reason = Self::map_error(e, None).to_string();
+                    abort(prepared, &format!("the batch was rolled back: {reason}"))
+                }
+            },
+            BulkMode::BestEffort => match self.write_batch(&prepared).await {
+                Ok(results) => results,
+                Err(e) => {
+                    warn!("Bulk write failed, applying operations one by one: {e}");
+                    let mut results = Vec::with_capacity(prepared.len());
+
+                    for (operation, item) in request.operations.iter().zip(prepared) {
+                        results.push(match item {
+                            Ok(item) => self.apply(item, operation).await,
+                            Err(e) => Err(e),
+                        });
+                    }
+
+                    results
+                }
+            },
+        };
+
+        for (operation, item) in request.operations.iter().zip(results.iter_mut()) {
+            let ended = match (operation, &*item) {
+                (BulkOperation::Delete { id }, Ok(_)) => {
+                    if let Some(user) = targets.get(id) {
+                        self.clear_failed_logins(user).await;
+                    }
+
+                    self.end_sessions(*id).await
+                }
+                (BulkOperation::Update { .. }, Ok((_, Some(user)))) => {
+                    self.end_sessions_if_inactive(&User::from_dto(user)).await
+                }
+                _ => Ok(()),
+            };
+
+            // the write went through, but the user would keep access
+            if let Err(e) = ended {
+                *item = Err(e);
+            }
+        }
+
+        Ok(bulk_response(request.mode, results))
+    }
+
+    /// Checks a bulk operation and turns it into the entity to write, hashing its password.
+    async fn prepare(operation: &BulkOperation) -> Result<Prepared, UserError> {
+        let (payload, operation) = match operation {
+            BulkOperation::Delete { id } => return Ok(Prepared::Delete(*id)),
+            BulkOperation::Create { user } => match user.id {
+                Some(id) => return Err(UserError::CannotCreateExistingUser(id)),
+                None => (user, Operation::Create),
+            },
+            BulkOperation::Update { user } => match user.id {
+                Some(_) => (user, Operation::Update),
+                None => return Err(UserError::MissingId),
+            },
+        };
+
+        let document = serde_json::to_value(payload)
+            .map_err(|e| UserError::FailedRequest(e.to_string()))?;
+        let errors = validate(&document, payload, operation);
+
+        if !errors.is_empty() {
+            return Err(UserError::ValidationFailed(errors));
+        }
+
+        let mut user = User::from_dto(payload);
+        user.version = payload.version;
+        user.password_hash = match payload.password.as_deref() {
+            Some(password) => Some(Self::hash(password).await?),
+            None => None,
+        };
+
+        Ok(match operation {
+            Operation::Create => Prepared::Create(user),
+            Operation::Update => Prepared::Update(user),
+        })
+    }
+
+    /// Fails operations on missing users, on updates of users no longer at the given version, on a
+    /// user already targeted by an earlier operation, or taking a user name already used earlier in
+    /// the batch. Returns the targeted users by id.
+    async fn check_targets(
+        &self,
+        prepared: &mut [Result<Prepared, UserError>],
+    ) -> Result<HashMap<i32, User>, UserError> {
+        let mut ids = HashSet::new();
+        let mut user_names = HashSet::new();
+
+        for item in prepared.iter_mut() {
+            let error = match item {
+                Ok(Prepared::Update(User { id: Some(id), .. }) | Prepared::Delete(id)) if !ids.insert(*id) => {
+                    UserError::InvalidField("id".to_string(), "is targeted by an earlier operation".to_string())
+                }
+                Ok(Prepared::Create(user) | Prepared::Update(user)) => match &user.user_name {
+                    Some(name) if !user_names.insert(name.to_lowercase()) => UserError::UserNameTaken(name.clone()),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            *item = Err(error);
+        }
+
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ids: Vec<i32> = ids.into_iter().collect();
+        let found: HashMap<i32, User> = self
+            .user_repository
+            .find_all_by_id(&ids)
+            .await
+            .map_err(|e| Self::map_error(e, None))?
+            .into_iter()
+            .filter_map(|user| Some((user.id?, user)))
+            .collect();
+
+        for item in prepared.iter_mut() {
+            let error = match item {
+                Ok(Prepared::Update(User { id: Some(id), .. }) | Prepared::Delete(id)) if !found.contains_key(id) => {
+                    UserError::NotFound(*id)
+                }
+                Ok(Prepared::Update(User { id: Some(id), version: Some(version), .. }))
+                    if found[id].version != Some(*version) =>
+                {
+                    UserError::VersionConflict(*id)
+                }
+                _ => continue,
+            };
+            *item = Err(error);
+        }
+
+        Ok(found)
+    }
+
+    /// Writes every prepared operation with a single batch, assigning the default role to the
+    /// created users and recording their audit entries in the same transaction. Fails as a whole if the batch is rolled back.
+    async fn write_batch(&self, prepared: &[Result<Prepared, UserError>]) -> Result<Vec<BulkItem>, RepositoryError> {
+        let mut batch = Batch {
+            creates: Vec::new(),
+            updates: Vec::new(),
+            deletes: Vec::new(),
+        };
+
+        for item in prepared.iter().flatten() {
+            match item {
+                Prepared::Create(user) => batch.creates.push(user.clone()),
+                Prepared::Update(user) => batch.updates.push(user.clone()),
+                Prepared::Delete(id) => batch.deletes.push(*id),
+            }
+        }
+
+        let work = self.unit_of_work.begin().await?;
+        let result = Self::write_batch_with(work.as_ref(), &batch).await;
+        let outcome = Self::finish(work, result).await?;
+        let mut created = outcome.created.into_iter();
+        let mut updated: HashMap<i32, User> = outcome
+            .updated
+            .into_iter()
+            .filter_map(|user| Some((user.id?, user)))
+            .collect();
+        let missing = || UserError::FailedRequest("User was not returned by the batch".to_string());
+
+        Ok(prepared
+            .iter()
+            .map(|item| match item {
+                Err(e) => Err(e.clone()),
+                Ok(Prepared::Create(_)) => created
+                    .next()
+                    .map(|user| (StatusCode::CREATED, Some(user.as_dto())))
+                    .ok_or_else(missing),
+                Ok(Prepared::Update(user)) => user
+                    .id
+                    .and_then(|id| updated.remove(&id))
+                    .map(|user| (StatusCode::OK, Some(user.as_dto())))
+                    .ok_or_else(missing),
+                Ok(Prepared::Delete(_)) => Ok((StatusCode::OK, None)),
+            })
+            .collect())
+    }
+
+    async fn write_batch_with(
+        work: &(dyn UnitOfWork + Send + Sync),
+        batch: &Batch<User, i32>,
+    ) -> RepositoryResult<BatchOutcome<User, i32>> {
+        let outcome = work.users().write_batch(batch).await?;
+        let created_ids: Vec<i32> = outcome.created.iter().filter_map(|user| user.id).collect();
+
+        if !created_ids.is_empty() {
+            let entries: Vec<AuditEntry> = outcome
+                .created
+                .iter()
+                .filter_map(|user| Some(Self::created_entry(user, user.id?)))
+                .collect();
+
+            work.roles()
+                .assign_role_to_all(&created_ids, Self::DEFAULT_ROLE)
+                .await?;
+            work.audit().record_all(&entries).await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Writes a single prepared operation, used to find out which operations of a failed
+    /// best-effort batch can still be applied.
+    async fn apply(&self, prepared: Prepared, operation: &BulkOperation) -> BulkItem {
+        let payload = match operation {
+            BulkOperation::Create { user } | BulkOperation::Update { user } => user.clone(),
+            BulkOperation::Delete { id } => UserDto {
+                id: Some(*id),
+                ..UserDto::default()
+            },
+        };
+
+        match prepared {
+            Prepared::Create(user) => self
+                .insert(&user, &payload)
+                .await
+                .map(|user| (StatusCode::CREATED, Some(user.as_dto()))),
+            Prepared::Update(user) => self
+                .user_repository
+                .update(&user)
+                .await
+                .map(|user| (StatusCode::OK, Some(user.as_dto())))
+                .map_err(|e| match e {
+                    RepositoryError::NotFound => {
+                        Self::missed_write(user.id.unwrap_or_default(), user.version)
+                    }
+                    e => Self::map_write_error(e, &payload),
+                }),
+            Prepared::Delete(id) => match self.user_repository.delete_by_id(&id).await {
+                Ok(0) => Err(UserError::NotFound(id)),
+                Ok(_) => Ok((StatusCode::OK, None)),
+                Err(e) => Err(Self::map_error(e, Some(&id))),
+            },
+        }
+    }
+
+    /// Reports a taken user name as `UserError::UserNameTaken` rather than a generic conflict.
+    fn map_write_error(error: RepositoryError, payload: &UserDto) -> UserError {
+        match error {
+            RepositoryError::UniqueViolation(constraint) if constraint == USER_NAME_KEY => {
+                let user_name = payload.user_name.clone().unwrap_or_default();
+                error!("Unable to save user, user name {user_name} is already taken");
+                UserError::UserNameTaken(user_name)
+            }
+            error => Self::map_error(error, payload.id.as_ref()),
+        }
+    }
+
+    fn map_error(error: RepositoryError, id: Option<&i32>) -> UserError {
+        error!("User repository request failed: {error}");
+
+        match error {
+            RepositoryError::NotFound => match id {
+                Some(id) => UserError::NotFound(*id),
+                None => UserError::FailedRequest(error.to_string()),
+            },
+            RepositoryError::UniqueViolation(constraint) => UserError::AlreadyExists(constraint),
+            RepositoryError::ForeignKeyViolation(constraint) => {
+                UserError::InvalidReference(constraint)
+            }
+            RepositoryError::Connection(_) | RepositoryError::Timeout => {
+                UserError::ServiceUnavailable
+            }
+            RepositoryError::Query(e) => UserError::FailedRequest(e),
+        }
+    }
+
+    async fn hash(password: &str) -> Result<String, UserError> {
+        hash_password_blocking(password).await.map_err(|e| {
+            error!("Failed to hash user password: {e}");
+            UserError::FailedRequest("Failed to hash password".to_string())
+        })
+    }
+}
+
+/// Operation of a bulk request that passed its checks.
+enum Prepared {
+    Create(User),
+    Update(User),
+    Delete(i32),
+}
+
+/// Status and user of a successful bulk operation, or the error it failed with.
+type BulkItem = Result<(StatusCode, Option<UserDto>), UserError>;
+
+/// Fails every operation, reporting `reason` for those that were valid.
+fn abort(prepared: Vec<Result<Prepared, UserError>>, reason: &str) -> Vec<BulkItem> {
+    prepared
+        .into_iter()
+        .map(|item| Err(item.err().unwrap_or_else(|| UserError::BatchAborted(reason.to_string()))))
+        .collect()
+}
+
+fn bulk_response(mode: BulkMode, items: Vec<BulkItem>) -> BulkUserResponse {
+    let results: Vec<_> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| match item {
+            Ok((status, user)) => BulkItemResult {
+                index,
+                status: status.as_u16(),
+                user,
+                error: None,
+            },
+            Err(e) => {
+                let (status, error) = e.to_api_err_response();
+                BulkItemResult {
+                    index,
+                    status: status.as_u16(),
+                    user: None,
+                    error: Some(error),
+                }
+            }
+        })
+        .collect();
+    let failed = results.iter().filter(|result| result.error.is_some()).count();
+
+    BulkUserResponse {
+        mode,
+        succeeded: results.len() - failed,
+        failed,
+        results,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::repository::repository_traits::{ReadRepository, Repository, WriteRepository};
+    use crate::model::auth::RefreshToken;
+    use crate::repository::repository_traits::{
+        BatchOutcome, BatchRepository, ReadRepository, Repository, SoftDeleteRepository,
+        WriteRepository,
+    };
+    use crate::repository::{
+        InMemoryLoginFailureStore, InMemoryRepository, InMemoryRevokedTokenStore,
+        InMemoryRoleRepository, InMemoryUnitOfWorkFactory, LoginFailureStore, RefreshTokenStore,
+        RevokedTokenStore, UserNameRepository, UserRoleRepository,
+    };
     use async_trait::async_trait;
     use chrono::NaiveDateTime;
+    use serde_json::json;
     use std::sync::Arc;
 
-    struct MockUserRepository;
+    const FOO: i32 = 1;
+    const TAKEN: i32 = 2;
+    const DELETED: i32 = 3;
 
-    #[async_trait]
-    impl ReadRepository<User, i32> for MockUserRepository {
-        async fn find_by_id(&self, id: &i32) -> Option<User> {
-            match id {
-                1 => Some(User::new("foo")),
-                _ => None,
+    /// In-memory repositories holding the users `foo`, `taken`, whose email is
+    /// `taken@example.com`, and the deleted user `deleted`.
+    struct Fixture {
+        users: InMemoryRepository<User, i32>,
+        roles: InMemoryRoleRepository,
+        audit: InMemoryRepository<AuditEntry, i32>,
+        refresh_tokens: InMemoryRepository<RefreshToken, i32>,
+        login_failures: Arc<InMemoryLoginFailureStore>,
+        revocation_store: RevocationStore,
+        metrics: Metrics,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let users = InMemoryRepository::<User, i32>::new();
+            let taken = User {
+                email: Some("taken@example.com".to_string()),
+                ..User::new("taken")
+            };
+
+            users.create(&User::new("foo")).await.unwrap();
+            users.create(&taken).await.unwrap();
+            users.create(&User::new("deleted")).await.unwrap();
+            users.delete_by_id(&DELETED).await.unwrap();
+
+            Self {
+                roles: InMemoryRoleRepository::new(&users),
+                users,
+                audit: InMemoryRepository::new(),
+                refresh_tokens: InMemoryRepository::new(),
+                login_failures: Arc::new(InMemoryLoginFailureStore::default()),
+                revocation_store: RevocationStore::new(Arc::new(
+                    InMemoryRevokedTokenStore::default(),
+                )),
+                metrics: Metrics::new(),
             }
         }
 
-        async fn find_all(&self) -> Vec<User> {
-            vec![User::new("foo"), User::new("bar"), User::new("baz")]
+        fn manager(&self) -> UserManager {
+            UserManager::new(
+                Arc::new(self.users.clone()),
+                Arc::new(self.users.clone()),
+                Arc::new(self.refresh_tokens.clone()),
+                self.login_failures.clone(),
+                Arc::new(InMemoryUnitOfWorkFactory::new(&self.users, &self.roles, &self.audit)),
+                self.revocation_store.clone(),
+                self.metrics.clone(),
+            )
         }
     }
 
+    async fn manager() -> UserManager {
+        Fixture::new().await.manager()
+    }
+
+    /// Fails every request like a database that can't be reached, which the in-memory repository
+    /// never does.
+    struct UnavailableRepository;
+
     #[async_trait]
-    impl WriteRepository<User, i32> for MockUserRepository {
-        async fn create(&self, entity: &User) -> Option<User> {
-            Some(User {
-                id: Some(1),
-                user_name: entity.user_name.clone(),
-                created_timestamp: Some(NaiveDateTime::default()),
-                updated_timestamp: Some(NaiveDateTime::default()),
-            })
+    impl ReadRepository<User, i32> for UnavailableRepository {
+        async fn find_by_id(&self, _: &i32) -> RepositoryResult<User> {
+            Err(RepositoryError::Timeout)
         }
 
-        async fn update(&self, entity: &User) -> Option<User> {
-            Some(User {
-                id: Some(1),
-                user_name: entity.user_name.clone(),
-                created_timestamp: Some(NaiveDateTime::default()),
-                updated_timestamp: Some(NaiveDateTime::default()),
-            })
+        async fn find_all(&self) -> RepositoryResult<Vec<User>> {
+            Err(RepositoryError::Timeout)
         }
 
-        async fn delete_by_id(&self, id: &i32) -> u64 {
-            match id {
-                1 => 1,
-                _ => 0,
-            }
+        async fn find_page(&self, _: &PageRequest) -> RepositoryResult<Page<User>> {
+            Err(RepositoryError::Timeout)
         }
     }
 
-    impl Repository<User, i32> for MockUserRepository {}
+    #[async_trait]
+    impl WriteRepository<User, i32> for UnavailableRepository {
+        async fn create(&self, _: &User) -> RepositoryResult<User> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn update(&self, _: &User) -> RepositoryResult<User> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn delete_by_id(&self, _: &i32) -> RepositoryResult<u64> {
+            Err(RepositoryError::Connection("connection refused".to_string()))
+        }
+    }
+
+    #[async_trait]
+    impl SoftDeleteRepository<User, i32> for UnavailableRepository {
+        async fn find_by_id_including_deleted(&self, _: &i32) -> RepositoryResult<User> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn find_all_including_deleted(&self) -> RepositoryResult<Vec<User>> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn purge_by_id(&self, _: &i32) -> RepositoryResult<u64> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn delete_by_id_at_version(&self, _: &i32, _: Option<i32>) -> RepositoryResult<u64> {
+            Err(RepositoryError::Connection("connection refused".to_string()))
+        }
+
+        async fn purge_by_id_at_version(&self, _: &i32, _: Option<i32>) -> RepositoryResult<u64> {
+            Err(RepositoryError::Timeout)
+        }
+    }
+
+    #[async_trait]
+    impl BatchRepository<User, i32> for UnavailableRepository {
+        async fn find_all_by_id(&self, _: &[i32]) -> RepositoryResult<Vec<User>> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn write_batch(&self, _: &Batch<User, i32>) -> RepositoryResult<BatchOutcome<User, i32>> {
+            Err(RepositoryError::Timeout)
+        }
+    }
+
+    impl Repository<User, i32> for UnavailableRepository {}
+
+    #[async_trait]
+    impl UserNameRepository for UnavailableRepository {
+        async fn find_by_user_name(&self, _: &str) -> RepositoryResult<User> {
+            Err(RepositoryError::Timeout)
+        }
+    }
+
+    async fn unavailable_manager() -> UserManager {
+        let fixture = Fixture::new().await;
+
+        UserManager::new(
+            Arc::new(UnavailableRepository),
+            Arc::new(UnavailableRepository),
+            Arc::new(fixture.refresh_tokens.clone()),
+            fixture.login_failures.clone(),
+            Arc::new(InMemoryUnitOfWorkFactory::new(&fixture.users, &fixture.roles, &fixture.audit)),
+            fixture.revocation_store.clone(),
+            fixture.metrics.clone(),
+        )
+    }
 
     #[tokio::test]
     async fn test_create_user() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
+        let manager = manager().await;
         let user = UserDto {
             id: None,
-            user_name: Some("foo".to_string()),
+            user_name: Some("bar".to_string()),
+            password: Some("bar".to_string()),
+            ..UserDto::default()
         };
         let res = manager.create_user(&user).await;
 
@@ -186,16 +1081,19 @@ mod tests {
 
         let user = res.ok().unwrap();
 
-        assert_eq!(user.id, Some(1));
-        assert_eq!(user.user_name, Some("foo".to_string()));
+        assert_eq!(user.id, Some(4));
+        assert_eq!(user.user_name, Some("bar".to_string()));
+        assert!(user.password.is_none());
     }
 
     #[tokio::test]
     async fn test_create_user_with_existing_id() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
+        let manager = manager().await;
         let user = UserDto {
             id: Some(1),
             user_name: None,
+            password: None,
+            ..UserDto::default()
         };
         let res = manager.create_user(&user).await;
 
@@ -203,10 +1101,25 @@ mod tests {
         assert_eq!(res.err(), Some(UserError::CannotCreateExistingUser(1)))
     }
 
+    #[tokio::test]
+    async fn test_create_user_without_password() {
+        let manager = manager().await;
+        let user = UserDto {
+            id: None,
+            user_name: Some("foo".to_string()),
+            password: None,
+            ..UserDto::default()
+        };
+        let res = manager.create_user(&user).await;
+
+        assert!(res.is_err());
+        assert_eq!(res.err(), Some(UserError::MissingPassword))
+    }
+
     #[tokio::test]
     async fn get_user_by_id() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
-        let res = manager.get_user(&1).await;
+        let manager = manager().await;
+        let res = manager.get_user(&1, false).await;
 
         assert!(res.is_ok());
         assert_eq!(res.ok().unwrap().user_name, Some("foo".to_string()));
@@ -214,8 +1127,8 @@ mod tests {
 
     #[tokio::test]
     async fn get_user_by_id_not_found() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
-        let res = manager.get_user(&123).await;
+        let manager = manager().await;
+        let res = manager.get_user(&123, false).await;
 
         assert!(res.is_err());
         assert_eq!(res.err(), Some(UserError::NotFound(123)));
@@ -223,19 +1136,34 @@ mod tests {
 
     #[tokio::test]
     async fn get_all_users() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
-        let res = manager.get_users().await;
-        let users: Vec<String> = res.iter().map(|u| u.user_name.clone().unwrap()).collect();
+        let manager = manager().await;
+        let res = manager.get_users(&PageQuery::default()).await.unwrap();
+        let users: Vec<String> = res.items.iter().map(|u| u.user_name.clone().unwrap()).collect();
+
+        assert_eq!(users, vec!["foo", "taken"]);
+        assert_eq!(res.total, None);
+    }
+
+    #[tokio::test]
+    async fn get_users_with_invalid_limit() {
+        let manager = manager().await;
+        let query = PageQuery {
+            limit: Some(1000),
+            ..PageQuery::default()
+        };
+        let res = manager.get_users(&query).await;
 
-        assert_eq!(users, vec!["foo", "bar", "baz"]);
+        assert!(matches!(res, Err(UserError::InvalidPageRequest(_))));
     }
 
     #[tokio::test]
     async fn test_update_user() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
+        let manager = manager().await;
         let user = UserDto {
             id: Some(1),
             user_name: Some("foo".to_string()),
+            password: None,
+            ..UserDto::default()
         };
         let res = manager.update_user(&user).await;
 
@@ -249,10 +1177,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_update_user_with_missing_id() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
+        let manager = manager().await;
         let user = UserDto {
             id: None,
             user_name: None,
+            password: None,
+            ..UserDto::default()
         };
         let res = manager.update_user(&user).await;
 
@@ -260,19 +1190,446 @@ mod tests {
         assert_eq!(res.err(), Some(UserError::MissingId))
     }
 
+    #[tokio::test]
+    async fn test_update_user_with_empty_password() {
+        let manager = manager().await;
+        let user = UserDto {
+            id: Some(1),
+            user_name: Some("foo".to_string()),
+            password: Some(String::new()),
+            ..UserDto::default()
+        };
+        let res = manager.update_user(&user).await;
+
+        assert_eq!(res.err(), Some(UserError::MissingPassword))
+    }
+
     #[tokio::test]
     async fn test_delete_user() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
-        let res = manager.delete_user(&1).await;
+        let manager = manager().await;
+        let res = manager.delete_user(&1, false, None).await;
+
+        assert_eq!(res, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_revokes_refresh_tokens() {
+        let fixture = Fixture::new().await;
+        let expires = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+        let token = RefreshToken::new(FOO, uuid::Uuid::new_v4(), "hash".to_string(), expires);
+
+        RefreshTokenStore::create(&fixture.refresh_tokens, &token).await.unwrap();
+
+        assert_eq!(fixture.manager().delete_user(&FOO, false, None).await, Ok(()));
+        assert!(fixture.refresh_tokens.consume("hash").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_clears_failed_logins() {
+        let fixture = Fixture::new().await;
+        let login_failures = &fixture.login_failures;
+        let now = chrono::Utc::now().naive_utc();
+
+        login_failures.record_failure(LoginScope::User, "foo", now, now).await.unwrap();
+
+        assert_eq!(fixture.manager().delete_user(&FOO, false, None).await, Ok(()));
+        assert!(login_failures.find(LoginScope::User, "foo").await.unwrap().is_none());
+    }
+
+    /// Fails every revocation like a revocation table that can't be reached.
+    struct UnavailableRevokedTokenStore;
+
+    #[async_trait]
+    impl RevokedTokenStore for UnavailableRevokedTokenStore {
+        async fn revoke(&self, _: &uuid::Uuid, _: i32, _: NaiveDateTime) -> RepositoryResult<u64> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn revoke_user_sessions(&self, _: i32, _: NaiveDateTime) -> RepositoryResult<u64> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn is_revoked(&self, _: &uuid::Uuid, _: i32, _: NaiveDateTime) -> RepositoryResult<bool> {
+            Err(RepositoryError::Timeout)
+        }
+
+        async fn purge_expired(&self) -> RepositoryResult<u64> {
+            Err(RepositoryError::Timeout)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_revocation_store_down() {
+        let mut fixture = Fixture::new().await;
+        fixture.revocation_store = RevocationStore::new(Arc::new(UnavailableRevokedTokenStore));
+        let manager = fixture.manager();
+        let suspend = PatchDocument::Merge(json!({"status": "suspended"}));
+
+        let unavailable = Some(UserError::ServiceUnavailable);
 
-        assert_eq!(res, StatusCode::OK);
+        assert_eq!(manager.delete_user(&FOO, false, None).await.err(), unavailable);
+        assert_eq!(manager.delete_user(&DELETED, true, None).await.err(), unavailable);
+        assert_eq!(manager.patch_user(&TAKEN, &suspend, None).await.err(), unavailable);
     }
 
     #[tokio::test]
     async fn test_delete_missing_user() {
-        let manager = UserManager::new(Arc::new(MockUserRepository));
-        let res = manager.delete_user(&123).await;
+        let manager = manager().await;
+        let res = manager.delete_user(&123, false, None).await;
+
+        assert_eq!(res, Err(UserError::NotFound(123)));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_database_down() {
+        let manager = unavailable_manager().await;
+        let res = manager.delete_user(&FOO, false, None).await;
+
+        assert_eq!(res, Err(UserError::ServiceUnavailable));
+    }
+
+    #[tokio::test]
+    async fn get_user_by_id_timeout() {
+        let manager = unavailable_manager().await;
+        let res = manager.get_user(&FOO, false).await;
+
+        assert_eq!(res.err(), Some(UserError::ServiceUnavailable));
+    }
+
+    #[tokio::test]
+    async fn test_create_duplicate_user() {
+        let manager = manager().await;
+        let user = UserDto {
+            id: None,
+            user_name: Some("taken".to_string()),
+            password: Some("bar".to_string()),
+            ..UserDto::default()
+        };
+        let res = manager.create_user(&user).await;
+
+        assert_eq!(res.err(), Some(UserError::UserNameTaken("taken".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_create_user_with_taken_email() {
+        let manager = manager().await;
+        let user = UserDto {
+            user_name: Some("bar".to_string()),
+            password: Some("bar".to_string()),
+            email: Some("taken@example.com".to_string()),
+            ..UserDto::default()
+        };
+        let res = manager.create_user(&user).await;
+
+        assert!(matches!(res, Err(UserError::AlreadyExists(_))));
+    }
+
+    #[tokio::test]
+    async fn test_user_name_availability() {
+        let manager = manager().await;
+
+        assert!(!manager.get_user_name_availability("taken").await.unwrap().available);
+        assert!(manager.get_user_name_availability("free").await.unwrap().available);
+        assert_eq!(
+            unavailable_manager().await.get_user_name_availability("free").await.err(),
+            Some(UserError::ServiceUnavailable)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_missing_user() {
+        let manager = manager().await;
+        let user = UserDto {
+            id: Some(123),
+            user_name: Some("foo".to_string()),
+            password: None,
+            ..UserDto::default()
+        };
+        let res = manager.update_user(&user).await;
+
+        assert_eq!(res.err(), Some(UserError::NotFound(123)));
+    }
+
+    #[tokio::test]
+    async fn test_operations_are_counted() {
+        let fixture = Fixture::new().await;
+        let manager = fixture.manager();
+
+        let _ = manager.get_user(&FOO, false).await;
+        let _ = manager.get_user(&123, false).await;
+
+        let text = fixture.metrics.render(None);
+
+        assert!(text.contains(r#"user_operations_total{operation="get",outcome="ok"} 1"#));
+        assert!(text.contains(r#"user_operations_total{operation="get",outcome="NotFound"} 1"#));
+    }
+
+    #[tokio::test]
+    async fn test_create_user_with_profile() {
+        let manager = manager().await;
+        let user = UserDto {
+            user_name: Some("bar".to_string()),
+            password: Some("bar".to_string()),
+            email: Some("bar@example.com".to_string()),
+            display_name: Some("Bar".to_string()),
+            ..UserDto::default()
+        };
+        let user = manager.create_user(&user).await.ok().unwrap();
+
+        assert_eq!(user.email.as_deref(), Some("bar@example.com"));
+        assert_eq!(user.display_name.as_deref(), Some("Bar"));
+        assert_eq!(user.status, Some(UserStatus::Active));
+    }
+
+    #[tokio::test]
+    async fn get_deleted_user() {
+        let manager = manager().await;
+
+        assert_eq!(manager.get_user(&DELETED, false).await.err(), Some(UserError::NotFound(DELETED)));
+
+        let user = manager.get_user(&DELETED, true).await.ok().unwrap();
+
+        assert_eq!(user.status, Some(UserStatus::Deleted));
+        assert!(user.deleted_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_permanently_delete_user() {
+        let manager = manager().await;
+
+        assert_eq!(manager.delete_user(&DELETED, true, None).await, Ok(()));
+        assert_eq!(manager.delete_user(&DELETED, false, None).await, Err(UserError::NotFound(DELETED)));
+    }
+
+    #[tokio::test]
+    async fn test_replace_user() {
+        let manager = manager().await;
+        let user = UserDto {
+            user_name: Some("bar".to_string()),
+            ..UserDto::default()
+        };
+
+        assert_eq!(manager.replace_user(&1, &user, None).await.unwrap().id, Some(1));
+
+        let user = UserDto {
+            id: Some(TAKEN),
+            ..user
+        };
+        let res = manager.replace_user(&FOO, &user, None).await;
+
+        assert!(matches!(res, Err(UserError::InvalidField(field, _)) if field == "id"));
+    }
+
+    #[tokio::test]
+    async fn test_patch_user() {
+        let manager = manager().await;
+        let patch = PatchDocument::Merge(json!({"display_name": "Foo"}));
+        let user = manager.patch_user(&1, &patch, None).await.unwrap();
+
+        assert_eq!(user.user_name.as_deref(), Some("foo"));
+        assert_eq!(user.display_name.as_deref(), Some("Foo"));
+
+        let patch = PatchDocument::Merge(json!({"user_name": null}));
+        let res = manager.patch_user(&1, &patch, None).await;
+
+        assert!(matches!(res, Err(UserError::ValidationFailed(errors)) if errors[0].field == "user_name"));
+
+        let patch = PatchDocument::Merge(json!({"id": 2}));
+
+        assert!(matches!(manager.patch_user(&1, &patch, None).await, Err(UserError::InvalidField(_, _))));
+
+        let patch = PatchDocument::Merge(json!({"user_name": 1}));
+
+        assert!(matches!(manager.patch_user(&1, &patch, None).await, Err(UserError::InvalidPatch(_))));
+        assert_eq!(manager.patch_user(&123, &patch, None).await.err(), Some(UserError::NotFound(123)));
+    }
+
+    #[tokio::test]
+    async fn test_update_with_if_match() {
+        let manager = manager().await;
+        let user = UserDto {
+            user_name: Some("bar".to_string()),
+            ..UserDto::default()
+        };
+        let stale = EntityTags::Versions(vec![1]);
+
+        assert_eq!(manager.replace_user(&FOO, &user, Some(&stale)).await.unwrap().version, Some(2));
+        assert_eq!(manager.replace_user(&FOO, &user, Some(&EntityTags::Any)).await.unwrap().version, Some(3));
+        assert_eq!(
+            manager.replace_user(&FOO, &user, Some(&stale)).await.err(),
+            Some(UserError::VersionConflict(FOO))
+        );
+
+        let patch = PatchDocument::Merge(json!({"display_name": "Foo"}));
+
+        assert!(manager.patch_user(&FOO, &patch, Some(&EntityTags::Versions(vec![3]))).await.is_ok());
+        assert_eq!(
+            manager.patch_user(&FOO, &patch, Some(&stale)).await.err(),
+            Some(UserError::VersionConflict(FOO))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_if_match() {
+        let manager = manager().await;
+
+        assert_eq!(
+            manager.delete_user(&1, false, Some(&EntityTags::Versions(vec![2]))).await,
+            Err(UserError::VersionConflict(1))
+        );
+        assert_eq!(manager.delete_user(&1, false, Some(&EntityTags::Versions(vec![1]))).await, Ok(()));
+        assert_eq!(
+            manager.delete_user(&123, false, Some(&EntityTags::Any)).await,
+            Err(UserError::NotFound(123))
+        );
+    }
+
+    fn bulk(mode: BulkMode, operations: Vec<BulkOperation>) -> BulkUserRequest {
+        BulkUserRequest { mode, operations }
+    }
+
+    fn create_op(user_name: &str) -> BulkOperation {
+        BulkOperation::Create {
+            user: UserDto {
+                user_name: Some(user_name.to_string()),
+                password: Some("password".to_string()),
+                ..UserDto::default()
+            },
+        }
+    }
+
+    fn codes(response: &BulkUserResponse) -> Vec<String> {
+        response
+            .results
+            .iter()
+            .map(|result| result.error.as_ref().map_or_else(|| result.status.to_string(), |e| e.code.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_bulk_transactional() {
+        let manager = manager().await;
+        let update = BulkOperation::Update {
+            user: UserDto {
+                id: Some(1),
+                user_name: Some("bar".to_string()),
+                ..UserDto::default()
+            },
+        };
+        let request = bulk(BulkMode::Transactional, vec![create_op("a"), create_op("b"), update]);
+        let response = manager.bulk_users(&request).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["201", "201", "200"]);
+        assert_eq!(response.results[1].user.as_ref().unwrap().id, Some(5));
+        assert_eq!(response.succeeded, 3);
+
+        let request = bulk(BulkMode::Transactional, vec![create_op("a"), create_op("A"), BulkOperation::Delete { id: 123 }]);
+        let response = manager.bulk_users(&request).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["BatchAborted", "UserNameTaken", "NotFound"]);
+        assert_eq!(response.failed, 3);
+
+        let request = bulk(BulkMode::Transactional, vec![create_op("c"), create_op("taken")]);
+        let response = manager.bulk_users(&request).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["BatchAborted", "BatchAborted"]);
+        assert_eq!(response.results[0].status, 424);
+        assert!(manager.get_user_name_availability("c").await.unwrap().available);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_best_effort() {
+        let manager = manager().await;
+        let without_password = BulkOperation::Create {
+            user: UserDto {
+                user_name: Some("c".to_string()),
+                ..UserDto::default()
+            },
+        };
+        let operations = vec![
+            create_op("a"),
+            without_password,
+            BulkOperation::Delete { id: 1 },
+            BulkOperation::Delete { id: 1 },
+        ];
+        let response = manager.bulk_users(&bulk(BulkMode::BestEffort, operations)).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["201", "ValidationFailed", "200", "InvalidField"]);
+        assert_eq!(response.results[1].error.as_ref().unwrap().errors[0].field, "password");
+
+        // the batch fails as a whole, so the operations are retried one by one
+        let operations = vec![create_op("taken"), create_op("b")];
+        let response = manager.bulk_users(&bulk(BulkMode::BestEffort, operations)).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["UserNameTaken", "201"]);
+        assert_eq!(response.succeeded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update_checks_version() {
+        let manager = manager().await;
+        let update = |version| BulkOperation::Update {
+            user: UserDto {
+                id: Some(1),
+                user_name: Some("bar".to_string()),
+                version: Some(version),
+                ..UserDto::default()
+            },
+        };
+        let response = manager.bulk_users(&bulk(BulkMode::BestEffort, vec![update(2)])).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["VersionConflict"]);
+        assert_eq!(response.results[0].status, 412);
+
+        // operations retried one by one keep their version
+        let operations = vec![create_op("taken"), update(1)];
+        let response = manager.bulk_users(&bulk(BulkMode::BestEffort, operations)).await.unwrap();
+
+        assert_eq!(codes(&response), vec!["UserNameTaken", "200"]);
+        assert_eq!(response.results[1].user.as_ref().unwrap().version, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_creates_are_audited() {
+        let fixture = Fixture::new().await;
+        let request = bulk(BulkMode::Transactional, vec![create_op("a"), create_op("b")]);
+
+        fixture.manager().bulk_users(&request).await.unwrap();
+
+        for subject in ["a", "b"] {
+            let entries = fixture.audit.find_where(|entry| entry.subject == subject);
+
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].action, "user_created");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_is_all_or_nothing() {
+        let fixture = Fixture::new().await;
+        let manager = fixture.manager();
+        let user = UserDto {
+            user_name: Some("bar".to_string()),
+            password: Some("password".to_string()),
+            ..UserDto::default()
+        };
+        let id = manager.create_user(&user).await.unwrap().id.unwrap();
+
+        assert_eq!(fixture.roles.find_roles_by_user_id(&id).await, Ok(vec!["user".to_string()]));
+        assert_eq!(fixture.audit.find_where(|entry| entry.subject == "bar").len(), 1);
+
+        let taken = UserDto {
+            user_name: Some("taken".to_string()),
+            password: Some("password".to_string()),
+            ..UserDto::default()
+        };
+
+        assert!(manager.create_user(&taken).await.is_err());
+        assert!(fixture.audit.find_where(|entry| entry.subject == "taken").is_empty());
+
+        let request = bulk(BulkMode::Transactional, vec![create_op("a"), create_op("taken")]);
+        manager.bulk_users(&request).await.unwrap();
 
-        assert_eq!(res, StatusCode::NOT_FOUND);
+        assert_eq!(fixture.users.find_by_user_name("a").await.err(), Some(RepositoryError::NotFound));
     }
 }
@@ -0,0 +1,196 @@
+use crate::middleware::decode_claims;
+use crate::model::auth::JwtClaims;
+use crate::model::auth_error::AuthError;
+use crate::model::user::User;
+use crate::repository::repository_traits::ArcRepository;
+use crate::repository::RepositoryError;
+use crate::state::AppState;
+use axum::extract::{FromRef, FromRequestParts, OptionalFromRequestParts};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+/// The caller of a request, taken from the claims set by `auth_layer` or decoded from the
+/// `Authorization` header when the route sits outside of it. Use `Option<AuthUser>` for routes
+/// where logging in is optional.
+#[derive(Clone)]
+pub struct AuthUser {
+    pub claims: JwtClaims,
+    user_repository: ArcRepository<User, i32>,
+    user: Arc<OnceCell<User>>,
+}
+
+impl AuthUser {
+    pub fn user_id(&self) -> Result<i32, AuthError> {
+        self.claims.user_id().ok_or(AuthError::InvalidToken)
+    }
+
+    /// Loads the user the token was issued to, reusing the result on later calls.
+    pub async fn user(&self) -> Result<&User, AuthError> {
+        let id = self.user_id()?;
+
+        self.user
+            .get_or_try_init(|| async {
+                self.user_repository
+                    .find_by_id(&id)
+                    .await
+                    .map_err(|e| match e {
+                        RepositoryError::NotFound => AuthError::InvalidToken,
+                        _ => AuthError::ServiceUnavailable,
+                    })
+            })
+            .await
+    }
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        let claims = match parts.extensions.get::<JwtClaims>() {
+            Some(claims) => claims.clone(),
+            None => {
+                let claims =
+                    decode_claims(&parts.headers, &state.keys, &state.revocation_store).await?;
+                parts.extensions.insert(claims.clone());
+                claims
+            }
+        };
+
+        Ok(Self {
+            claims,
+            user_repository: state.users_api.user_repository,
+            user: Arc::new(OnceCell::new()),
+        })
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        if parts.extensions.get::<JwtClaims>().is_none() && !parts.headers.contains_key(AUTHORIZATION) {
+            return Ok(None);
+        }
+
+        <Self as FromRequestParts<S>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::user::User;
+    use crate::repository::repository_traits::WriteRepository;
+    use crate::repository::UserRepository;
+    use crate::config::authentication::Keys;
+    use crate::config::settings::Settings;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use tower::util::ServiceExt;
+
+    async fn required(auth_user: AuthUser) -> Result<String, AuthError> {
+        Ok(auth_user.user().await?.user_name.clone().unwrap_or_default())
+    }
+
+    async fn optional(auth_user: Option<AuthUser>) -> String {
+        auth_user.map_or("anonymous".to_string(), |u| u.claims.sub)
+    }
+
+    async fn app(pool: PgPool) -> (Router, String) {
+        let user = UserRepository::new(&pool)
+            .create(&User::new("foo"))
+            .await
+            .unwrap();
+        let state = AppState::new(Settings::test(), pool, Keys::from_secret(b"secret")).await;
+        let token = state
+            .auth_service
+            .generate_tokens(user.id.unwrap())
+            .await
+            .unwrap()
+            .access_token;
+        let app = Router::new()
+            .route("/required", get(required))
+            .route("/optional", get(optional))
+            .with_state(state);
+
+        (app, token)
+    }
+
+    async fn get_with_token(app: &Router, uri: &str, token: Option<&str>) -> (StatusCode, String) {
+        let mut req = Request::get(uri);
+
+        if let Some(token) = token {
+            req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        let res = app.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn test_required_auth_user(pool: PgPool) {
+        let (app, token) = app(pool).await;
+        let (status, body) = get_with_token(&app, "/required", Some(&token)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "foo");
+    }
+
+    #[sqlx::test]
+    async fn test_required_auth_user_missing_token(pool: PgPool) {
+        let (app, _) = app(pool).await;
+        let (status, body) = get_with_token(&app, "/required", None).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body.contains("MissingToken"));
+    }
+
+    #[sqlx::test]
+    async fn test_required_auth_user_invalid_token(pool: PgPool) {
+        let (app, _) = app(pool).await;
+        let (status, _) = get_with_token(&app, "/required", Some("foo")).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_optional_auth_user(pool: PgPool) {
+        let (app, token) = app(pool).await;
+        let (status, body) = get_with_token(&app, "/optional", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "anonymous");
+
+        let (status, body) = get_with_token(&app, "/optional", Some(&token)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(body, "anonymous");
+
+        let (status, _) = get_with_token(&app, "/optional", Some("foo")).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::settings::Settings;
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap, HeaderName};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// IP address of the client, absent when the app is served without connect info, as in tests.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(client_ip(&parts.extensions)))
+    }
+}
+
+/// Resolves the client IP of the request once, taking `X-Forwarded-For` into account when the
+/// peer is one of the trusted proxies, and stores it for `client_ip`.
+pub async fn client_ip_layer(
+    State(settings): State<Arc<Settings>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let client = peer.map(|peer| {
+        resolve_client_ip(peer, request.headers(), &settings.server.trusted_proxies)
+    });
+
+    request.extensions_mut().insert(ClientIp(client));
+    next.run(request).await
+}
+
+pub fn client_ip(extensions: &Extensions) -> Option<IpAddr> {
+    match extensions.get::<ClientIp>() {
+        Some(ClientIp(ip)) => *ip,
+        None => extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip()),
+    }
+}
+
+/// Client of a request received from `peer`. Proxies append the address they received the request
+/// from to `X-Forwarded-For`, so the client is the last address that isn't a trusted proxy. Earlier
+/// addresses are set by the client itself and can't be trusted.
+fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let forwarded: Vec<&str> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    let mut client = peer;
+
+    for hop in forwarded.iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_untrusted_peer() {
+        let headers = forwarded_for("1.1.1.1");
+
+        assert_eq!(resolve_client_ip(ip("2.2.2.2"), &headers, &[]), ip("2.2.2.2"));
+        assert_eq!(resolve_client_ip(ip("2.2.2.2"), &headers, &[ip("10.0.0.1")]), ip("2.2.2.2"));
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let headers = forwarded_for("6.6.6.6, 1.1.1.1, 10.0.0.2");
+
+        assert_eq!(resolve_client_ip(ip("10.0.0.1"), &headers, &proxies), ip("1.1.1.1"));
+        assert_eq!(resolve_client_ip(ip("10.0.0.1"), &HeaderMap::new(), &proxies), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_malformed_forwarded_for() {
+        let headers = forwarded_for("1.1.1.1, unknown");
+
+        assert_eq!(resolve_client_ip(ip("10.0.0.1"), &headers, &[ip("10.0.0.1")]), ip("10.0.0.1"));
+    }
+}
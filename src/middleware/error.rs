@@ -0,0 +1,308 @@
+use crate::middleware::request_id;
+use crate::model::api_response::{ApiError, PROBLEM_JSON};
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+const MAX_REJECTION_BODY: usize = 64 * 1024;
+
+/// Brings every error response into the `ApiError` shape, tagged with the request id. Errors raised
+/// by our own handlers are taken from the response extensions, while plain text rejections produced
+/// by axum (malformed JSON, bad path parameters, unsupported methods...) are converted from their
+/// body. Clients that accept `application/problem+json` receive RFC 7807 problem details instead.
+pub async fn error_layer(request: Request, next: Next) -> Response {
+    let wants_problem = accepts_problem_json(request.headers());
+    let instance = request.uri().path().to_string();
+    let request_id = request_id(request.headers()).map(str::to_string);
+    let response = next.run(request).await;
+    let status = response.status();
+
+    if !status.is_client_error() && !status.is_server_error() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let mut error = match parts.extensions.get::<ApiError>() {
+        Some(error) => error.clone(),
+        None if is_json(&parts.headers) => return Response::from_parts(parts, body),
+        None => rejection_to_api_error(status, body).await,
+    };
+    error.request_id = request_id;
+
+    let rendered = if wants_problem {
+        error.to_problem_details(status, Some(instance)).into_response()
+    } else {
+        (status, error).into_response()
+    };
+    let (rendered_parts, body) = rendered.into_parts();
+
+    // keep headers set by the handler, such as `WWW-Authenticate`, and swap in the new body
+    parts.headers.remove(CONTENT_LENGTH);
+    if let Some(content_type) = rendered_parts.headers.get(CONTENT_TYPE) {
+        parts.headers.insert(CONTENT_TYPE, content_type.clone());
+    }
+    parts.extensions.extend(rendered_parts.extensions);
+
+    Response::from_parts(parts, body)
+}
+
+pub async fn route_not_found(uri: Uri) -> (StatusCode, ApiError) {
+    (
+        StatusCode::NOT_FOUND,
+        ApiError {
+            code: "RouteNotFound".to_string(),
+            message: format!("No route found for {}", uri.path()),
+            request_id: None,
+            errors: Vec::new(),
+        },
+    )
+}
+
+async fn rejection_to_api_error(status: StatusCode, body: Body) -> ApiError {
+    let body = to_bytes(body, MAX_REJECTION_BODY)
+        .await
+        .unwrap_or_default();
+
+    ApiError::from_status(status, &String::from_utf8_lossy(&body))
+}
+
+/// Whether the client weighs `application/problem+json` at least as high as plain JSON. Problem
+/// details are opt-in, so wildcards only count towards plain JSON.
+fn accepts_problem_json(headers: &HeaderMap) -> bool {
+    let ranges: Vec<(String, f32)> = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(media_range)
+        .collect();
+    // the most specific range listed for a media type gives its weight
+    let weight = |candidates: &[&str]| {
+        candidates
+            .iter()
+            .find_map(|candidate| ranges.iter().find(|(range, _)| range == candidate))
+            .map_or(0.0, |(_, q)| *q)
+    };
+    let problem = weight(&[PROBLEM_JSON]);
+
+    problem > 0.0 && problem >= weight(&["application/json", "application/*", "*/*"])
+}
+
+/// Splits a media range of an `Accept` header into its type and its `q` weight.
+fn media_range(range: &str) -> (String, f32) {
+    let mut parts = range.split(';');
+    let media_type = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+    let q = parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .map_or(1.0, |(_, value)| value.trim().parse().unwrap_or(0.0));
+
+    (media_type, q)
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json") || value.starts_with(PROBLEM_JSON))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::X_REQUEST_ID;
+    use crate::model::api_response::ProblemDetails;
+    use axum::extract::Path;
+    use axum::http::Request;
+    use axum::routing::{get, post};
+    use axum::{middleware, Json, Router};
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::util::ServiceExt;
+
+    async fn echo(Json(payload): Json<Value>) -> Json<Value> {
+        Json(payload)
+    }
+
+    async fn by_id(Path(id): Path<i32>) -> String {
+        id.to_string()
+    }
+
+    async fn failing() -> (StatusCode, ApiError) {
+        (
+            StatusCode::CONFLICT,
+            ApiError {
+                code: "Conflict".to_string(),
+                message: "Already exists".to_string(),
+                request_id: None,
+                errors: Vec::new(),
+            },
+        )
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/echo", post(echo))
+            .route("/item/{id}", get(by_id))
+            .route("/failing", get(failing))
+            .fallback(route_not_found)
+            .layer(middleware::from_fn(error_layer))
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, Option<String>, Vec<u8>) {
+        let res = app().oneshot(request).await.unwrap();
+        let status = res.status();
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, content_type, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json() {
+        let (status, content_type, body) = send(
+            Request::post("/echo")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from("{"))
+                .unwrap(),
+        )
+        .await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(error.code, "BadRequest");
+    }
+
+    #[tokio::test]
+    async fn test_missing_content_type() {
+        let (status, _, body) = send(Request::post("/echo").body(Body::from("{}")).unwrap()).await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(error.code, "UnsupportedMediaType");
+    }
+
+    #[tokio::test]
+    async fn test_bad_path_parameter_as_problem() {
+        let (status, content_type, body) = send(
+            Request::get("/item/abc")
+                .header(ACCEPT, "application/problem+json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some(PROBLEM_JSON));
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.code, "BadRequest");
+        assert_eq!(problem.instance.as_deref(), Some("/item/abc"));
+    }
+
+    #[tokio::test]
+    async fn test_api_error_as_problem() {
+        let (status, content_type, body) = send(
+            Request::get("/failing")
+                .header(ACCEPT, "application/json;q=0.9, application/problem+json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(content_type.as_deref(), Some(PROBLEM_JSON));
+        assert_eq!(problem.title, "Conflict");
+        assert_eq!(problem.detail, "Already exists");
+        assert_eq!(problem.code, "Conflict");
+    }
+
+    #[tokio::test]
+    async fn test_api_error_prefers_weighted_json() {
+        let (_, content_type, body) = send(
+            Request::get("/failing")
+                .header(ACCEPT, "application/json;q=1, application/problem+json;q=0.9")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(error.code, "Conflict");
+    }
+
+    #[tokio::test]
+    async fn test_api_error_ignores_wildcards() {
+        let (_, content_type, _) = send(
+            Request::get("/failing")
+                .header(ACCEPT, "*/*")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_api_error_unchanged_by_default() {
+        let (status, _, body) = send(Request::get("/failing").body(Body::empty()).unwrap()).await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(error.message, "Already exists");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_error() {
+        let (_, _, body) = send(
+            Request::get("/failing")
+                .header(X_REQUEST_ID, "abc-123")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(error.request_id.as_deref(), Some("abc-123"));
+
+        let (_, _, body) = send(
+            Request::get("/item/abc")
+                .header(X_REQUEST_ID, "abc-456")
+                .header(ACCEPT, PROBLEM_JSON)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(problem.request_id.as_deref(), Some("abc-456"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route() {
+        let (status, _, body) = send(Request::get("/missing").body(Body::empty()).unwrap()).await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error.code, "RouteNotFound");
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed() {
+        let (status, _, body) = send(Request::delete("/echo").body(Body::empty()).unwrap()).await;
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(error.code, "MethodNotAllowed");
+    }
+}
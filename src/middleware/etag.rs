@@ -0,0 +1,44 @@
+use crate::model::etag::EntityTags;
+use axum::extract::FromRequestParts;
+use axum::http::header::{IF_MATCH, IF_NONE_MATCH};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName};
+use std::convert::Infallible;
+
+/// Versions a `PUT`, `PATCH` or `DELETE` expects the resource to be at, absent without `If-Match`.
+pub struct IfMatch(pub Option<EntityTags>);
+
+/// Versions the client already has, absent without `If-None-Match`.
+pub struct IfNoneMatch(pub Option<EntityTags>);
+
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(entity_tags(&parts.headers, IF_MATCH, false)))
+    }
+}
+
+impl<S> FromRequestParts<S> for IfNoneMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(entity_tags(&parts.headers, IF_NONE_MATCH, true)))
+    }
+}
+
+fn entity_tags(headers: &HeaderMap, name: HeaderName, weak_comparison: bool) -> Option<EntityTags> {
+    let values: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+
+    (!values.is_empty()).then(|| EntityTags::parse(&values.join(","), weak_comparison))
+}
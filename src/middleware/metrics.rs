@@ -0,0 +1,21 @@
+use crate::services::Metrics;
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::time::Instant;
+
+/// Records the count and latency of every request, labelled with the route template it matched
+/// so that ids in the URI don't create a new series per request.
+pub async fn metrics_layer(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    metrics.record_request(&method, &path, response.status().as_u16(), started.elapsed());
+    response
+}
@@ -1,21 +1,64 @@
-use crate::config::authentication::KEYS;
+mod auth_user;
+mod client_ip;
+mod error;
+mod etag;
+mod metrics;
+mod patch;
+mod permission;
+mod rate_limit;
+mod request_id;
+mod validation;
+
+pub use auth_user::*;
+pub use client_ip::*;
+pub use error::*;
+pub use etag::*;
+pub use metrics::*;
+pub use permission::*;
+pub use rate_limit::*;
+pub use request_id::*;
+pub use validation::*;
+
+use crate::config::authentication::Keys;
 use crate::model::auth::JwtClaims;
 use crate::model::auth_error::AuthError;
-use axum::extract::Request;
+use crate::services::RevocationStore;
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
 use axum::middleware::Next;
 use axum::response::Response;
 use axum_extra::headers::authorization::Bearer;
 use axum_extra::headers::{Authorization, HeaderMapExt};
-use jsonwebtoken::{decode, Validation};
+use tracing::Span;
+
+pub async fn auth_layer(
+    State(keys): State<Keys>,
+    State(revocation_store): State<RevocationStore>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let claims = decode_claims(request.headers(), &keys, &revocation_store).await?;
+
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
+}
 
-pub async fn auth_layer(mut request: Request, next: Next) -> Result<Response, AuthError> {
-    let auth = request
-        .headers()
+async fn decode_claims(
+    headers: &HeaderMap,
+    keys: &Keys,
+    revocation_store: &RevocationStore,
+) -> Result<JwtClaims, AuthError> {
+    let auth = headers
         .typed_get::<Authorization<Bearer>>()
-        .ok_or(AuthError::MissingCredentials)?;
-    let claims = decode::<JwtClaims>(auth.token(), &KEYS.decoding, &Validation::default())
+        .ok_or(AuthError::MissingToken)?;
+    let claims = keys
+        .decode::<JwtClaims>(auth.token())
         .map_err(|_| AuthError::InvalidToken)?;
 
-    request.extensions_mut().insert(claims.claims.clone());
-    Ok(next.run(request).await)
+    if revocation_store.is_revoked(&claims.claims).await? {
+        return Err(AuthError::InvalidToken);
+    }
+
+    Span::current().record("user_id", claims.claims.sub.as_str());
+    Ok(claims.claims)
 }
@@ -0,0 +1,55 @@
+use crate::model::api_response::ApiError;
+use crate::model::patch::{PatchDocument, JSON_PATCH_JSON, MERGE_PATCH_JSON};
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+const ACCEPT_PATCH: HeaderName = HeaderName::from_static("accept-patch");
+
+/// Reads a `PatchDocument` according to the `Content-Type` of the request, rejecting other media
+/// types with 415 and an `Accept-Patch` header listing the supported ones.
+impl<S> FromRequest<S> for PatchDocument
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let media_type = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase());
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let document = match media_type.as_deref() {
+            Some(MERGE_PATCH_JSON) => serde_json::from_slice(&body).map(PatchDocument::Merge),
+            Some(JSON_PATCH_JSON) => serde_json::from_slice(&body).map(PatchDocument::Json),
+            _ => return Err(unsupported_media_type()),
+        };
+
+        document.map_err(|e| {
+            let status = StatusCode::BAD_REQUEST;
+            let message = format!("Failed to parse the patch document: {e}");
+
+            (status, ApiError::from_status(status, &message)).into_response()
+        })
+    }
+}
+
+fn unsupported_media_type() -> Response {
+    let status = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+    let message = format!("Expected a {MERGE_PATCH_JSON} or {JSON_PATCH_JSON} body");
+    let mut response = (status, ApiError::from_status(status, &message)).into_response();
+
+    response.headers_mut().insert(
+        ACCEPT_PATCH,
+        HeaderValue::from_str(&format!("{MERGE_PATCH_JSON}, {JSON_PATCH_JSON}")).unwrap(),
+    );
+    response
+}
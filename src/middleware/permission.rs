@@ -0,0 +1,37 @@
+use crate::middleware::AuthUser;
+use crate::model::auth::permission::Permission;
+use crate::model::auth_error::AuthError;
+use crate::state::AppState;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::request::Parts;
+use log::warn;
+use std::marker::PhantomData;
+
+/// Rejects the request with `AuthError::Forbidden` unless one of the caller's roles grants the
+/// permission `P`, or with `AuthError::ServiceUnavailable` if the permissions can't be loaded.
+pub struct RequirePermission<P: Permission>(PhantomData<P>);
+
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+    P: Permission,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth_user = AuthUser::from_request_parts(parts, state).await?;
+        let claims = &auth_user.claims;
+
+        if !AppState::from_ref(state)
+            .access_control
+            .has_permission(&claims.roles, P::NAME)
+            .await?
+        {
+            warn!("User {} is missing permission {}", claims.sub, P::NAME);
+            return Err(AuthError::Forbidden);
+        }
+
+        Ok(Self(PhantomData))
+    }
+}
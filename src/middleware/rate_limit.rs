@@ -0,0 +1,86 @@
+use crate::middleware::client_ip;
+use crate::model::api_response::ApiError;
+use crate::model::auth::JwtClaims;
+use crate::services::{RateLimitDecision, RateLimiter, RouteGroup};
+use axum::extract::{Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use log::warn;
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Limits public routes per client IP.
+pub async fn public_rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = client_key(&request);
+
+    rate_limit(&limiter, RouteGroup::Public, &client, request, next).await
+}
+
+/// Limits protected routes per user, so it must run after `auth_layer` stored the claims.
+pub async fn protected_rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = match request.extensions().get::<JwtClaims>() {
+        Some(claims) => claims.sub.clone(),
+        None => client_key(&request),
+    };
+
+    rate_limit(&limiter, RouteGroup::Protected, &client, request, next).await
+}
+
+async fn rate_limit(
+    limiter: &RateLimiter,
+    group: RouteGroup,
+    client: &str,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(decision) = limiter.check(group, client).await else {
+        return next.run(request).await;
+    };
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        warn!("Rate limit of {group} routes exceeded by {client}");
+        too_many_requests(&decision)
+    };
+
+    set_headers(response.headers_mut(), &decision);
+    response
+}
+
+fn too_many_requests(decision: &RateLimitDecision) -> Response {
+    let status = StatusCode::TOO_MANY_REQUESTS;
+    let retry_after = seconds(decision.retry_after.as_secs_f64());
+    let message = format!("Rate limit exceeded, retry in {retry_after} seconds");
+    let mut response = (status, ApiError::from_status(status, &message)).into_response();
+
+    response.headers_mut().insert(RETRY_AFTER, retry_after.into());
+    response
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, seconds(decision.reset.as_secs_f64()).into());
+}
+
+fn client_key(request: &Request) -> String {
+    client_ip(request.extensions()).map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
+/// Rounds up to whole seconds, as expected by `Retry-After` and `RateLimit-Reset`.
+fn seconds(seconds: f64) -> u64 {
+    seconds.ceil() as u64
+}
@@ -0,0 +1,28 @@
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderMap, HeaderName};
+use tracing::field::Empty;
+use tracing::Span;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+pub fn request_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get(X_REQUEST_ID).and_then(|value| value.to_str().ok())
+}
+
+/// Span wrapping each request in `TraceLayer`, so every event logged while handling the request
+/// carries its id and route. `user_id` is recorded once the bearer token has been decoded.
+pub fn request_span(request: &Request) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        route,
+        request_id = request_id(request.headers()).unwrap_or_default(),
+        user_id = Empty,
+    )
+}
@@ -0,0 +1,220 @@
+use crate::model::api_response::{ApiError, FieldError};
+use crate::util::validation::{field_errors, missing_fields, validate, Operation, RequiredFields};
+use axum::extract::{FromRequest, FromRequestParts, Query, Request};
+use axum::http::request::Parts;
+use axum::http::{Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use validator::Validate;
+
+/// JSON body extractor that rejects payloads missing a required field or breaking one of their
+/// constraints with 422 `ValidationFailed`, listing every failing field.
+pub struct ValidatedJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ValidatedJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate + RequiredFields,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let operation = match *request.method() {
+            Method::POST => Operation::Create,
+            _ => Operation::Update,
+        };
+        let Json(value) = Json::<Value>::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let payload = match T::deserialize(&value) {
+            Ok(payload) => payload,
+            Err(e) => {
+                let errors = missing_fields::<T>(&value, operation);
+                return Err(validation_failed(&e.to_string(), errors));
+            }
+        };
+        let errors = validate(&value, &payload, operation);
+
+        if !errors.is_empty() {
+            return Err(validation_failed("The request body is invalid.", errors));
+        }
+
+        Ok(Self(payload))
+    }
+}
+
+/// Query string extractor that rejects parameters breaking one of their constraints with 422
+/// `ValidationFailed`, like [`ValidatedJson`].
+pub struct ValidatedQuery<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for ValidatedQuery<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if let Err(e) = query.validate() {
+            return Err(validation_failed("The query string is invalid.", field_errors(&e)));
+        }
+
+        Ok(Self(query))
+    }
+}
+
+fn validation_failed(message: &str, errors: Vec<FieldError>) -> Response {
+    let error = ApiError {
+        code: "ValidationFailed".to_string(),
+        message: message.to_string(),
+        request_id: None,
+        errors,
+    };
+
+    (StatusCode::UNPROCESSABLE_ENTITY, error).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header::CONTENT_TYPE;
+    use axum::routing::post;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use serde::Deserialize;
+    use tower::util::ServiceExt;
+
+    #[derive(Deserialize, Validate)]
+    struct Payload {
+        id: Option<i32>,
+        #[validate(length(min = 1, max = 5, message = "must be between 1 and 5 characters"))]
+        name: Option<String>,
+        #[validate(nested)]
+        items: Option<Vec<Item>>,
+    }
+
+    #[derive(Deserialize, Validate)]
+    struct Item {
+        #[validate(email(message = "must be an email address"))]
+        email: String,
+    }
+
+    impl RequiredFields for Payload {
+        const REQUIRED_ON_CREATE: &'static [&'static str] = &["name"];
+        const REQUIRED_ON_UPDATE: &'static [&'static str] = &["id"];
+    }
+
+    async fn handler(ValidatedJson(payload): ValidatedJson<Payload>) -> String {
+        format!("{:?} {:?}", payload.id, payload.name)
+    }
+
+    async fn send(method: Method, body: &str) -> (StatusCode, Value) {
+        let app = Router::new().route("/", post(handler).put(handler));
+        let req = Request::builder()
+            .method(method)
+            .uri("/")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_valid_payload() {
+        let (status, _) = send(Method::POST, r#"{"name": "foo"}"#).await;
+
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_required_fields_depend_on_method() {
+        let (status, body) = send(Method::POST, r#"{"id": 1, "name": null}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "ValidationFailed");
+        assert_eq!(body["errors"][0]["field"], "name");
+        assert_eq!(body["errors"][0]["code"], "required");
+
+        let (status, body) = send(Method::PUT, r#"{"name": "foo"}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"][0]["field"], "id");
+    }
+
+    #[tokio::test]
+    async fn test_every_failing_field_is_listed() {
+        let (status, body) = send(
+            Method::POST,
+            r#"{"name": "foobar", "items": [{"email": "a@example.com"}, {"email": "b"}]}"#,
+        )
+        .await;
+        let errors = body["errors"].as_array().unwrap();
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0]["field"], "items[1].email");
+        assert_eq!(errors[0]["message"], "must be an email address");
+        assert_eq!(errors[1]["field"], "name");
+        assert_eq!(errors[1]["code"], "length");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_type() {
+        let (status, body) = send(Method::POST, r#"{"name": 1}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "ValidationFailed");
+    }
+
+    #[tokio::test]
+    async fn test_validated_query() {
+        #[derive(Deserialize, Validate)]
+        struct Search {
+            #[validate(length(max = 3))]
+            name: String,
+        }
+
+        async fn search(ValidatedQuery(query): ValidatedQuery<Search>) -> String {
+            query.name
+        }
+
+        let app = Router::new().route("/", axum::routing::get(search));
+        let res = app.clone().oneshot(Request::get("/?name=foo").body(Body::empty()).unwrap()).await;
+
+        assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+        let res = app.clone().oneshot(Request::get("/?name=fooo").body(Body::empty()).unwrap()).await;
+        let res = res.unwrap();
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["errors"][0]["field"], "name");
+        assert_eq!(body["errors"][0]["message"], "is invalid");
+
+        let res = app.oneshot(Request::get("/").body(Body::empty()).unwrap()).await;
+
+        assert_eq!(res.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_rejected_by_json() {
+        let (status, _) = send(Method::POST, "{").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
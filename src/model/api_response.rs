@@ -1,15 +1,81 @@
-use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderValue, StatusCode};
 use axum::Json;
 use axum::response::{IntoResponse, Response};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-pub type ApiResponse<T> = (StatusCode, Result<Json<T>, Json<ApiError>>);
+pub type ApiResponse<T> = (StatusCode, Result<Json<T>, ApiError>);
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiError {
     pub code: String,
     pub message: String,
+    /// Id of the request that failed, taken from the `X-Request-Id` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Fields of the request body that failed validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// Reason a single field of a request body was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Path of the field, such as `email` or `users[1].user_name`.
+    pub field: String,
+    /// Rule the field broke, such as `required`, `length` or `email`.
+    pub code: String,
+    pub message: String,
+}
+
+/// RFC 7807 representation of an [`ApiError`], returned instead of it when the client sends
+/// `Accept: application/problem+json`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    /// Builds an error for responses that were not produced by a [`ResponseError`], such as axum's
+    /// own extractor rejections, using the status reason as the code.
+    pub fn from_status(status: StatusCode, message: &str) -> Self {
+        let reason = status.canonical_reason().unwrap_or("Unknown");
+        let message = if message.trim().is_empty() { reason } else { message.trim() };
+
+        Self {
+            code: reason.split_whitespace().collect(),
+            message: message.to_string(),
+            request_id: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn to_problem_details(&self, status: StatusCode, instance: Option<String>) -> ProblemDetails {
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Unknown").to_string(),
+            status: status.as_u16(),
+            detail: self.message.clone(),
+            instance,
+            code: self.code.clone(),
+            request_id: self.request_id.clone(),
+            errors: self.errors.clone(),
+        }
+    }
 }
 
 pub trait AsApiError {
@@ -23,6 +89,8 @@ impl<T : ToString> AsApiError for T {
         let error = ApiError {
             code: code.to_string(),
             message: self.to_string(),
+            request_id: None,
+            errors: Vec::new(),
         };
 
         (status_code, error)
@@ -51,14 +119,63 @@ where
             .map(|t| (ok_status, Ok(Json(t))))
             .unwrap_or_else(|e| {
                 let (status, err) = e.to_api_err_response();
-                (status, Err(Json(err)))
+                (status, Err(err))
             })
     }
 }
 
+/// Responds with a 500 unless wrapped in a tuple with a more specific status. The error is kept in
+/// the response extensions so `error_layer` can render it as problem+json when asked to.
 impl IntoResponse for ApiError {
 
     fn into_response(self) -> Response {
-        todo!()
+        let mut response = (StatusCode::INTERNAL_SERVER_ERROR, Json(self.clone())).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, Json(self)).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        let error = ApiError::from_status(StatusCode::UNSUPPORTED_MEDIA_TYPE, "");
+
+        assert_eq!(error.code, "UnsupportedMediaType");
+        assert_eq!(error.message, "Unsupported Media Type");
+    }
+
+    #[test]
+    fn test_to_problem_details() {
+        let error = ApiError {
+            code: "NotFound".to_string(),
+            message: "User ID 1 does not exist".to_string(),
+            request_id: Some("abc".to_string()),
+            errors: Vec::new(),
+        };
+        let problem = error.to_problem_details(StatusCode::NOT_FOUND, Some("/user/1".to_string()));
+        let json = serde_json::to_value(&problem).unwrap();
+
+        assert_eq!(json["type"], "about:blank");
+        assert_eq!(json["title"], "Not Found");
+        assert_eq!(json["status"], 404);
+        assert_eq!(json["detail"], "User ID 1 does not exist");
+        assert_eq!(json["instance"], "/user/1");
+        assert_eq!(json["code"], "NotFound");
+        assert_eq!(json["request_id"], "abc");
     }
 }
\ No newline at end of file
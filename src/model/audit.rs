@@ -0,0 +1,26 @@
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct AuditEntry {
+    pub id: Option<i32>,
+    pub action: String,
+    pub subject: String,
+    pub actor: Option<String>,
+    pub detail: Option<String>,
+    pub created_timestamp: Option<NaiveDateTime>,
+}
+
+impl AuditEntry {
+    pub fn new(action: &str, subject: &str, actor: Option<&str>, detail: Option<String>) -> Self {
+        Self {
+            id: None,
+            action: action.to_string(),
+            subject: subject.to_string(),
+            actor: actor.map(str::to_string),
+            detail,
+            created_timestamp: None,
+        }
+    }
+}
@@ -1,35 +1,50 @@
+use chrono::{DateTime, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JwtClaims {
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+    /// Issue time in microseconds since the epoch. Finer than `iat`, so that revoking the sessions
+    /// of a user also catches the tokens issued earlier within the same second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_us: Option<i64>,
+    pub jti: Uuid,
+    pub sid: Uuid,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl JwtClaims {
+    pub fn user_id(&self) -> Option<i32> {
+        self.sub.parse().ok()
+    }
+
+    /// When the token was issued, falling back to the whole second of `iat` for tokens without
+    /// `iat_us`.
+    pub fn issued_at(&self) -> NaiveDateTime {
+        self.iat_us
+            .and_then(DateTime::from_timestamp_micros)
+            .or_else(|| DateTime::from_timestamp(self.iat as i64, 0))
+            .unwrap_or_default()
+            .naive_utc()
+    }
 }
 
 impl Display for JwtClaims {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Sub: {}\nExpire: {}", self.sub, self.exp)
+        write!(
+            f,
+            "Sub: {}\nExpire: {}\nIssued: {}\nToken: {}\nSession: {}\nRoles: {}",
+            self.sub,
+            self.exp,
+            self.iat,
+            self.jti,
+            self.sid,
+            self.roles.join(", ")
+        )
     }
 }
-
-// impl<S> FromRequestParts<S> for JwtClaims
-// where
-//     S: Send + Sync,
-// {
-//     type Rejection = AuthError;
-//
-//     async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
-//         let TypedHeader(Authorization(bearer)) = parts
-//             .extract::<TypedHeader<Authorization<Bearer>>>()
-//             .await
-//             .map_err(|_| AuthError::InvalidToken)?;
-//         let token_data = decode::<JwtClaims>(
-//             bearer.token(),
-//             &KEYS.decoding,
-//             &Validation::default(),
-//         )
-//             .map_err(|_| AuthError::InvalidToken)?;
-//         Ok(token_data.claims)
-//     }
-// }
\ No newline at end of file
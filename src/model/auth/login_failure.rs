@@ -0,0 +1,35 @@
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+use std::fmt::{Display, Formatter};
+
+/// What failed login attempts are counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoginScope {
+    User,
+    Ip,
+}
+
+impl LoginScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginScope::User => "user",
+            LoginScope::Ip => "ip",
+        }
+    }
+}
+
+impl Display for LoginScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct LoginFailure {
+    pub scope: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure_timestamp: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+}
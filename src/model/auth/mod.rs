@@ -2,11 +2,21 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 mod claims;
+mod login_failure;
+pub mod permission;
+mod refresh_token;
 
 pub use claims::*;
+pub use login_failure::*;
+pub use refresh_token::*;
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct LoginDto {
     pub user_name: String,
     pub password: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RefreshDto {
+    pub refresh_token: String,
+}
@@ -0,0 +1,23 @@
+pub trait Permission: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+macro_rules! permissions {
+    ($($permission:ident => $name:literal),* $(,)?) => {
+        $(
+            pub struct $permission;
+
+            impl Permission for $permission {
+                const NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+
+permissions! {
+    UserRead => "user:read",
+    UserCreate => "user:create",
+    UserUpdate => "user:update",
+    UserDelete => "user:delete",
+    UserUnlock => "user:unlock",
+}
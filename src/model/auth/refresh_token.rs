@@ -0,0 +1,31 @@
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Clone, FromRow)]
+#[allow(dead_code)]
+pub struct RefreshToken {
+    pub id: Option<i32>,
+    pub user_id: i32,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_timestamp: NaiveDateTime,
+    pub used_timestamp: Option<NaiveDateTime>,
+    pub revoked_timestamp: Option<NaiveDateTime>,
+    pub created_timestamp: Option<NaiveDateTime>,
+}
+
+impl RefreshToken {
+    pub fn new(user_id: i32, family_id: Uuid, token_hash: String, expires_timestamp: NaiveDateTime) -> Self {
+        Self {
+            id: None,
+            user_id,
+            family_id,
+            token_hash,
+            expires_timestamp,
+            used_timestamp: None,
+            revoked_timestamp: None,
+            created_timestamp: None,
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::model::api_response::{ApiError, AsApiError, ResponseError};
-use axum::http::StatusCode;
+use axum::http::header::WWW_AUTHENTICATE;
+use axum::http::{HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
@@ -11,10 +13,23 @@ pub enum AuthError {
     WrongCredentials,
     #[error("Credentials not provided!")]
     MissingCredentials,
+    #[error("Authentication token not provided!")]
+    MissingToken,
     #[error("Error occurred in login token creation.")]
     TokenCreation,
     #[error("Invalid session token, please log back in.")]
     InvalidToken,
+    #[error("You do not have permission to perform this action.")]
+    Forbidden,
+    #[error("Authentication is temporarily unavailable, please try again later.")]
+    ServiceUnavailable,
+    #[error(
+        "Too many failed login attempts, locked until {}.",
+        .0.to_rfc3339_opts(SecondsFormat::Secs, true)
+    )]
+    AccountLocked(DateTime<Utc>),
+    #[error("This account is not active.")]
+    AccountDisabled,
 }
 
 impl ResponseError for AuthError {
@@ -25,10 +40,20 @@ impl ResponseError for AuthError {
                 self.as_api_error(StatusCode::BAD_REQUEST, "WrongCredentials"),
             AuthError::MissingCredentials =>
                 self.as_api_error(StatusCode::BAD_REQUEST, "MissingCredentials"),
+            AuthError::MissingToken =>
+                self.as_api_error(StatusCode::UNAUTHORIZED, "MissingToken"),
             AuthError::InvalidToken =>
                 self.as_api_error(StatusCode::UNAUTHORIZED, "InvalidToken"),
             AuthError::TokenCreation =>
                 self.as_api_error(StatusCode::UNAUTHORIZED, "InvalidToken"),
+            AuthError::Forbidden =>
+                self.as_api_error(StatusCode::FORBIDDEN, "Forbidden"),
+            AuthError::ServiceUnavailable =>
+                self.as_api_error(StatusCode::SERVICE_UNAVAILABLE, "ServiceUnavailable"),
+            AuthError::AccountLocked(_) =>
+                self.as_api_error(StatusCode::LOCKED, "AccountLocked"),
+            AuthError::AccountDisabled =>
+                self.as_api_error(StatusCode::FORBIDDEN, "AccountDisabled"),
         }
     }
 }
@@ -36,6 +61,15 @@ impl ResponseError for AuthError {
 impl IntoResponse for AuthError {
 
     fn into_response(self) -> Response {
-        self.to_api_err_response().into_response()
+        let (status, err) = self.to_api_err_response();
+        let mut response = (status, err).into_response();
+
+        if let AuthError::MissingToken = self {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
+        response
     }
 }
\ No newline at end of file
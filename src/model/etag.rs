@@ -0,0 +1,58 @@
+/// Entity tags listed by an `If-Match` or `If-None-Match` header. Our tags are the version of the
+/// resource in quotes, so only those are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityTags {
+    Any,
+    Versions(Vec<i32>),
+}
+
+impl EntityTags {
+    /// Parses a header value. `If-Match` uses the strong comparison, where weak tags never match,
+    /// and `If-None-Match` the weak one, where `W/"1"` matches version 1.
+    pub fn parse(value: &str, weak_comparison: bool) -> Self {
+        if value.trim() == "*" {
+            return EntityTags::Any;
+        }
+
+        let versions = value
+            .split(',')
+            .filter_map(|tag| {
+                let tag = tag.trim();
+                let tag = match tag.strip_prefix("W/") {
+                    Some(_) if !weak_comparison => return None,
+                    Some(tag) => tag,
+                    None => tag,
+                };
+
+                tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok()
+            })
+            .collect();
+
+        EntityTags::Versions(versions)
+    }
+
+    pub fn matches(&self, version: i32) -> bool {
+        match self {
+            EntityTags::Any => true,
+            EntityTags::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+pub fn etag(version: i32) -> String {
+    format!("\"{version}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(EntityTags::parse(" * ", false), EntityTags::Any);
+        assert_eq!(EntityTags::parse(r#""1", W/"2", "x""#, false), EntityTags::Versions(vec![1]));
+        assert_eq!(EntityTags::parse(r#""1", W/"2""#, true), EntityTags::Versions(vec![1, 2]));
+        assert!(!EntityTags::parse("1", true).matches(1));
+        assert!(EntityTags::parse(&etag(3), false).matches(3));
+    }
+}
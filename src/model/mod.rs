@@ -1,4 +1,9 @@
+pub mod audit;
 pub mod auth;
 pub mod auth_error;
+pub mod etag;
+pub mod health;
+pub mod page;
+pub mod patch;
 pub mod user;
 pub mod api_response;
\ No newline at end of file
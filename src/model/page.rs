@@ -0,0 +1,210 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PageError {
+    #[error("Limit must be between 1 and {max}, got {0}", max = PageRequest::MAX_LIMIT)]
+    InvalidLimit(u32),
+    #[error("Cursor is malformed or was issued for a different sort order")]
+    InvalidCursor,
+}
+
+/// Sort orders that can be used for keyset pagination. A leading `-` sorts descending.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Sort {
+    #[default]
+    #[serde(rename = "id")]
+    IdAsc,
+    #[serde(rename = "-id")]
+    IdDesc,
+    #[serde(rename = "created_timestamp")]
+    CreatedAsc,
+    #[serde(rename = "-created_timestamp")]
+    CreatedDesc,
+}
+
+impl Sort {
+    pub fn is_descending(&self) -> bool {
+        matches!(self, Sort::IdDesc | Sort::CreatedDesc)
+    }
+
+    pub fn is_by_created(&self) -> bool {
+        matches!(self, Sort::CreatedAsc | Sort::CreatedDesc)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Maximum number of items to return, between 1 and 100.
+    pub limit: Option<u32>,
+    /// Opaque cursor taken from the `next_cursor` of a previous page.
+    pub cursor: Option<String>,
+    #[param(inline)]
+    pub sort: Option<Sort>,
+    /// Only return items whose name starts with this prefix, ignoring case.
+    pub user_name: Option<String>,
+    /// Only return items created after this time.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Whether to count every item matching the filters.
+    pub include_total: Option<bool>,
+    /// Whether to include deleted items.
+    pub include_deleted: Option<bool>,
+}
+
+/// Position of the last item of a page, encoded into the opaque `next_cursor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: Sort,
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_timestamp: Option<NaiveDateTime>,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, PageError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| PageError::InvalidCursor)?;
+
+        serde_json::from_slice(&bytes).map_err(|_| PageError::InvalidCursor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest {
+    pub limit: u32,
+    pub cursor: Option<Cursor>,
+    pub sort: Sort,
+    pub name_prefix: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub include_total: bool,
+    pub include_deleted: bool,
+}
+
+impl PageRequest {
+    pub const DEFAULT_LIMIT: u32 = 20;
+    pub const MAX_LIMIT: u32 = 100;
+
+    pub fn from_query(query: &PageQuery) -> Result<Self, PageError> {
+        let limit = query.limit.unwrap_or(Self::DEFAULT_LIMIT);
+        let sort = query.sort.unwrap_or_default();
+
+        if !(1..=Self::MAX_LIMIT).contains(&limit) {
+            return Err(PageError::InvalidLimit(limit));
+        }
+
+        let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+
+        if let Some(cursor) = &cursor
+            && (cursor.sort != sort || sort.is_by_created() != cursor.created_timestamp.is_some())
+        {
+            return Err(PageError::InvalidCursor);
+        }
+
+        Ok(Self {
+            limit,
+            cursor,
+            sort,
+            name_prefix: query.user_name.clone().filter(|p| !p.is_empty()),
+            created_after: query.created_after.map(|t| t.naive_utc()),
+            include_total: query.include_total.unwrap_or(false),
+            include_deleted: query.include_deleted.unwrap_or(false),
+        })
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            limit: Self::DEFAULT_LIMIT,
+            cursor: None,
+            sort: Sort::default(),
+            name_prefix: None,
+            created_after: None,
+            include_total: false,
+            include_deleted: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, absent on the last page.
+    pub next_cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(&T) -> U) -> Page<U> {
+        Page {
+            items: self.items.iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(cursor: Option<String>, sort: Option<Sort>) -> PageQuery {
+        PageQuery {
+            cursor,
+            sort,
+            ..PageQuery::default()
+        }
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            sort: Sort::CreatedDesc,
+            id: 42,
+            created_timestamp: Some(NaiveDateTime::default()),
+        };
+
+        assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+        assert_eq!(Cursor::decode("not a cursor"), Err(PageError::InvalidCursor));
+    }
+
+    #[test]
+    fn test_default_request() {
+        let request = PageRequest::from_query(&PageQuery::default()).unwrap();
+
+        assert_eq!(request, PageRequest::default());
+    }
+
+    #[test]
+    fn test_invalid_limit() {
+        let query = PageQuery {
+            limit: Some(0),
+            ..PageQuery::default()
+        };
+
+        assert_eq!(PageRequest::from_query(&query), Err(PageError::InvalidLimit(0)));
+    }
+
+    #[test]
+    fn test_cursor_for_other_sort() {
+        let cursor = Cursor {
+            sort: Sort::IdAsc,
+            id: 42,
+            created_timestamp: None,
+        };
+        let query = query(Some(cursor.encode()), Some(Sort::IdDesc));
+
+        assert_eq!(PageRequest::from_query(&query), Err(PageError::InvalidCursor));
+    }
+}
@@ -0,0 +1,55 @@
+use json_patch::Patch;
+use serde_json::Value;
+
+pub const MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+pub const JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+/// Body of a `PATCH` request: an RFC 7396 merge patch, which sets the fields present and removes
+/// those set to `null`, or an RFC 6902 list of JSON patch operations.
+#[derive(Debug, Clone)]
+pub enum PatchDocument {
+    Merge(Value),
+    Json(Patch),
+}
+
+impl PatchDocument {
+    /// Applies the patch to `target`, leaving it untouched if any JSON patch operation fails.
+    pub fn apply(&self, target: &mut Value) -> Result<(), String> {
+        match self {
+            PatchDocument::Merge(patch) => {
+                json_patch::merge(target, patch);
+                Ok(())
+            }
+            PatchDocument::Json(patch) => json_patch::patch(target, patch).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut user = json!({"id": 1, "user_name": "foo", "email": "foo@example.com"});
+        let patch = PatchDocument::Merge(json!({"email": null, "display_name": "Foo"}));
+
+        patch.apply(&mut user).unwrap();
+
+        assert_eq!(user, json!({"id": 1, "user_name": "foo", "display_name": "Foo"}));
+    }
+
+    #[test]
+    fn test_json_patch_is_atomic() {
+        let mut user = json!({"id": 1, "user_name": "foo"});
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "replace", "path": "/user_name", "value": "bar"},
+            {"op": "test", "path": "/id", "value": 2},
+        ]))
+        .unwrap();
+
+        assert!(PatchDocument::Json(patch).apply(&mut user).is_err());
+        assert_eq!(user["user_name"], "foo");
+    }
+}
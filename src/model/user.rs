@@ -1,7 +1,48 @@
+use crate::model::api_response::ApiError;
+use crate::model::auth::permission::{Permission, UserCreate, UserDelete, UserUpdate};
+use crate::util::validation::RequiredFields;
 use crate::util::AsDtoEnabled;
+use chrono::NaiveDateTime;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use utoipa::ToSchema;
+use std::sync::LazyLock;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
+
+/// Letters, digits and `.`, `_`, `-` or `@`, so an email address can double as user name.
+const USER_NAME_PATTERN: &str = r"^[A-Za-z0-9._@-]+$";
+
+static USER_NAME_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(USER_NAME_PATTERN).unwrap());
+
+fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank"));
+    }
+
+    Ok(())
+}
+
+/// Users are deleted through `DELETE /user/{id}`, which also records when.
+fn not_deleted(status: &UserStatus) -> Result<(), ValidationError> {
+    match status {
+        UserStatus::Deleted => Err(ValidationError::new("deleted")),
+        _ => Ok(()),
+    }
+}
+
+/// Lifecycle of an account. Only active users can log in, and deleted users are hidden unless
+/// explicitly requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "user_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UserStatus {
+    Pending,
+    #[default]
+    Active,
+    Suspended,
+    Deleted,
+}
 
 #[derive(Clone, FromRow)]
 #[allow(dead_code)]
@@ -9,18 +50,24 @@ use utoipa::ToSchema;
 pub struct User {
     pub id: Option<i32>,
     pub user_name: Option<String>,
-    pub created_timestamp: Option<chrono::NaiveDateTime>,
-    pub updated_timestamp: Option<chrono::NaiveDateTime>,
+    pub created_timestamp: Option<NaiveDateTime>,
+    pub updated_timestamp: Option<NaiveDateTime>,
+    pub password_hash: Option<String>,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub status: Option<UserStatus>,
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Incremented on every change. When set on an entity passed to `update`, the update only
+    /// applies if the stored user is still at this version.
+    pub version: Option<i32>,
 }
 
 #[cfg(test)]
 impl User {
     pub(crate) fn new(user_name: &str) -> Self {
         User {
-            id: None,
             user_name: Some(String::from(user_name)),
-            created_timestamp: None,
-            updated_timestamp: None,
+            ..User::empty()
         }
     }
 
@@ -30,14 +77,154 @@ impl User {
             user_name: None,
             created_timestamp: None,
             updated_timestamp: None,
+            password_hash: None,
+            email: None,
+            display_name: None,
+            status: None,
+            deleted_at: None,
+            version: None,
         }
     }
 }
 
-#[derive(Clone, Deserialize, Serialize, ToSchema)]
+/// `user_name` and `password` are required when creating a user, `user_name` when replacing one.
+#[derive(Clone, Default, Deserialize, Serialize, ToSchema, Validate)]
 pub struct UserDto {
     pub id: Option<i32>,
+    #[validate(
+        length(min = 1, max = 255, message = "must be between 1 and 255 characters"),
+        regex(path = *USER_NAME_REGEX, message = "may only contain letters, digits, '.', '_', '-' and '@'"),
+    )]
+    #[schema(min_length = 1, max_length = 255, pattern = r"^[A-Za-z0-9._@-]+$")]
     pub user_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
+    #[schema(write_only, min_length = 8, max_length = 128)]
+    pub password: Option<String>,
+    #[serde(default)]
+    #[validate(
+        length(max = 255, message = "must be at most 255 characters"),
+        email(message = "must be an address such as name@example.com"),
+    )]
+    #[schema(format = Email, max_length = 255)]
+    pub email: Option<String>,
+    #[serde(default)]
+    #[validate(
+        length(min = 1, max = 255, message = "must be between 1 and 255 characters"),
+        custom(function = "not_blank", message = "must not be blank"),
+    )]
+    #[schema(min_length = 1, max_length = 255)]
+    pub display_name: Option<String>,
+    /// Defaults to `active` for new users. Use `DELETE /user/{id}` to delete a user.
+    #[serde(default)]
+    #[validate(custom(function = "not_deleted", message = "users are deleted through DELETE /user/{id}"))]
+    pub status: Option<UserStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Version of the user, also sent as its `ETag`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub version: Option<i32>,
+}
+
+impl RequiredFields for UserDto {
+    const REQUIRED_ON_CREATE: &'static [&'static str] = &["user_name", "password"];
+    const REQUIRED_ON_UPDATE: &'static [&'static str] = &["user_name"];
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserQuery {
+    /// Whether to return the user even if it was deleted.
+    pub include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct AvailabilityQuery {
+    #[validate(
+        length(min = 1, max = 255, message = "must be between 1 and 255 characters"),
+        regex(path = *USER_NAME_REGEX, message = "may only contain letters, digits, '.', '_', '-' and '@'"),
+    )]
+    #[param(min_length = 1, max_length = 255, pattern = r"^[A-Za-z0-9._@-]+$")]
+    pub user_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UserNameAvailability {
+    pub user_name: String,
+    /// Whether no other user has this name, ignoring case.
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteQuery {
+    /// Remove the user for good instead of marking it as deleted.
+    pub permanent: Option<bool>,
+}
+
+/// Whether a bulk request is applied as a whole or operation by operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    /// Either every operation is applied or none is.
+    #[default]
+    Transactional,
+    /// Valid operations are applied even if others fail.
+    BestEffort,
+}
+
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Create { user: UserDto },
+    Update { user: UserDto },
+    Delete { id: i32 },
+}
+
+impl BulkOperation {
+    pub fn permission(&self) -> &'static str {
+        match self {
+            BulkOperation::Create { .. } => UserCreate::NAME,
+            BulkOperation::Update { .. } => UserUpdate::NAME,
+            BulkOperation::Delete { .. } => UserDelete::NAME,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, ToSchema, Validate)]
+pub struct BulkUserRequest {
+    #[serde(default)]
+    pub mode: BulkMode,
+    #[validate(length(min = 1, max = 100, message = "must hold between 1 and 100 operations"))]
+    #[schema(min_items = 1, max_items = 100)]
+    pub operations: Vec<BulkOperation>,
+}
+
+impl RequiredFields for BulkUserRequest {
+    const REQUIRED_ON_CREATE: &'static [&'static str] = &["operations"];
+}
+
+/// Outcome of one operation of a bulk request, with the resulting user or the error it failed with.
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct BulkItemResult {
+    /// Position of the operation in the request.
+    pub index: usize,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<UserDto>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct BulkUserResponse {
+    pub mode: BulkMode,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
 }
 
 impl AsDtoEnabled<UserDto> for User {
@@ -45,6 +232,12 @@ impl AsDtoEnabled<UserDto> for User {
         UserDto {
             id: self.id,
             user_name: self.user_name.clone(),
+            password: None,
+            email: self.email.clone(),
+            display_name: self.display_name.clone(),
+            status: self.status,
+            deleted_at: self.deleted_at,
+            version: self.version,
         }
     }
 
@@ -54,6 +247,12 @@ impl AsDtoEnabled<UserDto> for User {
             user_name: dto.user_name.clone(),
             created_timestamp: None,
             updated_timestamp: None,
+            password_hash: None,
+            email: dto.email.clone(),
+            display_name: dto.display_name.clone(),
+            status: dto.status,
+            deleted_at: None,
+            version: None,
         }
     }
 }
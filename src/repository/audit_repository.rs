@@ -0,0 +1,177 @@
+use crate::model::audit::AuditEntry;
+use crate::repository::repository_traits::WriteRepository;
+use crate::repository::{
+    Database, InMemoryEntity, InMemoryRepository, RepositoryError, RepositoryResult,
+    SharedTransaction,
+};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as, PgPool};
+use std::sync::Arc;
+
+pub type ArcAuditRecorder = Arc<dyn AuditRecorder + Send + Sync>;
+
+#[async_trait]
+pub trait AuditRecorder {
+    async fn record(&self, entry: &AuditEntry) -> RepositoryResult<AuditEntry>;
+
+    /// Appends every entry of `entries` with a single statement.
+    async fn record_all(&self, entries: &[AuditEntry]) -> RepositoryResult<u64>;
+}
+
+#[derive(Clone)]
+pub struct AuditRepository {
+    db: Database,
+}
+
+impl AuditRepository {
+    pub fn new(pool: &PgPool) -> Self {
+        Self {
+            db: Database::Pool(pool.clone()),
+        }
+    }
+
+    /// Runs the queries of the repository on the transaction of a unit of work.
+    pub fn in_transaction(transaction: &SharedTransaction) -> Self {
+        Self {
+            db: Database::Transaction(transaction.clone()),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub async fn find_by_subject(&self, subject: &str) -> RepositoryResult<Vec<AuditEntry>> {
+        let query = query_as!(
+            AuditEntry,
+            "
+            select *
+            from audit_log
+            where subject = $1
+            order by id
+        ",
+            subject
+        );
+
+        Ok(query.fetch_all(&mut *self.db.acquire().await?).await?)
+    }
+}
+
+#[async_trait]
+impl AuditRecorder for AuditRepository {
+    async fn record(&self, entry: &AuditEntry) -> RepositoryResult<AuditEntry> {
+        let query = query_as!(
+            AuditEntry,
+            "
+            insert into audit_log (action, subject, actor, detail)
+            values ($1, $2, $3, $4)
+            returning *
+        ",
+            entry.action,
+            entry.subject,
+            entry.actor,
+            entry.detail
+        );
+
+        Ok(query.fetch_one(&mut *self.db.acquire().await?).await?)
+    }
+
+    async fn record_all(&self, entries: &[AuditEntry]) -> RepositoryResult<u64> {
+        let actions: Vec<String> = entries.iter().map(|e| e.action.clone()).collect();
+        let subjects: Vec<String> = entries.iter().map(|e| e.subject.clone()).collect();
+        let actors: Vec<Option<String>> = entries.iter().map(|e| e.actor.clone()).collect();
+        let details: Vec<Option<String>> = entries.iter().map(|e| e.detail.clone()).collect();
+        let query = query!(
+            "
+            insert into audit_log (action, subject, actor, detail)
+            select *
+            from unnest($1::varchar[], $2::varchar[], $3::varchar[], $4::text[])
+        ",
+            &actions,
+            &subjects,
+            &actors as &[Option<String>],
+            &details as &[Option<String>]
+        );
+
+        query
+            .execute(&mut *self.db.acquire().await?)
+            .await
+            .map(|r| r.rows_affected())
+            .map_err(RepositoryError::from)
+    }
+}
+
+/// Audit entries are only ever appended.
+impl InMemoryEntity<i32> for AuditEntry {
+    fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    fn created(&mut self, id: i32, now: NaiveDateTime) {
+        self.id = Some(id);
+        self.created_timestamp = Some(now);
+    }
+
+    fn updated(&mut self, stored: &Self, _now: NaiveDateTime) {
+        self.created_timestamp = stored.created_timestamp;
+    }
+
+    fn deleted(&mut self, _now: NaiveDateTime) {}
+
+    fn is_deleted(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl AuditRecorder for InMemoryRepository<AuditEntry, i32> {
+    async fn record(&self, entry: &AuditEntry) -> RepositoryResult<AuditEntry> {
+        self.create(entry).await
+    }
+
+    async fn record_all(&self, entries: &[AuditEntry]) -> RepositoryResult<u64> {
+        for entry in entries {
+            self.create(entry).await?;
+        }
+
+        Ok(entries.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_record(pool: PgPool) {
+        let repo = AuditRepository::new(&pool);
+        let entry = AuditEntry::new("account_locked", "foo", None, Some("detail".to_string()));
+
+        let recorded = repo.record(&entry).await.unwrap();
+
+        assert!(recorded.id.is_some());
+        assert!(recorded.created_timestamp.is_some());
+
+        let entries = repo.find_by_subject("foo").await.unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "account_locked");
+        assert!(repo.find_by_subject("bar").await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_record_all(pool: PgPool) {
+        let repo = AuditRepository::new(&pool);
+        let entries = [
+            AuditEntry::new("user_created", "foo", None, Some("user 1".to_string())),
+            AuditEntry::new("user_created", "bar", Some("admin"), None),
+        ];
+
+        assert_eq!(repo.record_all(&entries).await, Ok(2));
+        assert_eq!(repo.record_all(&[]).await, Ok(0));
+
+        let entries = repo.find_by_subject("bar").await.unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor.as_deref(), Some("admin"));
+        assert_eq!(entries[0].detail, None);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::repository::{RepositoryError, RepositoryResult};
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// Transaction shared by the repositories of a unit of work, taken out once it is committed or
+/// rolled back.
+pub type SharedTransaction = Arc<Mutex<Option<Transaction<'static, Postgres>>>>;
+
+/// Where a repository runs its queries: on a connection of the pool, or on the transaction of the
+/// unit of work it belongs to.
+#[derive(Clone)]
+pub enum Database {
+    Pool(PgPool),
+    Transaction(SharedTransaction),
+}
+
+impl Database {
+    pub async fn acquire(&self) -> RepositoryResult<DbConnection<'_>> {
+        match self {
+            Database::Pool(pool) => Ok(DbConnection::Pooled(pool.acquire().await?)),
+            Database::Transaction(transaction) => {
+                MutexGuard::try_map(transaction.lock().await, Option::as_mut)
+                    .map(DbConnection::Transaction)
+                    .map_err(|_| RepositoryError::Query("The transaction has already finished".to_string()))
+            }
+        }
+    }
+}
+
+pub enum DbConnection<'a> {
+    Pooled(PoolConnection<Postgres>),
+    Transaction(MappedMutexGuard<'a, Transaction<'static, Postgres>>),
+}
+
+impl Deref for DbConnection<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            DbConnection::Pooled(connection) => connection,
+            DbConnection::Transaction(transaction) => transaction,
+        }
+    }
+}
+
+impl DerefMut for DbConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            DbConnection::Pooled(connection) => connection,
+            DbConnection::Transaction(transaction) => transaction,
+        }
+    }
+}
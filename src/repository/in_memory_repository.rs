@@ -0,0 +1,532 @@
+use crate::model::page::{Cursor, Page, PageRequest};
+use crate::repository::repository_traits::{
+    Batch, BatchOutcome, BatchRepository, ReadRepository, Repository, SoftDeleteRepository,
+    WriteRepository,
+};
+use crate::repository::{RepositoryError, RepositoryResult};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Ids handed out by an `InMemoryRepository`, counting up from one like an identity column.
+pub trait EntityId: Copy + Ord + Send + Sync + TryFrom<i64> + Into<i64> {}
+
+impl<ID: Copy + Ord + Send + Sync + TryFrom<i64> + Into<i64>> EntityId for ID {}
+
+/// Entities kept by an `InMemoryRepository`. The hooks fill in what the database would otherwise
+/// set through column defaults and the statements of the Postgres repositories.
+pub trait InMemoryEntity<ID>: Clone + Send + Sync {
+    fn id(&self) -> Option<ID>;
+
+    /// Prepares a new entity stored under `id` at `now`.
+    fn created(&mut self, id: ID, now: NaiveDateTime);
+
+    /// Prepares the entity to replace `stored` at `now`.
+    fn updated(&mut self, stored: &Self, now: NaiveDateTime);
+
+    /// Marks the entity as deleted at `now`.
+    fn deleted(&mut self, now: NaiveDateTime);
+
+    fn is_deleted(&self) -> bool;
+
+    /// Version of the entity. When set on an entity passed to `update`, the stored entity must
+    /// still be at this version.
+    fn version(&self) -> Option<i32> {
+        None
+    }
+
+    /// Values that must be unique among entities that are not deleted, with the name of the
+    /// constraint reported by `RepositoryError::UniqueViolation`.
+    fn unique_keys(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    /// Whether the entity passes the filters of `request`, other than `include_deleted`.
+    fn matches(&self, _request: &PageRequest) -> bool {
+        true
+    }
+
+    fn created_timestamp(&self) -> Option<NaiveDateTime> {
+        None
+    }
+}
+
+/// Repository keeping its entities in memory, for tests and running without a database. Clones
+/// share their entities.
+#[derive(Clone)]
+pub struct InMemoryRepository<T, ID> {
+    entities: Arc<Mutex<Entities<T, ID>>>,
+}
+
+#[derive(Clone)]
+struct Entities<T, ID> {
+    rows: BTreeMap<ID, T>,
+    /// Last id handed out, shared with staged copies like a database sequence.
+    last_id: Arc<AtomicI64>,
+    /// Ids of the entities written since `stage`, only kept by staged copies.
+    staged: Option<BTreeSet<ID>>,
+}
+
+impl<T, ID> Default for InMemoryRepository<T, ID> {
+    fn default() -> Self {
+        Self {
+            entities: Arc::new(Mutex::new(Entities {
+                rows: BTreeMap::new(),
+                last_id: Arc::new(AtomicI64::new(0)),
+                staged: None,
+            })),
+        }
+    }
+}
+
+impl<T, ID> InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies the current entities into a repository of their own, keeping track of the entities
+    /// written to it for `apply_staged`. Both hand out ids from the same sequence, so entities
+    /// created in either never share an id.
+    pub fn stage(&self) -> Self {
+        let entities = self.entities();
+
+        Self {
+            entities: Arc::new(Mutex::new(Entities {
+                rows: entities.rows.clone(),
+                last_id: entities.last_id.clone(),
+                staged: Some(BTreeSet::new()),
+            })),
+        }
+    }
+
+    /// Copies the entities written to `staged`, a repository from `stage`, leaving the others as
+    /// they are.
+    pub fn apply_staged(&self, staged: &Self) {
+        let staged = staged.entities();
+        let mut entities = self.entities();
+
+        for id in staged.staged.iter().flatten() {
+            match staged.rows.get(id) {
+                Some(entity) => entities.rows.insert(*id, entity.clone()),
+                None => entities.rows.remove(id),
+            };
+        }
+    }
+
+    /// Entities, including deleted ones, for which `filter` holds.
+    pub fn find_where(&self, filter: impl Fn(&T) -> bool) -> Vec<T> {
+        self.entities().rows.values().filter(|e| filter(e)).cloned().collect()
+    }
+
+    /// Applies `change` to every entity, including deleted ones, returning how many it changed.
+    pub fn update_where(&self, change: impl Fn(&mut T) -> bool) -> u64 {
+        let mut entities = self.entities();
+        let changed: Vec<ID> = entities
+            .rows
+            .iter_mut()
+            .filter_map(|(id, e)| change(e).then_some(*id))
+            .collect();
+
+        for id in &changed {
+            entities.written(*id);
+        }
+
+        changed.len() as u64
+    }
+
+    fn entities(&self) -> MutexGuard<'_, Entities<T, ID>> {
+        self.entities.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T, ID> Entities<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    fn written(&mut self, id: ID) {
+        if let Some(staged) = &mut self.staged {
+            staged.insert(id);
+        }
+    }
+
+    fn find(&self, id: &ID) -> Option<&T> {
+        self.rows.get(id).filter(|e| !e.is_deleted())
+    }
+
+    fn check_unique(&self, entity: &T, id: Option<ID>) -> RepositoryResult<()> {
+        let keys = entity.unique_keys();
+        let taken = self
+            .rows
+            .iter()
+            .filter(|(other, e)| Some(**other) != id && !e.is_deleted())
+            .flat_map(|(_, e)| e.unique_keys())
+            .find(|key| keys.contains(key));
+
+        match taken {
+            Some((constraint, _)) => Err(RepositoryError::UniqueViolation(constraint.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn create(&mut self, entity: &T, now: NaiveDateTime) -> RepositoryResult<T> {
+        self.check_unique(entity, None)?;
+
+        let id = ID::try_from(self.last_id.fetch_add(1, Ordering::SeqCst) + 1)
+            .map_err(|_| RepositoryError::Query("No ids are left".to_string()))?;
+        let mut created = entity.clone();
+
+        created.created(id, now);
+        self.rows.insert(id, created.clone());
+        self.written(id);
+
+        Ok(created)
+    }
+
+    fn update(&mut self, entity: &T, now: NaiveDateTime) -> RepositoryResult<T> {
+        let id = entity.id().ok_or(RepositoryError::NotFound)?;
+        let stored = self
+            .find(&id)
+            .filter(|stored| entity.version().is_none() || entity.version() == stored.version())
+            .ok_or(RepositoryError::NotFound)?;
+        let mut updated = entity.clone();
+
+        updated.updated(stored, now);
+        self.check_unique(&updated, Some(id))?;
+        self.rows.insert(id, updated.clone());
+        self.written(id);
+
+        Ok(updated)
+    }
+
+    fn delete(&mut self, id: &ID, version: Option<i32>, now: NaiveDateTime) -> u64 {
+        let current = |e: &&mut T| !e.is_deleted() && (version.is_none() || version == e.version());
+
+        match self.rows.get_mut(id).filter(current) {
+            Some(entity) => {
+                entity.deleted(now);
+                self.written(*id);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, ID> ReadRepository<T, ID> for InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    async fn find_by_id(&self, id: &ID) -> RepositoryResult<T> {
+        self.entities().find(id).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_all(&self) -> RepositoryResult<Vec<T>> {
+        Ok(self.find_where(|e| !e.is_deleted()))
+    }
+
+    async fn find_page(&self, request: &PageRequest) -> RepositoryResult<Page<T>> {
+        let key = |e: &T| {
+            let created = if request.sort.is_by_created() { e.created_timestamp() } else { None };
+
+            (created, e.id().map(Into::into))
+        };
+        let mut items =
+            self.find_where(|e| (request.include_deleted || !e.is_deleted()) && e.matches(request));
+        let total = request.include_total.then_some(items.len() as i64);
+
+        if request.sort.is_descending() {
+            items.sort_by_key(|e| Reverse(key(e)));
+        } else {
+            items.sort_by_key(key);
+        }
+
+        if let Some(cursor) = &request.cursor {
+            let after = (cursor.created_timestamp, Some(i64::from(cursor.id)));
+
+            items.retain(|e| if request.sort.is_descending() { key(e) < after } else { key(e) > after });
+        }
+
+        let next_cursor = if items.len() > request.limit as usize {
+            items.truncate(request.limit as usize);
+            items.last().and_then(|e| {
+                Some(Cursor {
+                    sort: request.sort,
+                    id: i32::try_from(e.id()?.into()).ok()?,
+                    created_timestamp: if request.sort.is_by_created() {
+                        Some(e.created_timestamp()?)
+                    } else {
+                        None
+                    },
+                })
+            })
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items,
+            next_cursor: next_cursor.map(|c| c.encode()),
+            total,
+        })
+    }
+}
+
+#[async_trait]
+impl<T, ID> WriteRepository<T, ID> for InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    async fn create(&self, entity: &T) -> RepositoryResult<T> {
+        self.entities().create(entity, Utc::now().naive_utc())
+    }
+
+    async fn update(&self, entity: &T) -> RepositoryResult<T> {
+        self.entities().update(entity, Utc::now().naive_utc())
+    }
+
+    async fn delete_by_id(&self, id: &ID) -> RepositoryResult<u64> {
+        self.delete_by_id_at_version(id, None).await
+    }
+}
+
+#[async_trait]
+impl<T, ID> SoftDeleteRepository<T, ID> for InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    async fn find_by_id_including_deleted(&self, id: &ID) -> RepositoryResult<T> {
+        self.entities().rows.get(id).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_all_including_deleted(&self) -> RepositoryResult<Vec<T>> {
+        Ok(self.find_where(|_| true))
+    }
+
+    async fn purge_by_id(&self, id: &ID) -> RepositoryResult<u64> {
+        self.purge_by_id_at_version(id, None).await
+    }
+
+    async fn delete_by_id_at_version(&self, id: &ID, version: Option<i32>) -> RepositoryResult<u64> {
+        Ok(self.entities().delete(id, version, Utc::now().naive_utc()))
+    }
+
+    async fn purge_by_id_at_version(&self, id: &ID, version: Option<i32>) -> RepositoryResult<u64> {
+        let mut entities = self.entities();
+        let current = entities.rows.get(id).is_some_and(|e| version.is_none() || version == e.version());
+        let purged = current && entities.rows.remove(id).is_some();
+
+        if purged {
+            entities.written(*id);
+        }
+
+        Ok(u64::from(purged))
+    }
+}
+
+#[async_trait]
+impl<T, ID> BatchRepository<T, ID> for InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+    async fn find_all_by_id(&self, ids: &[ID]) -> RepositoryResult<Vec<T>> {
+        Ok(self.find_where(|e| !e.is_deleted() && e.id().is_some_and(|id| ids.contains(&id))))
+    }
+
+    async fn write_batch(&self, batch: &Batch<T, ID>) -> RepositoryResult<BatchOutcome<T, ID>> {
+        let now = Utc::now().naive_utc();
+        let mut entities = self.entities();
+        // writes go to a copy, which only replaces the entities once the whole batch applied
+        let mut staged = entities.clone();
+        let created = batch
+            .creates
+            .iter()
+            .map(|entity| staged.create(entity, now))
+            .collect::<RepositoryResult<Vec<_>>>()?;
+        let updated = batch
+            .updates
+            .iter()
+            .map(|entity| staged.update(entity, now))
+            .collect::<RepositoryResult<Vec<_>>>()?;
+
+        for id in &batch.deletes {
+            if staged.delete(id, None, now) == 0 {
+                return Err(RepositoryError::NotFound);
+            }
+        }
+
+        *entities = staged;
+
+        Ok(BatchOutcome {
+            created,
+            updated,
+            deleted: batch.deletes.clone(),
+        })
+    }
+}
+
+impl<T, ID> Repository<T, ID> for InMemoryRepository<T, ID>
+where
+    T: InMemoryEntity<ID>,
+    ID: EntityId,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::page::{PageQuery, Sort};
+    use crate::model::user::{User, UserStatus};
+    use crate::repository::{UserNameRepository, USER_NAME_KEY};
+
+    async fn create(repo: &InMemoryRepository<User, i32>, user_name: &str) -> User {
+        repo.create(&User::new(user_name)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_assigns_ids_and_timestamps() {
+        let repo = InMemoryRepository::new();
+        let foo = create(&repo, "foo").await;
+        let bar = create(&repo, "bar").await;
+
+        assert_eq!(foo.id, Some(1));
+        assert_eq!(bar.id, Some(2));
+        assert_eq!(foo.version, Some(1));
+        assert_eq!(foo.status, Some(UserStatus::Active));
+        assert!(foo.created_timestamp.is_some());
+        assert_eq!(repo.find_by_id(&1).await.unwrap().user_name, foo.user_name);
+        assert_eq!(repo.find_by_id(&3).await.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_user_name_is_unique() {
+        let repo = InMemoryRepository::new();
+        create(&repo, "foo").await;
+
+        let res = repo.create(&User::new("FOO")).await;
+
+        assert_eq!(res.err(), Some(RepositoryError::UniqueViolation(USER_NAME_KEY.to_string())));
+        assert_eq!(repo.find_by_user_name("Foo").await.unwrap().id, Some(1));
+
+        // deleted users give up their name
+        repo.delete_by_id(&1).await.unwrap();
+
+        assert_eq!(create(&repo, "foo").await.id, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_update_checks_version() {
+        let repo = InMemoryRepository::new();
+        let foo = create(&repo, "foo").await;
+        let renamed = User {
+            user_name: Some("bar".to_string()),
+            ..foo.clone()
+        };
+
+        let updated = repo.update(&renamed).await.unwrap();
+
+        assert_eq!(updated.version, Some(2));
+        assert_eq!(updated.created_timestamp, foo.created_timestamp);
+        assert_eq!(repo.update(&renamed).await.err(), Some(RepositoryError::NotFound));
+        assert!(repo.update(&User { version: None, ..renamed }).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_and_purge() {
+        let repo = InMemoryRepository::new();
+        create(&repo, "foo").await;
+
+        assert_eq!(repo.delete_by_id(&1).await, Ok(1));
+        assert_eq!(repo.delete_by_id(&1).await, Ok(0));
+        assert!(repo.find_by_id(&1).await.is_err());
+        assert!(repo.find_all().await.unwrap().is_empty());
+
+        let deleted = repo.find_by_id_including_deleted(&1).await.unwrap();
+
+        assert_eq!(deleted.status, Some(UserStatus::Deleted));
+        assert!(deleted.deleted_at.is_some());
+        assert_eq!(repo.purge_by_id(&1).await, Ok(1));
+        assert!(repo.find_all_including_deleted().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_checks_version() {
+        let repo = InMemoryRepository::new();
+        create(&repo, "foo").await;
+
+        assert_eq!(repo.delete_by_id_at_version(&1, Some(2)).await, Ok(0));
+        assert_eq!(repo.delete_by_id_at_version(&1, Some(1)).await, Ok(1));
+        assert_eq!(repo.purge_by_id_at_version(&1, Some(1)).await, Ok(0));
+        assert_eq!(repo.purge_by_id_at_version(&1, Some(2)).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_find_page() {
+        let repo = InMemoryRepository::new();
+
+        for user_name in ["a1", "a2", "a3", "b1"] {
+            create(&repo, user_name).await;
+        }
+
+        let query = PageQuery {
+            limit: Some(2),
+            sort: Some(Sort::IdDesc),
+            user_name: Some("a".to_string()),
+            include_total: Some(true),
+            ..PageQuery::default()
+        };
+        let first = repo.find_page(&PageRequest::from_query(&query).unwrap()).await.unwrap();
+        let names = |page: &Page<User>| -> Vec<String> {
+            page.items.iter().filter_map(|user| user.user_name.clone()).collect()
+        };
+
+        assert_eq!(names(&first), vec!["a3", "a2"]);
+        assert_eq!(first.total, Some(3));
+
+        let query = PageQuery {
+            cursor: first.next_cursor,
+            ..query
+        };
+        let second = repo.find_page(&PageRequest::from_query(&query).unwrap()).await.unwrap();
+
+        assert_eq!(names(&second), vec!["a1"]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_write_batch_applies_all_or_nothing() {
+        let repo = InMemoryRepository::new();
+        let foo = create(&repo, "foo").await;
+        let batch = Batch {
+            creates: vec![User::new("bar")],
+            updates: vec![],
+            deletes: vec![foo.id.unwrap(), 42],
+        };
+
+        assert_eq!(repo.write_batch(&batch).await.err(), Some(RepositoryError::NotFound));
+        assert_eq!(repo.find_all().await.unwrap().len(), 1);
+
+        let batch = Batch {
+            deletes: vec![foo.id.unwrap()],
+            ..batch
+        };
+        let outcome = repo.write_batch(&batch).await.unwrap();
+
+        // like a sequence, the id of the aborted create isn't handed out again
+        assert_eq!(outcome.created[0].id, Some(3));
+        assert_eq!(outcome.deleted, vec![1]);
+        assert_eq!(repo.find_all_by_id(&[1, 3]).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::model::auth::{LoginFailure, LoginScope};
+use crate::repository::RepositoryResult;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as, PgPool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub type ArcLoginFailureStore = Arc<dyn LoginFailureStore + Send + Sync>;
+
+#[async_trait]
+pub trait LoginFailureStore {
+    async fn find(&self, scope: LoginScope, subject: &str) -> RepositoryResult<Option<LoginFailure>>;
+
+    /// Counts a failed attempt at `now`, starting over from one when the previous failure happened
+    /// before `window_start`.
+    async fn record_failure(
+        &self,
+        scope: LoginScope,
+        subject: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> RepositoryResult<LoginFailure>;
+
+    async fn lock(&self, scope: LoginScope, subject: &str, until: NaiveDateTime) -> RepositoryResult<u64>;
+
+    /// Forgets every failed attempt, lifting any lock on `subject`.
+    async fn clear(&self, scope: LoginScope, subject: &str) -> RepositoryResult<u64>;
+}
+
+#[derive(Clone)]
+pub struct LoginFailureRepository {
+    pool: PgPool,
+}
+
+impl LoginFailureRepository {
+    pub fn new(pool: &PgPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+}
+
+#[async_trait]
+impl LoginFailureStore for LoginFailureRepository {
+    async fn find(&self, scope: LoginScope, subject: &str) -> RepositoryResult<Option<LoginFailure>> {
+        let query = query_as!(
+            LoginFailure,
+            "
+            select *
+            from login_failure
+            where scope = $1
+              and subject = $2
+        ",
+            scope.as_str(),
+            subject
+        );
+
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    async fn record_failure(
+        &self,
+        scope: LoginScope,
+        subject: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> RepositoryResult<LoginFailure> {
+        let query = query_as!(
+            LoginFailure,
+            "
+            insert into login_failure (scope, subject, failures, last_failure_timestamp)
+            values ($1, $2, 1, $3)
+            on conflict (scope, subject) do update
+            set failures = case
+                    when login_failure.last_failure_timestamp < $4 then 1
+                    else login_failure.failures + 1
+                end,
+                last_failure_timestamp = excluded.last_failure_timestamp
+            returning *
+        ",
+            scope.as_str(),
+            subject,
+            now,
+            window_start
+        );
+
+        Ok(query.fetch_one(&self.pool).await?)
+    }
+
+    async fn lock(&self, scope: LoginScope, subject: &str, until: NaiveDateTime) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            update login_failure
+            set locked_until = $3
+            where scope = $1
+              and subject = $2
+        ",
+            scope.as_str(),
+            subject,
+            until
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+
+    async fn clear(&self, scope: LoginScope, subject: &str) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            delete
+            from login_failure
+            where scope = $1
+              and subject = $2
+        ",
+            scope.as_str(),
+            subject
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryLoginFailureStore {
+    failures: Mutex<HashMap<(LoginScope, String), LoginFailure>>,
+}
+
+impl InMemoryLoginFailureStore {
+    fn failures(&self) -> MutexGuard<'_, HashMap<(LoginScope, String), LoginFailure>> {
+        self.failures.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl LoginFailureStore for InMemoryLoginFailureStore {
+    async fn find(&self, scope: LoginScope, subject: &str) -> RepositoryResult<Option<LoginFailure>> {
+        Ok(self.failures().get(&(scope, subject.to_string())).cloned())
+    }
+
+    async fn record_failure(
+        &self,
+        scope: LoginScope,
+        subject: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> RepositoryResult<LoginFailure> {
+        let mut failures = self.failures();
+        let failure = failures
+            .entry((scope, subject.to_string()))
+            .and_modify(|failure| {
+                failure.failures = if failure.last_failure_timestamp < window_start {
+                    1
+                } else {
+                    failure.failures + 1
+                };
+                failure.last_failure_timestamp = now;
+            })
+            .or_insert_with(|| LoginFailure {
+                scope: scope.as_str().to_string(),
+                subject: subject.to_string(),
+                failures: 1,
+                last_failure_timestamp: now,
+                locked_until: None,
+            });
+
+        Ok(failure.clone())
+    }
+
+    async fn lock(&self, scope: LoginScope, subject: &str, until: NaiveDateTime) -> RepositoryResult<u64> {
+        let mut failures = self.failures();
+        let failure = failures.get_mut(&(scope, subject.to_string()));
+
+        Ok(failure.map_or(0, |failure| {
+            failure.locked_until = Some(until);
+            1
+        }))
+    }
+
+    async fn clear(&self, scope: LoginScope, subject: &str) -> RepositoryResult<u64> {
+        Ok(u64::from(self.failures().remove(&(scope, subject.to_string())).is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[sqlx::test]
+    async fn test_record_failure(pool: PgPool) {
+        let repo = LoginFailureRepository::new(&pool);
+        let now = Utc::now().naive_utc();
+        let window_start = now - Duration::minutes(15);
+
+        repo.record_failure(LoginScope::User, "foo", now, window_start).await.unwrap();
+        let failure = repo.record_failure(LoginScope::User, "foo", now, window_start).await.unwrap();
+
+        assert_eq!(failure.failures, 2);
+        assert_eq!(failure.locked_until, None);
+
+        let other = repo.record_failure(LoginScope::Ip, "foo", now, window_start).await.unwrap();
+
+        assert_eq!(other.failures, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_failures_outside_window_start_over(pool: PgPool) {
+        let repo = LoginFailureRepository::new(&pool);
+        let earlier = Utc::now().naive_utc() - Duration::hours(1);
+        let now = Utc::now().naive_utc();
+
+        repo.record_failure(LoginScope::User, "foo", earlier, earlier).await.unwrap();
+        repo.record_failure(LoginScope::User, "foo", earlier, earlier).await.unwrap();
+        let failure = repo
+            .record_failure(LoginScope::User, "foo", now, now - Duration::minutes(15))
+            .await
+            .unwrap();
+
+        assert_eq!(failure.failures, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_lock_and_clear(pool: PgPool) {
+        let repo = LoginFailureRepository::new(&pool);
+        let now = Utc::now().naive_utc();
+
+        repo.record_failure(LoginScope::User, "foo", now, now).await.unwrap();
+
+        assert_eq!(repo.lock(LoginScope::User, "foo", now + Duration::minutes(15)).await.unwrap(), 1);
+        assert!(repo.find(LoginScope::User, "foo").await.unwrap().unwrap().locked_until.is_some());
+        assert_eq!(repo.clear(LoginScope::User, "foo").await.unwrap(), 1);
+        assert!(repo.find(LoginScope::User, "foo").await.unwrap().is_none());
+    }
+}
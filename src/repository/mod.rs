@@ -1,3 +1,21 @@
+mod audit_repository;
+mod database;
+mod in_memory_repository;
+mod login_failure_repository;
+mod refresh_token_repository;
+mod repository_error;
+mod revoked_token_repository;
+mod role_repository;
+mod unit_of_work;
 mod user_repository;
 pub mod repository_traits;
+pub use audit_repository::*;
+pub use database::*;
+pub use in_memory_repository::*;
+pub use login_failure_repository::*;
+pub use refresh_token_repository::*;
+pub use repository_error::*;
+pub use revoked_token_repository::*;
+pub use role_repository::*;
+pub use unit_of_work::*;
 pub use user_repository::*;
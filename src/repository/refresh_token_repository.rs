@@ -0,0 +1,285 @@
+use crate::model::auth::RefreshToken;
+use crate::repository::repository_traits::WriteRepository;
+use crate::repository::{InMemoryEntity, InMemoryRepository, RepositoryResult};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type ArcRefreshTokenStore = Arc<dyn RefreshTokenStore + Send + Sync>;
+
+#[async_trait]
+pub trait RefreshTokenStore {
+    async fn create(&self, token: &RefreshToken) -> RepositoryResult<RefreshToken>;
+
+    async fn find_by_hash(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>>;
+
+    /// Marks a token as used, returning it only if it was still valid beforehand. Two concurrent
+    /// calls for the same token can't both succeed.
+    async fn consume(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>>;
+
+    async fn revoke_family(&self, family_id: &Uuid) -> RepositoryResult<u64>;
+
+    async fn revoke_all_for_user(&self, user_id: i32) -> RepositoryResult<u64>;
+}
+
+#[derive(Clone)]
+pub struct RefreshTokenRepository {
+    pool: PgPool,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: &PgPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for RefreshTokenRepository {
+    async fn create(&self, token: &RefreshToken) -> RepositoryResult<RefreshToken> {
+        let query = query_as!(
+            RefreshToken,
+            "
+            insert into refresh_token (user_id, family_id, token_hash, expires_timestamp)
+            values ($1, $2, $3, $4)
+            returning *
+        ",
+            token.user_id,
+            token.family_id,
+            token.token_hash,
+            token.expires_timestamp
+        );
+        Ok(query.fetch_one(&self.pool).await?)
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>> {
+        let query = query_as!(
+            RefreshToken,
+            "
+            select *
+            from refresh_token
+            where token_hash = $1
+        ",
+            token_hash
+        );
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    /// The check and the update happen in a single statement.
+    async fn consume(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>> {
+        let query = query_as!(
+            RefreshToken,
+            "
+            update refresh_token
+            set used_timestamp = now()
+            where token_hash = $1
+              and used_timestamp is null
+              and revoked_timestamp is null
+              and expires_timestamp > now()
+            returning *
+        ",
+            token_hash
+        );
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    async fn revoke_family(&self, family_id: &Uuid) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            update refresh_token
+            set revoked_timestamp = now()
+            where family_id = $1
+              and revoked_timestamp is null
+        ",
+            family_id
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: i32) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            update refresh_token
+            set revoked_timestamp = now()
+            where user_id = $1
+              and revoked_timestamp is null
+        ",
+            user_id
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+}
+
+/// Refresh tokens are revoked rather than deleted.
+impl InMemoryEntity<i32> for RefreshToken {
+    fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    fn created(&mut self, id: i32, now: NaiveDateTime) {
+        self.id = Some(id);
+        self.created_timestamp = Some(now);
+    }
+
+    fn updated(&mut self, stored: &Self, _now: NaiveDateTime) {
+        self.created_timestamp = stored.created_timestamp;
+    }
+
+    fn deleted(&mut self, _now: NaiveDateTime) {}
+
+    fn is_deleted(&self) -> bool {
+        false
+    }
+
+    fn unique_keys(&self) -> Vec<(&'static str, String)> {
+        vec![("refresh_token_token_hash_key", self.token_hash.clone())]
+    }
+}
+
+fn revoke_where(
+    tokens: &InMemoryRepository<RefreshToken, i32>,
+    filter: impl Fn(&RefreshToken) -> bool,
+) -> u64 {
+    let now = Utc::now().naive_utc();
+
+    tokens.update_where(|token| {
+        let revoke = token.revoked_timestamp.is_none() && filter(token);
+
+        if revoke {
+            token.revoked_timestamp = Some(now);
+        }
+        revoke
+    })
+}
+
+#[async_trait]
+impl RefreshTokenStore for InMemoryRepository<RefreshToken, i32> {
+    async fn create(&self, token: &RefreshToken) -> RepositoryResult<RefreshToken> {
+        WriteRepository::create(self, token).await
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>> {
+        Ok(self.find_where(|token| token.token_hash == token_hash).pop())
+    }
+
+    async fn consume(&self, token_hash: &str) -> RepositoryResult<Option<RefreshToken>> {
+        let now = Utc::now().naive_utc();
+        let consumed = self.update_where(|token| {
+            let valid = token.token_hash == token_hash
+                && token.used_timestamp.is_none()
+                && token.revoked_timestamp.is_none()
+                && token.expires_timestamp > now;
+
+            if valid {
+                token.used_timestamp = Some(now);
+            }
+            valid
+        });
+
+        if consumed == 0 {
+            return Ok(None);
+        }
+
+        self.find_by_hash(token_hash).await
+    }
+
+    async fn revoke_family(&self, family_id: &Uuid) -> RepositoryResult<u64> {
+        Ok(revoke_where(self, |token| token.family_id == *family_id))
+    }
+
+    async fn revoke_all_for_user(&self, user_id: i32) -> RepositoryResult<u64> {
+        Ok(revoke_where(self, |token| token.user_id == user_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::user::User;
+    use crate::repository::repository_traits::WriteRepository;
+    use crate::repository::UserRepository;
+    use chrono::{Duration, Utc};
+
+    async fn user_id(pool: &PgPool) -> i32 {
+        let user = UserRepository::new(pool)
+            .create(&User::new("foo"))
+            .await
+            .expect("User could not be created");
+
+        user.id.unwrap()
+    }
+
+    fn token(user_id: i32, family_id: Uuid, token_hash: &str) -> RefreshToken {
+        let expires = Utc::now().naive_utc() + Duration::days(1);
+
+        RefreshToken::new(user_id, family_id, token_hash.to_string(), expires)
+    }
+
+    #[sqlx::test]
+    async fn test_create_token(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RefreshTokenRepository::new(&pool);
+        let token = repo
+            .create(&token(user_id, Uuid::new_v4(), "foo"))
+            .await
+            .expect("Token could not be created");
+
+        assert!(token.id.is_some());
+        assert_eq!(token.user_id, user_id);
+        assert!(token.used_timestamp.is_none());
+        assert!(token.revoked_timestamp.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_consume_token_once(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RefreshTokenRepository::new(&pool);
+        let _ = repo.create(&token(user_id, Uuid::new_v4(), "foo")).await;
+
+        assert!(repo.consume("foo").await.unwrap().is_some());
+        assert!(repo.consume("foo").await.unwrap().is_none());
+        assert!(repo.find_by_hash("foo").await.unwrap().unwrap().used_timestamp.is_some());
+    }
+
+    #[sqlx::test]
+    async fn test_consume_expired_token(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RefreshTokenRepository::new(&pool);
+        let expired = RefreshToken {
+            expires_timestamp: Utc::now().naive_utc() - Duration::days(1),
+            ..token(user_id, Uuid::new_v4(), "foo")
+        };
+        let _ = repo.create(&expired).await;
+
+        assert!(repo.consume("foo").await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_family(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let family_id = Uuid::new_v4();
+        let repo = RefreshTokenRepository::new(&pool);
+        let _ = repo.create(&token(user_id, family_id, "foo")).await;
+        let _ = repo.create(&token(user_id, family_id, "bar")).await;
+        let _ = repo.create(&token(user_id, Uuid::new_v4(), "baz")).await;
+
+        assert_eq!(repo.revoke_family(&family_id).await, Ok(2));
+        assert!(repo.consume("foo").await.unwrap().is_none());
+        assert!(repo.consume("bar").await.unwrap().is_none());
+        assert!(repo.consume("baz").await.unwrap().is_some());
+    }
+
+    #[sqlx::test]
+    async fn test_closed_pool(pool: PgPool) {
+        let repo = RefreshTokenRepository::new(&pool);
+        pool.close().await;
+
+        assert!(repo.create(&token(1, Uuid::new_v4(), "foo")).await.is_err());
+        assert!(repo.consume("foo").await.is_err());
+        assert!(repo.find_by_hash("foo").await.is_err());
+        assert!(repo.revoke_family(&Uuid::new_v4()).await.is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use sqlx::error::ErrorKind;
+use thiserror::Error;
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RepositoryError {
+    #[error("Entity not found")]
+    NotFound,
+
+    #[error("Unique constraint {0} violated")]
+    UniqueViolation(String),
+
+    #[error("Foreign key constraint {0} violated")]
+    ForeignKeyViolation(String),
+
+    #[error("Unable to reach the database: {0}")]
+    Connection(String),
+
+    #[error("Database operation timed out")]
+    Timeout,
+
+    #[error("Database query failed: {0}")]
+    Query(String),
+}
+
+impl RepositoryError {
+    /// Whether the database itself is unavailable, as opposed to the query being rejected.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, RepositoryError::Connection(_) | RepositoryError::Timeout)
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => RepositoryError::NotFound,
+            sqlx::Error::PoolTimedOut => RepositoryError::Timeout,
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => RepositoryError::Connection(error.to_string()),
+            sqlx::Error::Database(e) => {
+                let constraint = e.constraint().unwrap_or_default().to_string();
+
+                match (e.kind(), e.code().as_deref()) {
+                    (ErrorKind::UniqueViolation, _) => RepositoryError::UniqueViolation(constraint),
+                    (ErrorKind::ForeignKeyViolation, _) => {
+                        RepositoryError::ForeignKeyViolation(constraint)
+                    }
+                    // query_canceled, raised when statement_timeout is exceeded
+                    (_, Some("57014")) => RepositoryError::Timeout,
+                    // connection_exception class and admin/crash shutdowns
+                    (_, Some(code)) if code.starts_with("08") || code.starts_with("57P") => {
+                        RepositoryError::Connection(e.message().to_string())
+                    }
+                    _ => RepositoryError::Query(e.message().to_string()),
+                }
+            }
+            _ => RepositoryError::Query(error.to_string()),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::model::page::{Page, PageRequest};
+use crate::repository::RepositoryResult;
 use async_trait::async_trait;
 use std::sync::Arc;
 
@@ -5,24 +7,74 @@ pub type ArcRepository<T, ID> = Arc<dyn Repository<T, ID> + Send + Sync>;
 
 #[async_trait]
 pub trait ReadRepository<T, ID> {
-    async fn find_by_id(&self, id: &ID) -> Option<T>;
+    async fn find_by_id(&self, id: &ID) -> RepositoryResult<T>;
 
-    async fn find_all(&self) -> Vec<T>;
+    async fn find_all(&self) -> RepositoryResult<Vec<T>>;
+
+    async fn find_page(&self, request: &PageRequest) -> RepositoryResult<Page<T>>;
 }
 
 #[async_trait]
 pub trait WriteRepository<T, ID> {
-    async fn create(&self, entity: &T) -> Option<T>;
+    async fn create(&self, entity: &T) -> RepositoryResult<T>;
 
-    async fn update(&self, entity: &T) -> Option<T>;
+    async fn update(&self, entity: &T) -> RepositoryResult<T>;
 
-    async fn delete_by_id(&self, id: &ID) -> u64;
+    async fn delete_by_id(&self, id: &ID) -> RepositoryResult<u64>;
+}
+
+/// Repositories whose `delete_by_id` only marks entities as deleted. Deleted entities are left out of
+/// the `ReadRepository` methods and can only be found through these.
+#[async_trait]
+pub trait SoftDeleteRepository<T, ID> {
+    async fn find_by_id_including_deleted(&self, id: &ID) -> RepositoryResult<T>;
+
+    async fn find_all_including_deleted(&self) -> RepositoryResult<Vec<T>>;
+
+    /// Removes the entity for good, whether or not it was deleted before.
+    async fn purge_by_id(&self, id: &ID) -> RepositoryResult<u64>;
+
+    /// Like `WriteRepository::delete_by_id`, but with a `version` the entity must still be at it.
+    async fn delete_by_id_at_version(&self, id: &ID, version: Option<i32>) -> RepositoryResult<u64>;
+
+    /// Like `purge_by_id`, but with a `version` the entity must still be at it.
+    async fn purge_by_id_at_version(&self, id: &ID, version: Option<i32>) -> RepositoryResult<u64>;
+}
+
+/// Writes applied together by `BatchRepository::write_batch`.
+pub struct Batch<T, ID> {
+    pub creates: Vec<T>,
+    pub updates: Vec<T>,
+    pub deletes: Vec<ID>,
+}
+
+/// Result of `BatchRepository::write_batch`, with `created` in the order of `Batch::creates`.
+pub struct BatchOutcome<T, ID> {
+    pub created: Vec<T>,
+    pub updated: Vec<T>,
+    pub deleted: Vec<ID>,
+}
+
+/// Reads and writes many entities with one statement per kind of operation, rather than a round
+/// trip per entity.
+#[async_trait]
+pub trait BatchRepository<T, ID> {
+    /// Finds the entities that exist among `ids`, leaving out missing and deleted ones.
+    async fn find_all_by_id(&self, ids: &[ID]) -> RepositoryResult<Vec<T>>;
+
+    /// Applies the whole batch in one transaction, which is rolled back with
+    /// `RepositoryError::NotFound` unless every update and delete found its entity, at the version
+    /// of the update when it has one.
+    async fn write_batch(&self, batch: &Batch<T, ID>) -> RepositoryResult<BatchOutcome<T, ID>>;
 }
 
 #[cfg(test)]
 #[async_trait]
 pub trait TruncateRepository {
-    async fn truncate(&self) -> u64;
+    async fn truncate(&self) -> RepositoryResult<u64>;
 }
 
-pub trait Repository<T, ID>: ReadRepository<T, ID> + WriteRepository<T, ID> {}
+pub trait Repository<T, ID>:
+    ReadRepository<T, ID> + WriteRepository<T, ID> + SoftDeleteRepository<T, ID> + BatchRepository<T, ID>
+{
+}
@@ -0,0 +1,238 @@
+use crate::repository::RepositoryResult;
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_scalar, PgPool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use uuid::Uuid;
+
+pub type ArcRevokedTokenStore = Arc<dyn RevokedTokenStore + Send + Sync>;
+
+#[async_trait]
+pub trait RevokedTokenStore {
+    async fn revoke(&self, jti: &Uuid, user_id: i32, expires: NaiveDateTime) -> RepositoryResult<u64>;
+
+    /// Revokes every token of `user_id` issued up to `revoked_before`, which is kept to the
+    /// microsecond. Tokens issued afterwards, such as the token of a login right after, stay valid.
+    async fn revoke_user_sessions(&self, user_id: i32, revoked_before: NaiveDateTime) -> RepositoryResult<u64>;
+
+    async fn is_revoked(&self, jti: &Uuid, user_id: i32, issued: NaiveDateTime) -> RepositoryResult<bool>;
+
+    async fn purge_expired(&self) -> RepositoryResult<u64>;
+}
+
+#[derive(Clone)]
+pub struct RevokedTokenRepository {
+    pool: PgPool,
+}
+
+impl RevokedTokenRepository {
+    pub fn new(pool: &PgPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+}
+
+#[async_trait]
+impl RevokedTokenStore for RevokedTokenRepository {
+    async fn revoke(&self, jti: &Uuid, user_id: i32, expires: NaiveDateTime) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            insert into revoked_token (jti, user_id, expires_timestamp)
+            values ($1, $2, $3)
+            on conflict (jti) do nothing
+        ",
+            jti,
+            user_id,
+            expires
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: i32, revoked_before: NaiveDateTime) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            insert into user_session_revocation (user_id, revoked_timestamp)
+            values ($1, $2)
+            on conflict (user_id) do update
+            set revoked_timestamp = excluded.revoked_timestamp
+        ",
+            user_id,
+            revoked_before
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+
+    async fn is_revoked(&self, jti: &Uuid, user_id: i32, issued: NaiveDateTime) -> RepositoryResult<bool> {
+        let query = query_scalar!(
+            r#"
+            select (
+                exists(select 1 from revoked_token where jti = $1)
+                or exists(
+                    select 1
+                    from user_session_revocation
+                    where user_id = $2
+                      and revoked_timestamp >= $3
+                )
+            ) as "revoked!"
+        "#,
+            jti,
+            user_id,
+            issued
+        );
+
+        Ok(query.fetch_one(&self.pool).await?)
+    }
+
+    async fn purge_expired(&self) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            delete
+            from revoked_token
+            where expires_timestamp < now()
+        "
+        );
+
+        Ok(query.execute(&self.pool).await?.rows_affected())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryRevokedTokenStore {
+    revocations: Mutex<Revocations>,
+}
+
+#[derive(Default)]
+struct Revocations {
+    tokens: HashMap<Uuid, NaiveDateTime>,
+    sessions: HashMap<i32, NaiveDateTime>,
+}
+
+impl InMemoryRevokedTokenStore {
+    fn revocations(&self) -> MutexGuard<'_, Revocations> {
+        self.revocations.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RevokedTokenStore for InMemoryRevokedTokenStore {
+    async fn revoke(&self, jti: &Uuid, _user_id: i32, expires: NaiveDateTime) -> RepositoryResult<u64> {
+        let mut revocations = self.revocations();
+
+        if revocations.tokens.contains_key(jti) {
+            return Ok(0);
+        }
+
+        revocations.tokens.insert(*jti, expires);
+        Ok(1)
+    }
+
+    async fn revoke_user_sessions(&self, user_id: i32, revoked_before: NaiveDateTime) -> RepositoryResult<u64> {
+        self.revocations().sessions.insert(user_id, revoked_before);
+        Ok(1)
+    }
+
+    async fn is_revoked(&self, jti: &Uuid, user_id: i32, issued: NaiveDateTime) -> RepositoryResult<bool> {
+        let revocations = self.revocations();
+        let session_revoked = revocations
+            .sessions
+            .get(&user_id)
+            .is_some_and(|revoked| *revoked >= issued);
+
+        Ok(revocations.tokens.contains_key(jti) || session_revoked)
+    }
+
+    async fn purge_expired(&self) -> RepositoryResult<u64> {
+        let now = Utc::now().naive_utc();
+        let mut revocations = self.revocations();
+        let count = revocations.tokens.len();
+
+        revocations.tokens.retain(|_, expires| *expires >= now);
+        Ok((count - revocations.tokens.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::user::User;
+    use crate::repository::repository_traits::{SoftDeleteRepository, WriteRepository};
+    use crate::repository::UserRepository;
+    use chrono::{Duration, SubsecRound, Utc};
+
+    async fn user_id(pool: &PgPool) -> i32 {
+        let user = UserRepository::new(pool)
+            .create(&User::new("foo"))
+            .await
+            .expect("User could not be created");
+
+        user.id.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_token(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RevokedTokenRepository::new(&pool);
+        let now = Utc::now().naive_utc();
+        let jti = Uuid::new_v4();
+
+        assert_eq!(repo.is_revoked(&jti, user_id, now).await, Ok(false));
+        assert_eq!(repo.revoke(&jti, user_id, now + Duration::minutes(15)).await, Ok(1));
+        assert_eq!(repo.is_revoked(&jti, user_id, now).await, Ok(true));
+        assert_eq!(repo.is_revoked(&Uuid::new_v4(), user_id, now).await, Ok(false));
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_user_sessions(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RevokedTokenRepository::new(&pool);
+        let now = Utc::now().naive_utc().trunc_subsecs(6);
+        let jti = Uuid::new_v4();
+
+        repo.revoke_user_sessions(user_id, now).await.unwrap();
+
+        assert_eq!(repo.is_revoked(&jti, user_id, now - Duration::minutes(1)).await, Ok(true));
+        assert_eq!(repo.is_revoked(&jti, user_id, now + Duration::minutes(1)).await, Ok(false));
+        assert_eq!(repo.is_revoked(&jti, user_id, now - Duration::microseconds(1)).await, Ok(true));
+        assert_eq!(repo.is_revoked(&jti, user_id, now + Duration::microseconds(1)).await, Ok(false));
+    }
+
+    #[sqlx::test]
+    async fn test_revocations_outlive_purged_user(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RevokedTokenRepository::new(&pool);
+        let now = Utc::now().naive_utc().trunc_subsecs(6);
+        let jti = Uuid::new_v4();
+
+        repo.revoke(&jti, user_id, now + Duration::minutes(15)).await.unwrap();
+        repo.revoke_user_sessions(user_id, now).await.unwrap();
+        UserRepository::new(&pool).purge_by_id(&user_id).await.unwrap();
+
+        assert_eq!(repo.is_revoked(&jti, user_id, now + Duration::minutes(1)).await, Ok(true));
+        assert_eq!(repo.is_revoked(&Uuid::new_v4(), user_id, now).await, Ok(true));
+    }
+
+    #[sqlx::test]
+    async fn test_purge_expired(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RevokedTokenRepository::new(&pool);
+        let now = Utc::now().naive_utc();
+
+        repo.revoke(&Uuid::new_v4(), user_id, now - Duration::minutes(1)).await.unwrap();
+        repo.revoke(&Uuid::new_v4(), user_id, now + Duration::minutes(15)).await.unwrap();
+
+        assert_eq!(repo.purge_expired().await, Ok(1));
+    }
+
+    #[sqlx::test]
+    async fn test_closed_pool(pool: PgPool) {
+        let repo = RevokedTokenRepository::new(&pool);
+        let now = Utc::now().naive_utc();
+        pool.close().await;
+
+        assert!(repo.revoke(&Uuid::new_v4(), 1, now).await.is_err());
+        assert!(repo.revoke_user_sessions(1, now).await.is_err());
+        assert!(repo.is_revoked(&Uuid::new_v4(), 1, now).await.is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::model::user::User;
+use crate::repository::{
+    Database, InMemoryRepository, RepositoryError, RepositoryResult, SharedTransaction,
+};
+use async_trait::async_trait;
+use sqlx::{query, query_scalar, PgPool};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub type ArcUserRoleRepository = Arc<dyn UserRoleRepository + Send + Sync>;
+
+pub type ArcRolePermissionRepository = Arc<dyn RolePermissionRepository + Send + Sync>;
+
+#[async_trait]
+pub trait UserRoleRepository {
+    async fn find_roles_by_user_id(&self, user_id: &i32) -> RepositoryResult<Vec<String>>;
+
+    async fn assign_role(&self, user_id: &i32, role: &str) -> RepositoryResult<u64>;
+
+    /// Assigns `role` to every user of `user_ids` with a single statement.
+    async fn assign_role_to_all(&self, user_ids: &[i32], role: &str) -> RepositoryResult<u64>;
+}
+
+#[async_trait]
+pub trait RolePermissionRepository {
+    /// Lists every permission granted by a role as `(role, permission)` pairs.
+    async fn find_role_permissions(&self) -> RepositoryResult<Vec<(String, String)>>;
+}
+
+#[derive(Clone)]
+pub struct RoleRepository {
+    db: Database,
+}
+
+impl RoleRepository {
+    pub fn new(pool: &PgPool) -> Self {
+        Self {
+            db: Database::Pool(pool.clone()),
+        }
+    }
+
+    /// Runs the queries of the repository on the transaction of a unit of work.
+    pub fn in_transaction(transaction: &SharedTransaction) -> Self {
+        Self {
+            db: Database::Transaction(transaction.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl RolePermissionRepository for RoleRepository {
+    async fn find_role_permissions(&self) -> RepositoryResult<Vec<(String, String)>> {
+        let query = query!(
+            "
+            select r.name as role, p.name as permission
+            from role r
+                     join role_permission rp on rp.role_id = r.id
+                     join permission p on p.id = rp.permission_id
+        "
+        );
+        let rows = query.fetch_all(&mut *self.db.acquire().await?).await?;
+
+        Ok(rows.into_iter().map(|r| (r.role, r.permission)).collect())
+    }
+}
+
+#[async_trait]
+impl UserRoleRepository for RoleRepository {
+    async fn find_roles_by_user_id(&self, user_id: &i32) -> RepositoryResult<Vec<String>> {
+        let query = query_scalar!(
+            "
+            select r.name
+            from role r
+                     join user_role ur on ur.role_id = r.id
+            where ur.user_id = $1
+            order by r.name
+        ",
+            user_id
+        );
+
+        query.fetch_all(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    async fn assign_role(&self, user_id: &i32, role: &str) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            insert into user_role (user_id, role_id)
+            select $1, id
+            from role
+            where name = $2
+            on conflict do nothing
+        ",
+            user_id,
+            role
+        );
+
+        query
+            .execute(&mut *self.db.acquire().await?)
+            .await
+            .map(|r| r.rows_affected())
+            .map_err(RepositoryError::from)
+    }
+
+    async fn assign_role_to_all(&self, user_ids: &[i32], role: &str) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            insert into user_role (user_id, role_id)
+            select batch.user_id, r.id
+            from unnest($1::int4[]) as batch (user_id)
+                     cross join role r
+            where r.name = $2
+            on conflict do nothing
+        ",
+            user_ids,
+            role
+        );
+
+        query
+            .execute(&mut *self.db.acquire().await?)
+            .await
+            .map(|r| r.rows_affected())
+            .map_err(RepositoryError::from)
+    }
+}
+
+/// Permissions of the roles created by the migrations.
+const DEFAULT_ROLE_PERMISSIONS: [(&str, &[&str]); 2] = [
+    ("admin", &["user:create", "user:delete", "user:read", "user:unlock", "user:update"]),
+    ("user", &["user:read"]),
+];
+
+/// Name of the foreign key from `user_role` to `user_account`.
+const USER_ROLE_USER_KEY: &str = "user_role_user_id_fkey";
+
+/// Keeps the roles created by the migrations in memory, along with the roles assigned to the users
+/// of an `InMemoryRepository`.
+#[derive(Clone)]
+pub struct InMemoryRoleRepository {
+    users: InMemoryRepository<User, i32>,
+    roles: Arc<Mutex<RoleAssignments>>,
+}
+
+#[derive(Clone)]
+struct RoleAssignments {
+    permissions: BTreeMap<String, BTreeSet<String>>,
+    user_roles: BTreeSet<(i32, String)>,
+    /// Roles assigned since `stage`, only kept by staged copies.
+    staged: Option<BTreeSet<(i32, String)>>,
+}
+
+impl InMemoryRoleRepository {
+    pub fn new(users: &InMemoryRepository<User, i32>) -> Self {
+        let permissions = DEFAULT_ROLE_PERMISSIONS
+            .iter()
+            .map(|(role, permissions)| {
+                (role.to_string(), permissions.iter().map(|p| p.to_string()).collect())
+            })
+            .collect();
+
+        Self {
+            users: users.clone(),
+            roles: Arc::new(Mutex::new(RoleAssignments {
+                permissions,
+                user_roles: BTreeSet::new(),
+                staged: None,
+            })),
+        }
+    }
+
+    /// Copies the current role assignments into a repository of their own, assigning roles to the
+    /// users of `users` and keeping track of them for `apply_staged`.
+    pub fn stage(&self, users: &InMemoryRepository<User, i32>) -> Self {
+        let roles = RoleAssignments {
+            staged: Some(BTreeSet::new()),
+            ..self.roles().clone()
+        };
+
+        Self {
+            users: users.clone(),
+            roles: Arc::new(Mutex::new(roles)),
+        }
+    }
+
+    /// Assigns the roles assigned in `staged`, a repository from `stage`.
+    pub fn apply_staged(&self, staged: &Self) {
+        let assigned = staged.roles().staged.clone().unwrap_or_default();
+
+        self.roles().user_roles.extend(assigned);
+    }
+
+    fn roles(&self) -> MutexGuard<'_, RoleAssignments> {
+        self.roles.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RolePermissionRepository for InMemoryRoleRepository {
+    async fn find_role_permissions(&self) -> RepositoryResult<Vec<(String, String)>> {
+        let roles = self.roles();
+
+        Ok(roles
+            .permissions
+            .iter()
+            .flat_map(|(role, permissions)| permissions.iter().map(|p| (role.clone(), p.clone())))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl UserRoleRepository for InMemoryRoleRepository {
+    async fn find_roles_by_user_id(&self, user_id: &i32) -> RepositoryResult<Vec<String>> {
+        let roles = self.roles();
+
+        Ok(roles
+            .user_roles
+            .iter()
+            .filter(|(id, _)| id == user_id)
+            .map(|(_, role)| role.clone())
+            .collect())
+    }
+
+    async fn assign_role(&self, user_id: &i32, role: &str) -> RepositoryResult<u64> {
+        self.assign_role_to_all(&[*user_id], role).await
+    }
+
+    async fn assign_role_to_all(&self, user_ids: &[i32], role: &str) -> RepositoryResult<u64> {
+        let existing = self
+            .users
+            .find_where(|user| user.id.is_some_and(|id| user_ids.contains(&id)));
+
+        if user_ids.iter().any(|id| !existing.iter().any(|user| user.id == Some(*id))) {
+            return Err(RepositoryError::ForeignKeyViolation(USER_ROLE_USER_KEY.to_string()));
+        }
+
+        let mut roles = self.roles();
+
+        if !roles.permissions.contains_key(role) {
+            return Ok(0);
+        }
+
+        let mut assigned = 0;
+
+        for id in user_ids {
+            if roles.user_roles.insert((*id, role.to_string())) {
+                if let Some(staged) = &mut roles.staged {
+                    staged.insert((*id, role.to_string()));
+                }
+                assigned += 1;
+            }
+        }
+
+        Ok(assigned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::user::User;
+    use crate::repository::repository_traits::WriteRepository;
+    use crate::repository::UserRepository;
+
+    async fn user_id(pool: &PgPool) -> i32 {
+        let user = UserRepository::new(pool)
+            .create(&User::new("foo"))
+            .await
+            .expect("User could not be created");
+
+        user.id.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_assign_role(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RoleRepository::new(&pool);
+
+        assert_eq!(repo.assign_role(&user_id, "user").await, Ok(1));
+        assert_eq!(repo.assign_role(&user_id, "user").await, Ok(0));
+        assert_eq!(repo.assign_role(&user_id, "admin").await, Ok(1));
+        assert_eq!(
+            repo.find_roles_by_user_id(&user_id).await,
+            Ok(vec!["admin".to_string(), "user".to_string()])
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_assign_missing_role(pool: PgPool) {
+        let user_id = user_id(&pool).await;
+        let repo = RoleRepository::new(&pool);
+
+        assert_eq!(repo.assign_role(&user_id, "foo").await, Ok(0));
+        assert!(repo.find_roles_by_user_id(&user_id).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_assign_role_to_missing_user(pool: PgPool) {
+        let repo = RoleRepository::new(&pool);
+        let res = repo.assign_role(&23423423, "user").await;
+
+        assert!(matches!(res, Err(RepositoryError::ForeignKeyViolation(_))));
+    }
+
+    #[sqlx::test]
+    async fn test_assign_role_to_all(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let foo = repo.create(&User::new("foo")).await.unwrap().id.unwrap();
+        let bar = repo.create(&User::new("bar")).await.unwrap().id.unwrap();
+        let repo = RoleRepository::new(&pool);
+
+        assert_eq!(repo.assign_role(&foo, "user").await, Ok(1));
+        assert_eq!(repo.assign_role_to_all(&[foo, bar], "user").await, Ok(1));
+        assert_eq!(repo.find_roles_by_user_id(&bar).await, Ok(vec!["user".to_string()]));
+    }
+
+    #[sqlx::test]
+    async fn test_find_role_permissions(pool: PgPool) {
+        let repo = RoleRepository::new(&pool);
+        let permissions = repo.find_role_permissions().await.unwrap();
+
+        assert!(permissions.contains(&("admin".to_string(), "user:delete".to_string())));
+        assert!(permissions.contains(&("user".to_string(), "user:read".to_string())));
+        assert!(!permissions.contains(&("user".to_string(), "user:delete".to_string())));
+    }
+}
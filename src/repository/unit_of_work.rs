@@ -0,0 +1,284 @@
+use crate::model::audit::AuditEntry;
+use crate::model::user::User;
+use crate::repository::repository_traits::ArcRepository;
+use crate::repository::{
+    ArcAuditRecorder, ArcUserRoleRepository, AuditRepository, InMemoryRepository,
+    InMemoryRoleRepository, RepositoryError, RepositoryResult, RoleRepository, SharedTransaction,
+    UserRepository,
+};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+pub type ArcUnitOfWorkFactory = Arc<dyn UnitOfWorkFactory + Send + Sync>;
+
+pub type BoxUnitOfWork = Box<dyn UnitOfWork + Send + Sync>;
+
+#[async_trait]
+pub trait UnitOfWorkFactory {
+    async fn begin(&self) -> RepositoryResult<BoxUnitOfWork>;
+}
+
+/// Repositories whose writes are applied together by `commit`. Dropping the unit of work without
+/// committing it rolls the writes back, but `rollback` reports whether that succeeded.
+#[async_trait]
+pub trait UnitOfWork {
+    fn users(&self) -> ArcRepository<User, i32>;
+
+    fn roles(&self) -> ArcUserRoleRepository;
+
+    fn audit(&self) -> ArcAuditRecorder;
+
+    async fn commit(self: Box<Self>) -> RepositoryResult<()>;
+
+    async fn rollback(self: Box<Self>) -> RepositoryResult<()>;
+}
+
+/// Starts units of work on a database transaction.
+#[derive(Clone)]
+pub struct PgUnitOfWorkFactory {
+    pool: PgPool,
+}
+
+impl PgUnitOfWorkFactory {
+    pub fn new(pool: &PgPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+}
+
+#[async_trait]
+impl UnitOfWorkFactory for PgUnitOfWorkFactory {
+    async fn begin(&self) -> RepositoryResult<BoxUnitOfWork> {
+        let transaction = Arc::new(Mutex::new(Some(self.pool.begin().await?)));
+
+        Ok(Box::new(PgUnitOfWork {
+            users: Arc::new(UserRepository::in_transaction(&transaction)),
+            roles: Arc::new(RoleRepository::in_transaction(&transaction)),
+            audit: Arc::new(AuditRepository::in_transaction(&transaction)),
+            transaction,
+        }))
+    }
+}
+
+pub struct PgUnitOfWork {
+    transaction: SharedTransaction,
+    users: Arc<UserRepository>,
+    roles: Arc<RoleRepository>,
+    audit: Arc<AuditRepository>,
+}
+
+impl PgUnitOfWork {
+    async fn finish(&self) -> RepositoryResult<sqlx::Transaction<'static, sqlx::Postgres>> {
+        self.transaction
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| RepositoryError::Query("The transaction has already finished".to_string()))
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for PgUnitOfWork {
+    fn users(&self) -> ArcRepository<User, i32> {
+        self.users.clone()
+    }
+
+    fn roles(&self) -> ArcUserRoleRepository {
+        self.roles.clone()
+    }
+
+    fn audit(&self) -> ArcAuditRecorder {
+        self.audit.clone()
+    }
+
+    async fn commit(self: Box<Self>) -> RepositoryResult<()> {
+        Ok(self.finish().await?.commit().await?)
+    }
+
+    async fn rollback(self: Box<Self>) -> RepositoryResult<()> {
+        Ok(self.finish().await?.rollback().await?)
+    }
+}
+
+/// Starts units of work on in-memory repositories, one at a time.
+#[derive(Clone)]
+pub struct InMemoryUnitOfWorkFactory {
+    users: InMemoryRepository<User, i32>,
+    roles: InMemoryRoleRepository,
+    audit: InMemoryRepository<AuditEntry, i32>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl InMemoryUnitOfWorkFactory {
+    pub fn new(
+        users: &InMemoryRepository<User, i32>,
+        roles: &InMemoryRoleRepository,
+        audit: &InMemoryRepository<AuditEntry, i32>,
+    ) -> Self {
+        Self {
+            users: users.clone(),
+            roles: roles.clone(),
+            audit: audit.clone(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+#[async_trait]
+impl UnitOfWorkFactory for InMemoryUnitOfWorkFactory {
+    async fn begin(&self) -> RepositoryResult<BoxUnitOfWork> {
+        let guard = self.lock.clone().lock_owned().await;
+        let users = self.users.stage();
+
+        Ok(Box::new(InMemoryUnitOfWork {
+            roles: self.roles.stage(&users),
+            audit: self.audit.stage(),
+            users,
+            repositories: self.clone(),
+            _guard: guard,
+        }))
+    }
+}
+
+/// Stages writes on copies of the repositories taken at `begin`, which `commit` carries over to
+/// the repositories while rolling back drops them. Writes made outside of the unit of work in the
+/// meantime are kept, but unique keys are only checked against the copies.
+pub struct InMemoryUnitOfWork {
+    repositories: InMemoryUnitOfWorkFactory,
+    users: InMemoryRepository<User, i32>,
+    roles: InMemoryRoleRepository,
+    audit: InMemoryRepository<AuditEntry, i32>,
+    _guard: OwnedMutexGuard<()>,
+}
+
+#[async_trait]
+impl UnitOfWork for InMemoryUnitOfWork {
+    fn users(&self) -> ArcRepository<User, i32> {
+        Arc::new(self.users.clone())
+    }
+
+    fn roles(&self) -> ArcUserRoleRepository {
+        Arc::new(self.roles.clone())
+    }
+
+    fn audit(&self) -> ArcAuditRecorder {
+        Arc::new(self.audit.clone())
+    }
+
+    async fn commit(self: Box<Self>) -> RepositoryResult<()> {
+        self.repositories.users.apply_staged(&self.users);
+        self.repositories.roles.apply_staged(&self.roles);
+        self.repositories.audit.apply_staged(&self.audit);
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> RepositoryResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repository_traits::{ReadRepository, WriteRepository};
+    use crate::repository::{UserNameRepository, UserRoleRepository};
+
+    async fn create_foo(work: &BoxUnitOfWork) -> i32 {
+        let user = work.users().create(&User::new("foo")).await.unwrap();
+        let id = user.id.unwrap();
+
+        work.roles().assign_role(&id, "user").await.unwrap();
+        work.audit()
+            .record(&AuditEntry::new("user_created", "foo", None, None))
+            .await
+            .unwrap();
+        id
+    }
+
+    #[sqlx::test]
+    async fn test_commit(pool: PgPool) {
+        let work = PgUnitOfWorkFactory::new(&pool).begin().await.unwrap();
+        let id = create_foo(&work).await;
+
+        assert!(work.users().find_by_id(&id).await.is_ok());
+        assert!(UserRepository::new(&pool).find_by_id(&id).await.is_err());
+
+        work.commit().await.unwrap();
+
+        assert!(UserRepository::new(&pool).find_by_id(&id).await.is_ok());
+        assert_eq!(RoleRepository::new(&pool).find_roles_by_user_id(&id).await, Ok(vec!["user".to_string()]));
+        assert_eq!(AuditRepository::new(&pool).find_by_subject("foo").await.unwrap().len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn test_rollback(pool: PgPool) {
+        let factory = PgUnitOfWorkFactory::new(&pool);
+        let work = factory.begin().await.unwrap();
+        let users = work.users();
+
+        create_foo(&work).await;
+        work.rollback().await.unwrap();
+
+        assert!(AuditRepository::new(&pool).find_by_subject("foo").await.unwrap().is_empty());
+        assert!(users.find_all().await.is_err());
+
+        // dropping the unit of work rolls back as well
+        create_foo(&factory.begin().await.unwrap()).await;
+
+        assert_eq!(
+            UserRepository::new(&pool).find_by_user_name("foo").await.err(),
+            Some(RepositoryError::NotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_rollback() {
+        let users = InMemoryRepository::new();
+        let roles = InMemoryRoleRepository::new(&users);
+        let audit = InMemoryRepository::new();
+        let factory = InMemoryUnitOfWorkFactory::new(&users, &roles, &audit);
+
+        // dropping the unit of work leaves the repositories as they were
+        create_foo(&factory.begin().await.unwrap()).await;
+
+        assert!(users.find_all().await.unwrap().is_empty());
+        assert!(audit.find_where(|_| true).is_empty());
+
+        let work = factory.begin().await.unwrap();
+        let id = create_foo(&work).await;
+
+        assert!(users.find_by_id(&id).await.is_err());
+
+        work.commit().await.unwrap();
+
+        assert_eq!(users.find_by_user_name("foo").await.unwrap().id, Some(id));
+        assert_eq!(roles.find_roles_by_user_id(&id).await, Ok(vec!["user".to_string()]));
+        assert_eq!(audit.find_where(|entry| entry.subject == "foo").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_keeps_outside_writes() {
+        let users = InMemoryRepository::new();
+        let roles = InMemoryRoleRepository::new(&users);
+        let audit = InMemoryRepository::new();
+        let factory = InMemoryUnitOfWorkFactory::new(&users, &roles, &audit);
+
+        let work = factory.begin().await.unwrap();
+        let bar = users.create(&User::new("bar")).await.unwrap().id.unwrap();
+
+        create_foo(&work).await;
+        work.rollback().await.unwrap();
+
+        assert!(users.find_by_id(&bar).await.is_ok());
+
+        let work = factory.begin().await.unwrap();
+        let baz = users.create(&User::new("baz")).await.unwrap().id.unwrap();
+        let foo = create_foo(&work).await;
+
+        work.commit().await.unwrap();
+
+        assert_ne!(foo, baz);
+        assert_eq!(users.find_all().await.unwrap().len(), 3);
+    }
+}
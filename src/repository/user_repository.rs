@@ -1,115 +1,530 @@
-use crate::model::user::User;
-use crate::repository::repository_traits::{ReadRepository, Repository, WriteRepository};
+use crate::model::page::{Cursor, Page, PageRequest, Sort};
+use crate::model::user::{User, UserStatus};
+use crate::repository::repository_traits::{
+    Batch, BatchOutcome, BatchRepository, ReadRepository, Repository, SoftDeleteRepository,
+    WriteRepository,
+};
+use crate::repository::{
+    Database, InMemoryEntity, InMemoryRepository, RepositoryError, RepositoryResult,
+    SharedTransaction,
+};
 use async_trait::async_trait;
-use sqlx::{query_as, PgPool};
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as, query_scalar, Connection, PgPool, Postgres, QueryBuilder};
+use std::sync::Arc;
+
+pub type ArcUserNameRepository = Arc<dyn UserNameRepository + Send + Sync>;
+
+/// Name of the unique index on `lower(user_name)`, reported by `RepositoryError::UniqueViolation`
+/// when a user name is already taken.
+pub const USER_NAME_KEY: &str = "user_account_user_name_key";
+
+/// Name of the unique index on `lower(email)`.
+pub const USER_EMAIL_KEY: &str = "user_account_email_key";
+
+#[async_trait]
+pub trait UserNameRepository {
+    /// Finds the user that is not deleted with the given name, ignoring case.
+    async fn find_by_user_name(&self, user_name: &str) -> RepositoryResult<User>;
+}
 
 #[derive(Clone)]
 pub struct UserRepository {
-    pool: PgPool,
+    db: Database,
 }
 
 impl UserRepository {
     pub fn new(pool: &PgPool) -> Self {
-        Self { pool: pool.clone() }
+        Self {
+            db: Database::Pool(pool.clone()),
+        }
+    }
+
+    /// Runs the queries of the repository on the transaction of a unit of work.
+    pub fn in_transaction(transaction: &SharedTransaction) -> Self {
+        Self {
+            db: Database::Transaction(transaction.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl UserNameRepository for UserRepository {
+    async fn find_by_user_name(&self, user_name: &str) -> RepositoryResult<User> {
+        let query = query_as!(
+            User,
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
+            from user_account
+            where lower(user_name) = lower($1)
+              and deleted_at is null
+        "#,
+            user_name
+        );
+
+        query.fetch_one(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
     }
 }
 
 #[async_trait]
 impl ReadRepository<User, i32> for UserRepository {
-    async fn find_by_id(&self, id: &i32) -> Option<User> {
+    async fn find_by_id(&self, id: &i32) -> RepositoryResult<User> {
         let query = query_as!(
             User,
-            "
-            select *
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
             from user_account
             where id = $1
-        ",
+              and deleted_at is null
+        "#,
             &id
         );
-        let user = query.fetch_one(&self.pool).await;
 
-        user.ok()
+        query.fetch_one(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
     }
 
-    async fn find_all(&self) -> Vec<User> {
+    async fn find_all(&self) -> RepositoryResult<Vec<User>> {
         let query = query_as!(
             User,
-            "
-            select *
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
             from user_account
+            where deleted_at is null
             order by id
-        "
+        "#
         );
-        let users = query.fetch_all(&self.pool).await;
 
-        users.unwrap_or(Vec::new())
+        query.fetch_all(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    async fn find_page(&self, request: &PageRequest) -> RepositoryResult<Page<User>> {
+        let mut query = QueryBuilder::new("select * from user_account where true");
+
+        push_filters(&mut query, request);
+
+        if let Some(cursor) = &request.cursor {
+            let comparison = if request.sort.is_descending() { " < " } else { " > " };
+
+            match cursor.created_timestamp {
+                Some(created) => {
+                    query.push(" and (created_timestamp, id)").push(comparison).push("(");
+                    query.push_bind(created).push(", ").push_bind(cursor.id).push(")");
+                }
+                None => {
+                    query.push(" and id").push(comparison).push_bind(cursor.id);
+                }
+            }
+        }
+
+        let order = match request.sort {
+            Sort::IdAsc => " order by id",
+            Sort::IdDesc => " order by id desc",
+            Sort::CreatedAsc => " order by created_timestamp, id",
+            Sort::CreatedDesc => " order by created_timestamp desc, id desc",
+        };
+        // one extra row tells us whether there is a next page
+        query.push(order).push(" limit ").push_bind(i64::from(request.limit) + 1);
+
+        let mut items = query
+            .build_query_as::<User>()
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+        let next_cursor = if items.len() > request.limit as usize {
+            items.truncate(request.limit as usize);
+            items.last().and_then(|user| {
+                Some(Cursor {
+                    sort: request.sort,
+                    id: user.id?,
+                    created_timestamp: if request.sort.is_by_created() {
+                        Some(user.created_timestamp?)
+                    } else {
+                        None
+                    },
+                })
+            })
+        } else {
+            None
+        };
+
+        let total = if request.include_total {
+            let mut query = QueryBuilder::new("select count(*) from user_account where true");
+
+            push_filters(&mut query, request);
+            Some(query.build_query_scalar::<i64>().fetch_one(&mut *self.db.acquire().await?).await?)
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items,
+            next_cursor: next_cursor.map(|c| c.encode()),
+            total,
+        })
+    }
+}
+
+fn push_filters(query: &mut QueryBuilder<Postgres>, request: &PageRequest) {
+    if !request.include_deleted {
+        query.push(" and deleted_at is null");
+    }
+
+    if let Some(prefix) = &request.name_prefix {
+        let escaped = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        // ignore case like the unique index on user names does
+        query
+            .push(" and lower(user_name) like lower(")
+            .push_bind(escaped)
+            .push(") || '%'");
+    }
+
+    if let Some(created_after) = request.created_after {
+        query.push(" and created_timestamp > ").push_bind(created_after);
     }
 }
 
 #[async_trait]
 impl WriteRepository<User, i32> for UserRepository {
-    async fn create(&self, entity: &User) -> Option<User> {
+    async fn create(&self, entity: &User) -> RepositoryResult<User> {
         let query = query_as!(
             User,
-            "
-            insert into user_account (user_name)
-            values ($1)
-            returning id, user_name, created_timestamp, updated_timestamp
-        ",
-            entity.user_name
+            r#"
+            insert into user_account (user_name, password_hash, email, display_name, status)
+            values ($1, $2, $3, $4, coalesce($5, 'active'::user_status))
+            returning id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                      status as "status: UserStatus", deleted_at, version
+        "#,
+            entity.user_name,
+            entity.password_hash,
+            entity.email,
+            entity.display_name,
+            entity.status as _
         );
-        let user = query.fetch_one(&self.pool).await;
 
-        user.ok()
+        query.fetch_one(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
     }
 
-    async fn update(&self, entity: &User) -> Option<User> {
+    async fn update(&self, entity: &User) -> RepositoryResult<User> {
         let query = query_as!(
             User,
-            "
+            r#"
             update user_account
             set user_name = $1,
-                updated_timestamp = now()
-            where id = $2
-            returning id, user_name, created_timestamp, updated_timestamp
-        ",
+                password_hash = coalesce($2, password_hash),
+                email = $3,
+                display_name = $4,
+                status = coalesce($5, status),
+                updated_timestamp = now(),
+                version = version + 1
+            where id = $6
+              and deleted_at is null
+              and ($7::int4 is null or version = $7)
+            returning id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                      status as "status: UserStatus", deleted_at, version
+        "#,
             entity.user_name,
-            entity.id
+            entity.password_hash,
+            entity.email,
+            entity.display_name,
+            entity.status as _,
+            entity.id,
+            entity.version
         );
-        let user = query.fetch_one(&self.pool).await;
 
-        user.ok()
+        query.fetch_one(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    /// Marks the user as deleted, keeping the row around. Use `purge_by_id` to remove it.
+    async fn delete_by_id(&self, id: &i32) -> RepositoryResult<u64> {
+        self.delete_by_id_at_version(id, None).await
     }
+}
 
-    async fn delete_by_id(&self, id: &i32) -> u64 {
+#[async_trait]
+impl SoftDeleteRepository<User, i32> for UserRepository {
+    async fn find_by_id_including_deleted(&self, id: &i32) -> RepositoryResult<User> {
         let query = query_as!(
             User,
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
+            from user_account
+            where id = $1
+        "#,
+            &id
+        );
+
+        query.fetch_one(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    async fn find_all_including_deleted(&self) -> RepositoryResult<Vec<User>> {
+        let query = query_as!(
+            User,
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
+            from user_account
+            order by id
+        "#
+        );
+
+        query.fetch_all(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    async fn purge_by_id(&self, id: &i32) -> RepositoryResult<u64> {
+        self.purge_by_id_at_version(id, None).await
+    }
+
+    async fn delete_by_id_at_version(&self, id: &i32, version: Option<i32>) -> RepositoryResult<u64> {
+        let query = query!(
+            "
+            update user_account
+            set status = 'deleted',
+                deleted_at = now(),
+                updated_timestamp = now(),
+                version = version + 1
+            where id = $1
+              and deleted_at is null
+              and ($2::int4 is null or version = $2)
+        ",
+            &id,
+            version
+        );
+
+        query
+            .execute(&mut *self.db.acquire().await?)
+            .await
+            .map(|r| r.rows_affected())
+            .map_err(RepositoryError::from)
+    }
+
+    async fn purge_by_id_at_version(&self, id: &i32, version: Option<i32>) -> RepositoryResult<u64> {
+        let query = query!(
             "
             delete
             from user_account
             where id = $1
+              and ($2::int4 is null or version = $2)
         ",
-            &id
+            &id,
+            version
         );
 
         query
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await
             .map(|r| r.rows_affected())
-            .unwrap_or(0)
+            .map_err(RepositoryError::from)
+    }
+}
+
+#[async_trait]
+impl BatchRepository<User, i32> for UserRepository {
+    async fn find_all_by_id(&self, ids: &[i32]) -> RepositoryResult<Vec<User>> {
+        let query = query_as!(
+            User,
+            r#"
+            select id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                   status as "status: UserStatus", deleted_at, version
+            from user_account
+            where id = any($1)
+              and deleted_at is null
+            order by id
+        "#,
+            ids
+        );
+
+        query.fetch_all(&mut *self.db.acquire().await?).await.map_err(RepositoryError::from)
+    }
+
+    async fn write_batch(&self, batch: &Batch<User, i32>) -> RepositoryResult<BatchOutcome<User, i32>> {
+        let mut connection = self.db.acquire().await?;
+        // a savepoint when the repository already runs in a transaction
+        let mut tx = connection.begin().await?;
+        let column = |users: &[User], f: fn(&User) -> Option<String>| -> Vec<Option<String>> {
+            users.iter().map(f).collect()
+        };
+        let statuses = |users: &[User]| -> Vec<Option<UserStatus>> {
+            users.iter().map(|user| user.status).collect()
+        };
+
+        let creates = &batch.creates;
+        let created = query_as!(
+            User,
+            r#"
+            insert into user_account (user_name, password_hash, email, display_name, status)
+            select user_name, password_hash, email, display_name, coalesce(status, 'active'::user_status)
+            from unnest($1::text[], $2::text[], $3::text[], $4::text[], $5::user_status[])
+                     as batch (user_name, password_hash, email, display_name, status)
+            returning id, user_name, created_timestamp, updated_timestamp, password_hash, email, display_name,
+                      status as "status: UserStatus", deleted_at, version
+        "#,
+            column(creates, |u| u.user_name.clone()) as _,
+            column(creates, |u| u.password_hash.clone()) as _,
+            column(creates, |u| u.email.clone()) as _,
+            column(creates, |u| u.display_name.clone()) as _,
+            statuses(creates) as _
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let updates = &batch.updates;
+        let ids: Vec<Option<i32>> = updates.iter().map(|user| user.id).collect();
+        let versions: Vec<Option<i32>> = updates.iter().map(|user| user.version).collect();
+        let updated = query_as!(
+            User,
+            r#"
+            update user_account u
+            set user_name = batch.user_name,
+                password_hash = coalesce(batch.password_hash, u.password_hash),
+                email = batch.email,
+                display_name = batch.display_name,
+                status = coalesce(batch.status, u.status),
+                updated_timestamp = now(),
+                version = u.version + 1
+            from unnest($1::int4[], $2::text[], $3::text[], $4::text[], $5::text[], $6::user_status[], $7::int4[])
+                     as batch (id, user_name, password_hash, email, display_name, status, version)
+            where u.id = batch.id
+              and u.deleted_at is null
+              and (batch.version is null or u.version = batch.version)
+            returning u.id, u.user_name, u.created_timestamp, u.updated_timestamp, u.password_hash, u.email,
+                      u.display_name, u.status as "status: UserStatus", u.deleted_at, u.version
+        "#,
+            ids as _,
+            column(updates, |u| u.user_name.clone()) as _,
+            column(updates, |u| u.password_hash.clone()) as _,
+            column(updates, |u| u.email.clone()) as _,
+            column(updates, |u| u.display_name.clone()) as _,
+            statuses(updates) as _,
+            versions as _
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let deleted = query_scalar!(
+            "
+            update user_account
+            set status = 'deleted',
+                deleted_at = now(),
+                updated_timestamp = now(),
+                version = version + 1
+            where id = any($1)
+              and deleted_at is null
+            returning id
+        ",
+            &batch.deletes
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // dropping the transaction rolls it back
+        if updated.len() != updates.len() || deleted.len() != batch.deletes.len() {
+            return Err(RepositoryError::NotFound);
+        }
+
+        tx.commit().await?;
+
+        Ok(BatchOutcome {
+            created,
+            updated,
+            deleted,
+        })
     }
 }
 
 impl Repository<User, i32> for UserRepository {}
 
+impl InMemoryEntity<i32> for User {
+    fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    fn created(&mut self, id: i32, now: NaiveDateTime) {
+        self.id = Some(id);
+        self.created_timestamp = Some(now);
+        self.updated_timestamp = Some(now);
+        self.status = Some(self.status.unwrap_or_default());
+        self.deleted_at = None;
+        self.version = Some(1);
+    }
+
+    fn updated(&mut self, stored: &Self, now: NaiveDateTime) {
+        self.created_timestamp = stored.created_timestamp;
+        self.updated_timestamp = Some(now);
+        self.password_hash = self.password_hash.take().or_else(|| stored.password_hash.clone());
+        self.status = self.status.or(stored.status);
+        self.deleted_at = stored.deleted_at;
+        self.version = stored.version.map(|version| version + 1);
+    }
+
+    fn deleted(&mut self, now: NaiveDateTime) {
+        self.status = Some(UserStatus::Deleted);
+        self.deleted_at = Some(now);
+        self.updated_timestamp = Some(now);
+        self.version = self.version.map(|version| version + 1);
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    fn version(&self) -> Option<i32> {
+        self.version
+    }
+
+    fn unique_keys(&self) -> Vec<(&'static str, String)> {
+        let user_name = self.user_name.as_ref().map(|name| (USER_NAME_KEY, name.to_lowercase()));
+        let email = self.email.as_ref().map(|email| (USER_EMAIL_KEY, email.to_lowercase()));
+
+        user_name.into_iter().chain(email).collect()
+    }
+
+    fn matches(&self, request: &PageRequest) -> bool {
+        let prefixed = request.name_prefix.as_ref().is_none_or(|prefix| {
+            self.user_name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().starts_with(&prefix.to_lowercase()))
+        });
+        let created_after = request
+            .created_after
+            .is_none_or(|after| self.created_timestamp.is_some_and(|created| created > after));
+
+        prefixed && created_after
+    }
+
+    fn created_timestamp(&self) -> Option<NaiveDateTime> {
+        self.created_timestamp
+    }
+}
+
+#[async_trait]
+impl UserNameRepository for InMemoryRepository<User, i32> {
+    async fn find_by_user_name(&self, user_name: &str) -> RepositoryResult<User> {
+        let user_name = user_name.to_lowercase();
+
+        self.find_where(|user| {
+            !user.is_deleted()
+                && user.user_name.as_ref().is_some_and(|name| name.to_lowercase() == user_name)
+        })
+        .pop()
+        .ok_or(RepositoryError::NotFound)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::repository::repository_traits::TruncateRepository;
+    use crate::util::password::{hash_password, verify_password};
 
     #[async_trait]
     impl TruncateRepository for UserRepository {
-        async fn truncate(&self) -> u64 {
+        async fn truncate(&self) -> RepositoryResult<u64> {
             let query = query_as!(
                 User,
                 "
@@ -118,10 +533,10 @@ mod tests {
             );
 
             query
-                .execute(&self.pool)
+                .execute(&mut *self.db.acquire().await?)
                 .await
                 .map(|r| r.rows_affected())
-                .unwrap_or(0)
+                .map_err(RepositoryError::from)
         }
     }
 
@@ -148,7 +563,7 @@ mod tests {
         let repo = UserRepository::new(&pool);
         let res = repo.create(&user).await;
 
-        assert_eq!(res.is_none(), true);
+        assert!(matches!(res, Err(RepositoryError::Query(_))));
     }
 
     #[sqlx::test]
@@ -167,6 +582,116 @@ mod tests {
         assert_eq!(user.updated_timestamp.is_some(), true);
     }
 
+    #[sqlx::test]
+    async fn test_get_missing_user_by_id(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let user = repo.find_by_id(&23423423).await;
+
+        assert_eq!(user.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn test_update_missing_user(pool: PgPool) {
+        let user = User {
+            id: Some(23423423),
+            ..User::new("foo")
+        };
+        let repo = UserRepository::new(&pool);
+        let user = repo.update(&user).await;
+
+        assert_eq!(user.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn test_update_with_stale_version(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let user = repo.create(&User::new("foo")).await.unwrap();
+
+        assert_eq!(user.version, Some(1));
+
+        let updated = repo.update(&User { version: Some(1), ..user.clone() }).await.unwrap();
+
+        assert_eq!(updated.version, Some(2));
+        assert_eq!(repo.update(&user).await.err(), Some(RepositoryError::NotFound));
+        assert_eq!(repo.update(&User { version: None, ..user }).await.unwrap().version, Some(3));
+    }
+
+    #[sqlx::test]
+    async fn test_write_batch(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let existing = repo.create(&User::new("foo")).await.unwrap();
+        let deleted = repo.create(&User::new("bar")).await.unwrap().id.unwrap();
+        let batch = Batch {
+            creates: vec![User::new("a"), User::new("b")],
+            updates: vec![User {
+                display_name: Some("Foo".to_string()),
+                ..existing.clone()
+            }],
+            deletes: vec![deleted],
+        };
+        let outcome = repo.write_batch(&batch).await.unwrap();
+        let names: Vec<_> = outcome.created.iter().map(|u| u.user_name.clone().unwrap()).collect();
+
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(outcome.created[0].status, Some(UserStatus::Active));
+        assert_eq!(outcome.updated[0].display_name.as_deref(), Some("Foo"));
+        assert_eq!(outcome.updated[0].version, Some(2));
+        assert_eq!(outcome.deleted, vec![deleted]);
+        assert_eq!(repo.find_all_by_id(&[existing.id.unwrap(), deleted]).await.unwrap().len(), 1);
+
+        let batch = Batch {
+            creates: vec![User::new("c")],
+            updates: Vec::new(),
+            deletes: vec![deleted],
+        };
+
+        assert_eq!(repo.write_batch(&batch).await.err(), Some(RepositoryError::NotFound));
+        assert_eq!(repo.find_by_user_name("c").await.err(), Some(RepositoryError::NotFound));
+
+        let batch = Batch {
+            creates: Vec::new(),
+            updates: vec![existing],
+            deletes: Vec::new(),
+        };
+
+        assert_eq!(repo.write_batch(&batch).await.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn test_closed_pool(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        pool.close().await;
+
+        let users = repo.find_all().await;
+
+        assert!(users.is_err_and(|e| e.is_unavailable()));
+    }
+
+    #[sqlx::test]
+    async fn test_get_user_by_user_name(pool: PgPool) {
+        let user = User {
+            password_hash: Some(hash_password("bar").unwrap()),
+            ..User::new("foo")
+        };
+        let repo = UserRepository::new(&pool);
+        let _ = repo.create(&user).await.expect("User could not be created");
+        let user = repo
+            .find_by_user_name("foo")
+            .await
+            .expect("User wasn't retrieved");
+
+        assert_eq!(user.user_name.unwrap(), "foo");
+        assert!(verify_password("bar", &user.password_hash.unwrap()));
+    }
+
+    #[sqlx::test]
+    async fn test_get_missing_user_by_user_name(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let user = repo.find_by_user_name("foo").await;
+
+        assert_eq!(user.err(), Some(RepositoryError::NotFound));
+    }
+
     #[sqlx::test]
     async fn test_get_all_users(pool: PgPool) {
         let users = vec![
@@ -181,7 +706,7 @@ mod tests {
             let _ = repo.create(&user).await;
         }
 
-        let users = repo.find_all().await;
+        let users = repo.find_all().await.unwrap();
 
         assert_eq!(users.len(), 3);
 
@@ -192,6 +717,92 @@ mod tests {
         assert_eq!(names[2], "bazbar".to_string());
     }
 
+    async fn find_names(repo: &UserRepository, request: &PageRequest) -> (Vec<String>, Option<String>) {
+        let page = repo.find_page(request).await.unwrap();
+        let names = page.items.into_iter().map(|u| u.user_name.unwrap()).collect();
+
+        (names, page.next_cursor)
+    }
+
+    #[sqlx::test]
+    async fn test_find_page(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+
+        for name in ["foo", "bar", "baz", "qux", "quux"] {
+            repo.create(&User::new(name)).await.unwrap();
+        }
+
+        let mut request = PageRequest {
+            limit: 2,
+            ..PageRequest::default()
+        };
+        let (names, cursor) = find_names(&repo, &request).await;
+
+        assert_eq!(names, vec!["foo", "bar"]);
+
+        request.cursor = Some(Cursor::decode(&cursor.unwrap()).unwrap());
+        let (names, cursor) = find_names(&repo, &request).await;
+
+        assert_eq!(names, vec!["baz", "qux"]);
+
+        request.cursor = Some(Cursor::decode(&cursor.unwrap()).unwrap());
+        let (names, cursor) = find_names(&repo, &request).await;
+
+        assert_eq!(names, vec!["quux"]);
+        assert!(cursor.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_find_page_by_created_desc(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+
+        for name in ["foo", "bar", "baz"] {
+            repo.create(&User::new(name)).await.unwrap();
+        }
+
+        let mut request = PageRequest {
+            limit: 2,
+            sort: Sort::CreatedDesc,
+            ..PageRequest::default()
+        };
+        let (names, cursor) = find_names(&repo, &request).await;
+
+        assert_eq!(names, vec!["baz", "bar"]);
+
+        request.cursor = Some(Cursor::decode(&cursor.unwrap()).unwrap());
+        let (names, _) = find_names(&repo, &request).await;
+
+        assert_eq!(names, vec!["foo"]);
+    }
+
+    #[sqlx::test]
+    async fn test_find_page_filtered(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+
+        for name in ["foo_1", "Foo_2", "fooX", "bar"] {
+            repo.create(&User::new(name)).await.unwrap();
+        }
+
+        let request = PageRequest {
+            name_prefix: Some("FOO_".to_string()),
+            include_total: true,
+            ..PageRequest::default()
+        };
+        let page = repo.find_page(&request).await.unwrap();
+
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.total, Some(2));
+
+        let request = PageRequest {
+            created_after: Some(chrono::Utc::now().naive_utc() + chrono::Duration::hours(1)),
+            ..PageRequest::default()
+        };
+        let page = repo.find_page(&request).await.unwrap();
+
+        assert!(page.items.is_empty());
+        assert_eq!(page.total, None);
+    }
+
     #[sqlx::test]
     async fn test_delete_user_by_id(pool: PgPool) {
         let user = User::new("foo");
@@ -199,7 +810,7 @@ mod tests {
         let user = repo.create(&user).await;
         let delete = repo.delete_by_id(&user.unwrap().id.unwrap()).await;
 
-        assert_eq!(delete, 1);
+        assert_eq!(delete, Ok(1));
     }
 
     #[sqlx::test]
@@ -211,6 +822,142 @@ mod tests {
         let _ = repo.delete_by_id(&id).await;
         let delete = repo.delete_by_id(&id).await;
 
-        assert_eq!(delete, 0);
+        assert_eq!(delete, Ok(0));
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_with_profile(pool: PgPool) {
+        let user = User {
+            email: Some("foo@example.com".to_string()),
+            display_name: Some("Foo".to_string()),
+            status: Some(UserStatus::Pending),
+            ..User::new("foo")
+        };
+        let repo = UserRepository::new(&pool);
+        let user = repo.create(&user).await.unwrap();
+
+        assert_eq!(user.email.as_deref(), Some("foo@example.com"));
+        assert_eq!(user.display_name.as_deref(), Some("Foo"));
+        assert_eq!(user.status, Some(UserStatus::Pending));
+
+        let user = repo.create(&User::new("bar")).await.unwrap();
+
+        assert_eq!(user.status, Some(UserStatus::Active));
+        assert!(user.deleted_at.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_email_is_unique(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let foo = User {
+            email: Some("foo@example.com".to_string()),
+            ..User::new("foo")
+        };
+        let bar = User {
+            email: Some("FOO@example.com".to_string()),
+            ..User::new("bar")
+        };
+        let foo = repo.create(&foo).await.unwrap();
+
+        assert_eq!(
+            repo.create(&bar).await.err(),
+            Some(RepositoryError::UniqueViolation("user_account_email_key".to_string()))
+        );
+
+        repo.delete_by_id(&foo.id.unwrap()).await.unwrap();
+
+        assert!(repo.create(&bar).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_soft_delete(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let foo = repo.create(&User::new("foo")).await.unwrap();
+        let id = foo.id.unwrap();
+
+        repo.create(&User::new("bar")).await.unwrap();
+
+        assert_eq!(repo.delete_by_id(&id).await, Ok(1));
+        assert_eq!(repo.find_by_id(&id).await.err(), Some(RepositoryError::NotFound));
+        assert_eq!(repo.find_by_user_name("foo").await.err(), Some(RepositoryError::NotFound));
+        assert_eq!(repo.find_all().await.unwrap().len(), 1);
+        assert_eq!(repo.find_all_including_deleted().await.unwrap().len(), 2);
+
+        let deleted = repo.find_by_id_including_deleted(&id).await.unwrap();
+
+        assert_eq!(deleted.status, Some(UserStatus::Deleted));
+        assert!(deleted.deleted_at.is_some());
+
+        let mut request = PageRequest {
+            include_total: true,
+            ..PageRequest::default()
+        };
+
+        assert_eq!(repo.find_page(&request).await.unwrap().total, Some(1));
+
+        request.include_deleted = true;
+
+        assert_eq!(repo.find_page(&request).await.unwrap().total, Some(2));
+        assert_eq!(repo.update(&foo).await.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn test_purge_user(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let id = repo.create(&User::new("foo")).await.unwrap().id.unwrap();
+
+        repo.delete_by_id(&id).await.unwrap();
+
+        assert_eq!(repo.purge_by_id(&id).await, Ok(1));
+        assert_eq!(
+            repo.find_by_id_including_deleted(&id).await.err(),
+            Some(RepositoryError::NotFound)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_delete_with_stale_version(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let id = repo.create(&User::new("foo")).await.unwrap().id.unwrap();
+
+        assert_eq!(repo.delete_by_id_at_version(&id, Some(2)).await, Ok(0));
+        assert_eq!(repo.delete_by_id_at_version(&id, Some(1)).await, Ok(1));
+        assert_eq!(repo.purge_by_id_at_version(&id, Some(1)).await, Ok(0));
+        assert_eq!(repo.purge_by_id_at_version(&id, Some(2)).await, Ok(1));
+    }
+
+    #[sqlx::test]
+    async fn test_find_by_user_name_ignores_case(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let foo = repo.create(&User::new("Foo")).await.unwrap();
+
+        assert_eq!(repo.find_by_user_name("fOO").await.unwrap().id, foo.id);
+        assert_eq!(repo.find_by_user_name("bar").await.err(), Some(RepositoryError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn test_user_name_is_unique(pool: PgPool) {
+        let repo = UserRepository::new(&pool);
+        let foo = repo.create(&User::new("foo")).await.unwrap();
+
+        assert_eq!(
+            repo.create(&User::new("FOO")).await.err(),
+            Some(RepositoryError::UniqueViolation(USER_NAME_KEY.to_string()))
+        );
+
+        let bar = repo.create(&User::new("bar")).await.unwrap();
+        let renamed = User {
+            user_name: Some("Foo".to_string()),
+            ..bar
+        };
+
+        assert_eq!(
+            repo.update(&renamed).await.err(),
+            Some(RepositoryError::UniqueViolation(USER_NAME_KEY.to_string()))
+        );
+
+        repo.delete_by_id(&foo.id.unwrap()).await.unwrap();
+
+        assert!(repo.create(&User::new("FOO")).await.is_ok());
     }
 }
@@ -0,0 +1,101 @@
+use crate::model::auth_error::AuthError;
+use crate::repository::ArcRolePermissionRepository;
+use log::error;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+type RolePermissions = HashMap<String, HashSet<String>>;
+type CachedPermissions = Option<(Arc<RolePermissions>, Instant)>;
+
+/// Resolves the permissions granted by a set of roles. The role to permission mapping is loaded
+/// from the role repository and cached for `CACHE_TTL`, falling back to the last known mapping if
+/// the repository can't be reached. Without one, permission checks fail with
+/// `AuthError::ServiceUnavailable`.
+#[derive(Clone)]
+pub struct AccessControl {
+    role_repository: ArcRolePermissionRepository,
+    cache: Arc<RwLock<CachedPermissions>>,
+}
+
+impl AccessControl {
+    const CACHE_TTL: Duration = Duration::from_secs(60);
+
+    pub fn new(role_repository: ArcRolePermissionRepository) -> Self {
+        Self {
+            role_repository,
+            cache: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub async fn has_permission(&self, roles: &[String], permission: &str) -> Result<bool, AuthError> {
+        let permissions = self.role_permissions().await?;
+
+        Ok(roles.iter().any(|role| {
+            permissions
+                .get(role)
+                .is_some_and(|granted| granted.contains(permission))
+        }))
+    }
+
+    async fn role_permissions(&self) -> Result<Arc<RolePermissions>, AuthError> {
+        let cached = self.cache.read().ok().and_then(|cache| cache.clone());
+
+        if let Some((permissions, loaded_at)) = &cached
+            && loaded_at.elapsed() < Self::CACHE_TTL
+        {
+            return Ok(permissions.clone());
+        }
+
+        let rows = match self.role_repository.find_role_permissions().await {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Unable to load role permissions: {e}");
+                return cached
+                    .map(|(permissions, _)| permissions)
+                    .ok_or(AuthError::ServiceUnavailable);
+            }
+        };
+        let mut permissions = RolePermissions::new();
+
+        for (role, permission) in rows {
+            permissions.entry(role).or_default().insert(permission);
+        }
+
+        let permissions = Arc::new(permissions);
+
+        if let Ok(mut cache) = self.cache.write() {
+            *cache = Some((permissions.clone(), Instant::now()));
+        }
+
+        Ok(permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{RepositoryError, RepositoryResult, RolePermissionRepository};
+    use async_trait::async_trait;
+
+    struct UnavailableRoleRepository;
+
+    #[async_trait]
+    impl RolePermissionRepository for UnavailableRoleRepository {
+        async fn find_role_permissions(&self) -> RepositoryResult<Vec<(String, String)>> {
+            Err(RepositoryError::Connection("connection refused".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_permissions() {
+        let access_control = AccessControl::new(Arc::new(UnavailableRoleRepository));
+        let roles = vec!["admin".to_string()];
+
+        assert!(matches!(
+            access_control.has_permission(&roles, "user:read").await,
+            Err(AuthError::ServiceUnavailable)
+        ));
+        assert!(access_control.cache.read().unwrap().is_none());
+    }
+}
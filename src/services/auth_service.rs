@@ -1,45 +1,245 @@
-use crate::config::authentication::KEYS;
-use crate::model::auth::JwtClaims;
+use crate::config::authentication::Keys;
+use crate::config::settings::AuthSettings;
+use crate::model::auth::{JwtClaims, LoginDto, RefreshToken};
 use crate::model::auth_error::AuthError;
-use crate::util;
-use jsonwebtoken::{encode, Header};
+use crate::model::user::{User, UserStatus};
+use crate::repository::repository_traits::ArcRepository;
+use crate::repository::{
+    ArcRefreshTokenStore, ArcUserNameRepository, ArcUserRoleRepository, RepositoryError,
+};
+use crate::services::{LoginThrottle, RevocationStore};
+use crate::state::UsersApi;
+use crate::util::password::verify_password_blocking;
+use crate::util::token::{generate_opaque_token, hash_token};
+use chrono::{Duration, Utc};
+use log::{error, warn};
 use serde::Serialize;
+use std::net::IpAddr;
 use utoipa::ToSchema;
+use uuid::Uuid;
 
-#[derive(Clone, Default)]
-pub struct AuthService;
+#[derive(Clone)]
+pub struct AuthService {
+    user_repository: ArcRepository<User, i32>,
+    user_name_repository: ArcUserNameRepository,
+    role_repository: ArcUserRoleRepository,
+    refresh_token_repository: ArcRefreshTokenStore,
+    revocation_store: RevocationStore,
+    login_throttle: LoginThrottle,
+    keys: Keys,
+    access_token_ttl_seconds: u32,
+    refresh_token_ttl: Duration,
+}
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct AuthBody {
     pub access_token: String,
     pub token_type: String,
+    pub expires_in: usize,
+    pub refresh_token: String,
 }
 impl AuthBody {
-    fn new(access_token: String) -> Self {
+    fn new(access_token: String, expires_in: usize, refresh_token: String) -> Self {
         Self {
             access_token,
             token_type: "Bearer".to_string(),
+            expires_in,
+            refresh_token,
         }
     }
 }
 
 
 impl AuthService {
-    const ACCESS_EXP_MINUTES: u32 = 15 * 60;
+    /// Builds the service on the user, role and refresh token repositories of `users_api`.
+    pub fn new(
+        users_api: &UsersApi,
+        revocation_store: RevocationStore,
+        login_throttle: LoginThrottle,
+        keys: Keys,
+        settings: &AuthSettings,
+    ) -> Self {
+        Self {
+            user_repository: users_api.user_repository.clone(),
+            user_name_repository: users_api.user_name_repository.clone(),
+            role_repository: users_api.role_repository.clone(),
+            refresh_token_repository: users_api.refresh_token_repository.clone(),
+            revocation_store,
+            login_throttle,
+            keys,
+            access_token_ttl_seconds: settings.access_token_ttl_seconds,
+            refresh_token_ttl: Duration::days(settings.refresh_token_ttl_days),
+        }
+    }
+
+    /// Verifies the credentials of a login attempt made from `ip`, refusing attempts while the user
+    /// name or IP is locked out, and users that are not active.
+    pub async fn authenticate(&self, payload: &LoginDto, ip: Option<IpAddr>) -> Result<User, AuthError> {
+        self.login_throttle.check(&payload.user_name, ip).await?;
+
+        let user = match self.user_name_repository.find_by_user_name(&payload.user_name).await {
+            Ok(user) => Some(user),
+            Err(RepositoryError::NotFound) => None,
+            Err(e) => {
+                error!("Unable to look up user {}: {e}", payload.user_name);
+                return Err(AuthError::ServiceUnavailable);
+            }
+        };
+        let password_hash = user.as_ref().and_then(|user| user.password_hash.as_deref());
+        let verified = verify_password_blocking(&payload.password, password_hash).await;
+
+        match user {
+            Some(user) if verified => {
+                self.login_throttle.record_success(&payload.user_name).await;
+
+                if user.status.unwrap_or_default() != UserStatus::Active {
+                    warn!("Login attempt for inactive user: {}", payload.user_name);
+                    return Err(AuthError::AccountDisabled);
+                }
+
+                Ok(user)
+            }
+            _ => {
+                warn!("Failed login attempt for user: {}", payload.user_name);
+                Err(self.login_throttle.record_failure(&payload.user_name, ip).await)
+            }
+        }
+    }
 
-    pub fn new() -> Self {
-        Self {}
+    pub async fn generate_tokens(&self, user_id: i32) -> Result<AuthBody, AuthError> {
+        self.issue_tokens(user_id, Uuid::new_v4()).await
     }
 
-    pub fn generate_tokens(&self, user_id: &str) -> Result<AuthBody, AuthError> {
+    pub async fn refresh_tokens(&self, refresh_token: &str) -> Result<AuthBody, AuthError> {
+        let token_hash = hash_token(refresh_token);
+
+        let consumed = self
+            .refresh_token_repository
+            .consume(&token_hash)
+            .await
+            .map_err(|e| refresh_tokens_unavailable("consume refresh token", e))?;
+
+        if let Some(token) = consumed {
+            self.check_active(token.user_id).await?;
+            return self.issue_tokens(token.user_id, token.family_id).await;
+        }
+
+        let reused = self
+            .refresh_token_repository
+            .find_by_hash(&token_hash)
+            .await
+            .map_err(|e| refresh_tokens_unavailable("look up refresh token", e))?
+            .filter(|token| token.used_timestamp.is_some());
+
+        if let Some(token) = reused {
+            warn!(
+                "Refresh token reuse detected for user {}, revoking token family {}",
+                token.user_id, token.family_id
+            );
+            // the family stays usable unless the revocation is written
+            self.refresh_token_repository
+                .revoke_family(&token.family_id)
+                .await
+                .map_err(|e| refresh_tokens_unavailable("revoke reused token family", e))?;
+        }
+
+        Err(AuthError::InvalidToken)
+    }
+
+    pub async fn logout(&self, claims: &JwtClaims) -> Result<(), AuthError> {
+        self.revocation_store.revoke(claims).await?;
+        self.refresh_token_repository
+            .revoke_family(&claims.sid)
+            .await
+            .map_err(|e| refresh_tokens_unavailable("revoke refresh tokens", e))?;
+
+        Ok(())
+    }
+
+    pub async fn logout_all(&self, claims: &JwtClaims) -> Result<(), AuthError> {
+        let user_id = claims.user_id().ok_or(AuthError::InvalidToken)?;
+
+        self.revocation_store.revoke(claims).await?;
+        self.revocation_store.revoke_user(user_id).await?;
+        self.refresh_token_repository
+            .revoke_all_for_user(user_id)
+            .await
+            .map_err(|e| refresh_tokens_unavailable("revoke refresh tokens", e))?;
+
+        Ok(())
+    }
+
+    /// Fails unless the user still exists and is active, revoking the refresh tokens of users that
+    /// were deleted or disabled since they logged in.
+    async fn check_active(&self, user_id: i32) -> Result<(), AuthError> {
+        let active = match self.user_repository.find_by_id(&user_id).await {
+            Ok(user) => {
+                user.deleted_at.is_none() && user.status.unwrap_or_default() == UserStatus::Active
+            }
+            Err(RepositoryError::NotFound) => false,
+            Err(e) => {
+                error!("Unable to load user {user_id} to refresh its tokens: {e}");
+                return Err(AuthError::ServiceUnavailable);
+            }
+        };
+
+        if !active {
+            warn!("Refusing to refresh tokens of inactive user {user_id}");
+            self.refresh_token_repository
+                .revoke_all_for_user(user_id)
+                .await
+                .map_err(|e| refresh_tokens_unavailable("revoke refresh tokens", e))?;
+            return Err(AuthError::InvalidToken);
+        }
+
+        Ok(())
+    }
+
+    async fn issue_tokens(&self, user_id: i32, family_id: Uuid) -> Result<AuthBody, AuthError> {
+        let roles = self
+            .role_repository
+            .find_roles_by_user_id(&user_id)
+            .await
+            .map_err(|e| {
+                error!("Unable to load roles for user {user_id}: {e}");
+                AuthError::ServiceUnavailable
+            })?;
+        let issued = Utc::now();
+        let now = issued.timestamp() as usize;
         let claims = JwtClaims {
-            sub: user_id.to_owned(),
-            exp: util::now_epoch() + AuthService::ACCESS_EXP_MINUTES as usize,
+            sub: user_id.to_string(),
+            exp: now + self.access_token_ttl_seconds as usize,
+            iat: now,
+            iat_us: Some(issued.timestamp_micros()),
+            jti: Uuid::new_v4(),
+            sid: family_id,
+            roles,
         };
 
-        let access_token = encode(&Header::default(), &claims, &KEYS.encoding)
+        let access_token = self
+            .keys
+            .encode(&claims)
             .map_err(|_| AuthError::TokenCreation)?;
 
-        Ok(AuthBody::new(access_token))
+        let refresh_token = generate_opaque_token();
+        let expires = Utc::now().naive_utc() + self.refresh_token_ttl;
+        let token = RefreshToken::new(user_id, family_id, hash_token(&refresh_token), expires);
+
+        self.refresh_token_repository
+            .create(&token)
+            .await
+            .map_err(|e| refresh_tokens_unavailable("store refresh token", e))?;
+
+        Ok(AuthBody::new(
+            access_token,
+            self.access_token_ttl_seconds as usize,
+            refresh_token,
+        ))
     }
 }
+
+/// Logs a failed refresh token store request, which leaves authentication unavailable.
+fn refresh_tokens_unavailable(action: &str, error: RepositoryError) -> AuthError {
+    error!("Unable to {action}: {error}");
+    AuthError::ServiceUnavailable
+}
@@ -0,0 +1,138 @@
+use crate::config::authentication::Keys;
+use crate::model::health::{HealthCheck, HealthReport, HealthStatus};
+use crate::state::MIGRATOR;
+use sqlx::{query_scalar, PgPool};
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// Checks whether the dependencies needed to serve requests are available. Without a pool the
+/// app runs on in-memory repositories, so only the signing keys are checked.
+#[derive(Clone)]
+pub struct HealthService {
+    pool: Option<PgPool>,
+    keys: Keys,
+}
+
+impl HealthService {
+    const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+    pub fn new(pool: Option<PgPool>, keys: Keys) -> Self {
+        Self { pool, keys }
+    }
+
+    pub async fn check(&self) -> HealthReport {
+        let mut checks = Vec::new();
+
+        if let Some(pool) = &self.pool {
+            checks.push(timed("database", Self::check_database(pool)).await);
+            checks.push(timed("migrations", Self::check_migrations(pool)).await);
+        }
+
+        checks.push(timed("signing_keys", async { self.check_keys() }).await);
+        HealthReport::new(checks)
+    }
+
+    async fn check_database(pool: &PgPool) -> Result<(), String> {
+        query_scalar::<_, i32>("select 1")
+            .fetch_one(pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                error!("Database health check failed: {e}");
+                "Database unavailable".to_string()
+            })
+    }
+
+    async fn check_migrations(pool: &PgPool) -> Result<(), String> {
+        // the migrations table is managed by sqlx, so it isn't checked at compile time
+        let applied: HashSet<i64> =
+            query_scalar::<_, i64>("select version from _sqlx_migrations where success")
+                .fetch_all(pool)
+                .await
+                .map_err(|e| {
+                    error!("Migration health check failed: {e}");
+                    "Applied migrations could not be read".to_string()
+                })?
+                .into_iter()
+                .collect();
+        let pending = MIGRATOR
+            .iter()
+            .filter(|m| m.migration_type.is_up_migration() && !applied.contains(&m.version))
+            .count();
+
+        match pending {
+            0 => Ok(()),
+            n => Err(format!("{n} migrations have not been applied")),
+        }
+    }
+
+    fn check_keys(&self) -> Result<(), String> {
+        self.keys
+            .signing_key()
+            .map(|_| ())
+            .ok_or_else(|| "No signing key is currently active".to_string())
+    }
+}
+
+async fn timed(name: &str, check: impl Future<Output = Result<(), String>>) -> HealthCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(HealthService::CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err("Check timed out".to_string()));
+
+    HealthCheck {
+        name: name.to_string(),
+        status: if result.is_ok() { HealthStatus::Up } else { HealthStatus::Down },
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        detail: result.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_healthy(pool: PgPool) {
+        MIGRATOR.run(&pool).await.unwrap();
+
+        let report = HealthService::new(Some(pool), Keys::from_secret(b"secret")).check().await;
+
+        assert_eq!(report.status, HealthStatus::Up);
+        assert_eq!(report.checks.len(), 3);
+        assert!(report.checks.iter().all(|c| c.detail.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_without_database() {
+        let report = HealthService::new(None, Keys::from_secret(b"secret")).check().await;
+
+        assert_eq!(report.status, HealthStatus::Up);
+        assert_eq!(report.checks.len(), 1);
+        assert_eq!(report.checks[0].name, "signing_keys");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_missing_migrations(pool: PgPool) {
+        let report = HealthService::new(Some(pool), Keys::from_secret(b"secret")).check().await;
+
+        assert_eq!(report.status, HealthStatus::Down);
+        assert_eq!(report.checks[0].status, HealthStatus::Up);
+        assert_eq!(report.checks[1].status, HealthStatus::Down);
+    }
+
+    #[sqlx::test]
+    async fn test_database_down(pool: PgPool) {
+        let service = HealthService::new(Some(pool.clone()), Keys::from_secret(b"secret"));
+        pool.close().await;
+
+        let report = service.check().await;
+
+        assert_eq!(report.status, HealthStatus::Down);
+        assert_eq!(report.checks[0].status, HealthStatus::Down);
+        assert_eq!(report.checks[0].detail.as_deref(), Some("Database unavailable"));
+        assert_eq!(report.checks[2].status, HealthStatus::Up);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::config::settings::LockoutSettings;
+use crate::model::audit::AuditEntry;
+use crate::model::auth::{LoginFailure, LoginScope};
+use crate::model::auth_error::AuthError;
+use crate::repository::{ArcAuditRecorder, ArcLoginFailureStore, RepositoryResult};
+use chrono::{Duration, NaiveDateTime, Utc};
+use log::{error, warn};
+use std::net::IpAddr;
+
+/// Throttles logins using the failed attempts stored per user name and per client IP.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    failure_repository: ArcLoginFailureStore,
+    audit_repository: ArcAuditRecorder,
+    settings: LockoutSettings,
+}
+
+impl LoginThrottle {
+    pub fn new(
+        failure_repository: ArcLoginFailureStore,
+        audit_repository: ArcAuditRecorder,
+        settings: &LockoutSettings,
+    ) -> Self {
+        Self {
+            failure_repository,
+            audit_repository,
+            settings: settings.clone(),
+        }
+    }
+
+    /// Rejects the attempt with `AuthError::AccountLocked` while the user name or IP is locked, or
+    /// still backing off from a previous failure.
+    pub async fn check(&self, user_name: &str, ip: Option<IpAddr>) -> Result<(), AuthError> {
+        let now = Utc::now().naive_utc();
+        let mut blocked_until = self
+            .find(LoginScope::User, &user_subject(user_name))
+            .await?
+            .and_then(|failure| self.user_blocked_until(&failure, now));
+
+        if let Some(ip) = ip
+            && let Some(failure) = self.find(LoginScope::Ip, &ip.to_string()).await?
+        {
+            blocked_until = blocked_until.max(failure.locked_until.filter(|until| *until > now));
+        }
+
+        match blocked_until {
+            Some(until) => Err(AuthError::AccountLocked(until.and_utc())),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed attempt, returning the error to report for it: `AuthError::AccountLocked`
+    /// if it caused a lockout, `AuthError::WrongCredentials` otherwise.
+    pub async fn record_failure(&self, user_name: &str, ip: Option<IpAddr>) -> AuthError {
+        let mut locked_until = self
+            .count_failure(LoginScope::User, &user_subject(user_name), self.settings.max_failures, ip)
+            .await;
+
+        if let Some(ip) = ip {
+            let ip_locked_until = self
+                .count_failure(LoginScope::Ip, &ip.to_string(), self.settings.ip_max_failures, Some(ip))
+                .await;
+            locked_until = locked_until.max(ip_locked_until);
+        }
+
+        match locked_until {
+            Some(until) => AuthError::AccountLocked(until.and_utc()),
+            None => AuthError::WrongCredentials,
+        }
+    }
+
+    /// Forgets the failures of a user name after a successful login.
+    pub async fn record_success(&self, user_name: &str) {
+        if let Err(e) = self
+            .failure_repository
+            .clear(LoginScope::User, &user_subject(user_name))
+            .await
+        {
+            error!("Unable to reset failed logins of user {user_name}: {e}");
+        }
+    }
+
+    /// Lifts the lock and back-off of a user name, returning whether there was anything to lift.
+    pub async fn unlock(&self, user_name: &str, actor: &str) -> RepositoryResult<bool> {
+        let subject = user_subject(user_name);
+        let cleared = self
+            .failure_repository
+            .clear(LoginScope::User, &subject)
+            .await?;
+
+        self.audit(AuditEntry::new("account_unlocked", &subject, Some(actor), None))
+            .await;
+
+        Ok(cleared > 0)
+    }
+
+    async fn find(&self, scope: LoginScope, subject: &str) -> Result<Option<LoginFailure>, AuthError> {
+        self.failure_repository
+            .find(scope, subject)
+            .await
+            .map_err(|e| {
+                error!("Unable to load failed logins of {scope} {subject}: {e}");
+                AuthError::ServiceUnavailable
+            })
+    }
+
+    fn user_blocked_until(&self, failure: &LoginFailure, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if let Some(until) = failure.locked_until
+            && until > now
+        {
+            return Some(until);
+        }
+
+        let retry_at = failure.last_failure_timestamp + backoff(&self.settings, failure.failures);
+
+        (retry_at > now).then_some(retry_at)
+    }
+
+    /// Counts the failure and locks the subject once `max_failures` is reached, returning the end
+    /// of the lock it placed.
+    async fn count_failure(
+        &self,
+        scope: LoginScope,
+        subject: &str,
+        max_failures: u32,
+        ip: Option<IpAddr>,
+    ) -> Option<NaiveDateTime> {
+        let now = Utc::now().naive_utc();
+        let window_start = now - seconds_to_duration(self.settings.failure_window_seconds);
+        let failure = self
+            .failure_repository
+            .record_failure(scope, subject, now, window_start)
+            .await
+            .inspect_err(|e| error!("Unable to record failed login of {scope} {subject}: {e}"))
+            .ok()?;
+
+        if u32::try_from(failure.failures).unwrap_or_default() < max_failures {
+            return None;
+        }
+
+        let until = now + seconds_to_duration(self.settings.lock_seconds);
+
+        if let Err(e) = self.failure_repository.lock(scope, subject, until).await {
+            error!("Unable to lock {scope} {subject}: {e}");
+            return None;
+        }
+
+        warn!("Locked {scope} {subject} until {until} after {} failed logins", failure.failures);
+
+        let detail = format!(
+            "{} failed logins, locked until {until} (last attempt from {})",
+            failure.failures,
+            ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+        );
+        let action = match scope {
+            LoginScope::User => "account_locked",
+            LoginScope::Ip => "ip_locked",
+        };
+        self.audit(AuditEntry::new(action, subject, None, Some(detail)))
+            .await;
+
+        Some(until)
+    }
+
+    async fn audit(&self, entry: AuditEntry) {
+        if let Err(e) = self.audit_repository.record(&entry).await {
+            error!("Unable to record audit entry {} for {}: {e}", entry.action, entry.subject);
+        }
+    }
+}
+
+/// Delay after `failures` failed attempts, doubling with each failure.
+fn backoff(settings: &LockoutSettings, failures: i32) -> Duration {
+    let exponent = u32::try_from(failures.saturating_sub(1)).unwrap_or_default().min(32);
+    let seconds = settings
+        .backoff_base_seconds
+        .saturating_mul(1_u64 << exponent)
+        .min(settings.max_backoff_seconds);
+
+    seconds_to_duration(seconds)
+}
+
+/// Subject the failed logins of `user_name` are counted against, ignoring case like user names do.
+pub fn user_subject(user_name: &str) -> String {
+    user_name.to_lowercase()
+}
+
+fn seconds_to_duration(seconds: u64) -> Duration {
+    Duration::seconds(i64::try_from(seconds).unwrap_or(i64::MAX).min(i64::MAX / 1000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{AuditRepository, LoginFailureRepository};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    fn throttle(pool: &PgPool, settings: LockoutSettings) -> LoginThrottle {
+        LoginThrottle::new(
+            Arc::new(LoginFailureRepository::new(pool)),
+            Arc::new(AuditRepository::new(pool)),
+            &settings,
+        )
+    }
+
+    fn without_backoff() -> LockoutSettings {
+        LockoutSettings {
+            max_failures: 3,
+            ip_max_failures: 4,
+            backoff_base_seconds: 0,
+            ..LockoutSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let settings = LockoutSettings::default();
+
+        assert_eq!(backoff(&settings, 1), Duration::seconds(1));
+        assert_eq!(backoff(&settings, 3), Duration::seconds(4));
+        assert_eq!(backoff(&settings, 10), Duration::seconds(30));
+        assert_eq!(backoff(&settings, i32::MAX), Duration::seconds(30));
+    }
+
+    #[sqlx::test]
+    async fn test_backoff_after_failure(pool: PgPool) {
+        let throttle = throttle(&pool, LockoutSettings::default());
+
+        assert!(throttle.check("foo", None).await.is_ok());
+        assert!(matches!(throttle.record_failure("foo", None).await, AuthError::WrongCredentials));
+        assert!(matches!(throttle.check("Foo", None).await, Err(AuthError::AccountLocked(_))));
+        assert!(throttle.check("bar", None).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_lock_after_max_failures(pool: PgPool) {
+        let throttle = throttle(&pool, without_backoff());
+
+        throttle.record_failure("foo", None).await;
+        throttle.record_failure("foo", None).await;
+
+        assert!(throttle.check("foo", None).await.is_ok());
+        assert!(matches!(throttle.record_failure("foo", None).await, AuthError::AccountLocked(_)));
+        assert!(matches!(throttle.check("foo", None).await, Err(AuthError::AccountLocked(_))));
+
+        let audit = AuditRepository::new(&pool).find_by_subject("foo").await.unwrap();
+
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].action, "account_locked");
+    }
+
+    #[sqlx::test]
+    async fn test_success_resets_failures(pool: PgPool) {
+        let throttle = throttle(&pool, without_backoff());
+
+        throttle.record_failure("foo", None).await;
+        throttle.record_failure("foo", None).await;
+        throttle.record_success("foo").await;
+        throttle.record_failure("foo", None).await;
+
+        assert!(throttle.check("foo", None).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_lock_ip(pool: PgPool) {
+        let throttle = throttle(&pool, without_backoff());
+        let ip = Some(IpAddr::from([10, 0, 0, 1]));
+
+        for user_name in ["a", "b", "c", "d"] {
+            throttle.record_failure(user_name, ip).await;
+        }
+
+        assert!(matches!(throttle.check("e", ip).await, Err(AuthError::AccountLocked(_))));
+        assert!(throttle.check("e", None).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_unlock(pool: PgPool) {
+        let throttle = throttle(&pool, without_backoff());
+
+        for _ in 0..3 {
+            throttle.record_failure("foo", None).await;
+        }
+
+        assert!(throttle.unlock("foo", "1").await.unwrap());
+        assert!(throttle.check("foo", None).await.is_ok());
+        assert!(!throttle.unlock("foo", "1").await.unwrap());
+
+        let audit = AuditRepository::new(&pool).find_by_subject("foo").await.unwrap();
+
+        assert_eq!(audit.last().unwrap().action, "account_unlocked");
+        assert_eq!(audit.last().unwrap().actor.as_deref(), Some("1"));
+    }
+}
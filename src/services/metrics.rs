@@ -0,0 +1,142 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Prometheus metrics of the app, kept in their own registry so every app instance (and test)
+/// reports its own values.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_connections: IntGauge,
+    db_connections_idle: IntGauge,
+    db_connections_active: IntGauge,
+    logins: IntCounterVec,
+    user_operations: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests handled"),
+            &["method", "path", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency in seconds"),
+            &["method", "path"],
+        )
+        .unwrap();
+        let db_connections =
+            IntGauge::new("db_pool_connections", "Connections currently held by the pool").unwrap();
+        let db_connections_idle =
+            IntGauge::new("db_pool_connections_idle", "Idle connections in the pool").unwrap();
+        let db_connections_active =
+            IntGauge::new("db_pool_connections_active", "Connections in use by a query").unwrap();
+        let logins = IntCounterVec::new(
+            Opts::new("auth_logins_total", "Number of login attempts"),
+            &["outcome"],
+        )
+        .unwrap();
+        let user_operations = IntCounterVec::new(
+            Opts::new("user_operations_total", "Number of UserManager operations"),
+            &["operation", "outcome"],
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(db_connections.clone()),
+            Box::new(db_connections_idle.clone()),
+            Box::new(db_connections_active.clone()),
+            Box::new(logins.clone()),
+            Box::new(user_operations.clone()),
+        ] {
+            registry.register(collector).unwrap();
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_connections,
+            db_connections_idle,
+            db_connections_active,
+            logins,
+            user_operations,
+        }
+    }
+
+    pub fn record_request(&self, method: &str, path: &str, status: u16, duration: Duration) {
+        self.http_requests
+            .with_label_values(&[method, path, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, path])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn record_login(&self, success: bool) {
+        let outcome = if success { "success" } else { "failure" };
+
+        self.logins.with_label_values(&[outcome]).inc();
+    }
+
+    /// Counts a `UserManager` operation, using `ok` or the error code as the outcome.
+    pub fn record_user_operation(&self, operation: &str, outcome: &str) {
+        self.user_operations
+            .with_label_values(&[operation, outcome])
+            .inc();
+    }
+
+    /// Renders every metric in the Prometheus text format, sampling the pool gauges first when
+    /// there is a pool.
+    pub fn render(&self, pool: Option<&PgPool>) -> String {
+        if let Some(pool) = pool {
+            let size = i64::from(pool.size());
+            let idle = i64::try_from(pool.num_idle()).unwrap_or(i64::MAX);
+
+            self.db_connections.set(size);
+            self.db_connections_idle.set(idle);
+            self.db_connections_active.set((size - idle).max(0));
+        }
+
+        let mut buffer = Vec::new();
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_render(pool: PgPool) {
+        let metrics = Metrics::new();
+
+        metrics.record_request("GET", "/user/{id}", 200, Duration::from_millis(5));
+        metrics.record_login(false);
+        metrics.record_user_operation("create", "ok");
+
+        let text = metrics.render(Some(&pool));
+
+        assert!(text.contains(r#"http_requests_total{method="GET",path="/user/{id}",status="200"} 1"#));
+        assert!(text.contains(r#"http_request_duration_seconds_count{method="GET",path="/user/{id}"} 1"#));
+        assert!(text.contains(r#"auth_logins_total{outcome="failure"} 1"#));
+        assert!(text.contains(r#"user_operations_total{operation="create",outcome="ok"} 1"#));
+        assert!(text.contains("db_pool_connections_idle"));
+    }
+}
@@ -1,3 +1,15 @@
+mod access_control;
 mod auth_service;
+mod health_service;
+mod login_throttle;
+mod metrics;
+mod rate_limit;
+mod revocation_store;
 
+pub use access_control::*;
 pub use auth_service::*;
+pub use health_service::*;
+pub use login_throttle::*;
+pub use metrics::*;
+pub use rate_limit::*;
+pub use revocation_store::*;
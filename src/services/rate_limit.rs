@@ -0,0 +1,192 @@
+use crate::config::settings::{RateLimitPolicy, RateLimitSettings};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Groups of routes sharing a rate limit policy. Public routes are limited per client IP and
+/// protected routes per authenticated user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteGroup {
+    Public,
+    Protected,
+}
+
+impl Display for RouteGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteGroup::Public => write!(f, "public"),
+            RouteGroup::Protected => write!(f, "protected"),
+        }
+    }
+}
+
+/// Outcome of taking a token from a bucket, used to fill the `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the bucket is full again.
+    pub reset: Duration,
+    /// Time until the next token is available, zero when the request was allowed.
+    pub retry_after: Duration,
+}
+
+/// Storage of token buckets. The in-memory store limits each instance on its own, a shared store
+/// can implement this trait to enforce the limits across instances.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket of `key`, creating a full bucket if it doesn't exist.
+    async fn acquire(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision;
+}
+
+#[derive(Default)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+impl InMemoryRateLimitStore {
+    /// Number of buckets above which buckets that have refilled completely are dropped.
+    const MAX_BUCKETS: usize = 10_000;
+
+    fn acquire_at(&self, key: &str, policy: &RateLimitPolicy, now: Instant) -> RateLimitDecision {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let capacity = f64::from(policy.capacity);
+
+        if buckets.len() >= Self::MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            full_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * policy.refill_per_second).min(capacity);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        let retry_after = if allowed {
+            bucket.tokens -= 1.0;
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / policy.refill_per_second)
+        };
+        let reset = Duration::from_secs_f64((capacity - bucket.tokens) / policy.refill_per_second);
+        bucket.full_at = now + reset;
+
+        RateLimitDecision {
+            allowed,
+            limit: policy.capacity,
+            remaining: bucket.tokens.floor() as u32,
+            reset,
+            retry_after,
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn acquire(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision {
+        self.acquire_at(key, policy, Instant::now())
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    settings: Arc<RateLimitSettings>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, settings: RateLimitSettings) -> Self {
+        Self {
+            store,
+            settings: Arc::new(settings),
+        }
+    }
+
+    pub fn in_memory(settings: RateLimitSettings) -> Self {
+        Self::new(Arc::new(InMemoryRateLimitStore::default()), settings)
+    }
+
+    /// Counts a request of `client` against the policy of `group`, returning `None` when rate
+    /// limiting is disabled.
+    pub async fn check(&self, group: RouteGroup, client: &str) -> Option<RateLimitDecision> {
+        if !self.settings.enabled {
+            return None;
+        }
+
+        let policy = match group {
+            RouteGroup::Public => &self.settings.public,
+            RouteGroup::Protected => &self.settings.protected,
+        };
+
+        Some(self.store.acquire(&format!("{group}:{client}"), policy).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RateLimitPolicy = RateLimitPolicy {
+        capacity: 2,
+        refill_per_second: 0.5,
+    };
+
+    #[test]
+    fn test_bucket_empties_and_refills() {
+        let store = InMemoryRateLimitStore::default();
+        let now = Instant::now();
+
+        let first = store.acquire_at("client", &POLICY, now);
+        let second = store.acquire_at("client", &POLICY, now);
+        let third = store.acquire_at("client", &POLICY, now);
+
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.reset, Duration::from_secs(4));
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after, Duration::from_secs(2));
+
+        let refilled = store.acquire_at("client", &POLICY, now + Duration::from_secs(2));
+
+        assert!(refilled.allowed);
+        assert_eq!(refilled.remaining, 0);
+    }
+
+    #[test]
+    fn test_buckets_are_per_key() {
+        let store = InMemoryRateLimitStore::default();
+        let now = Instant::now();
+
+        store.acquire_at("a", &POLICY, now);
+        store.acquire_at("a", &POLICY, now);
+
+        assert!(!store.acquire_at("a", &POLICY, now).allowed);
+        assert!(store.acquire_at("b", &POLICY, now).allowed);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let settings = RateLimitSettings {
+            enabled: false,
+            ..RateLimitSettings::default()
+        };
+        let limiter = RateLimiter::in_memory(settings);
+
+        assert_eq!(limiter.check(RouteGroup::Public, "client").await, None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::model::auth::JwtClaims;
+use crate::model::auth_error::AuthError;
+use crate::repository::ArcRevokedTokenStore;
+use crate::util;
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Tracks revoked access tokens. Revocations are persisted to the `RevokedTokenStore` so they are
+/// shared between instances, while lookups are cached in-process. Revoked entries are cached until
+/// the token expires, and unrevoked entries are re-checked once `CACHE_TTL` has passed so that
+/// revocations made by other instances are picked up. Expired revocations are purged from the
+/// store at most once per `PURGE_INTERVAL`.
+#[derive(Clone)]
+pub struct RevocationStore {
+    repository: ArcRevokedTokenStore,
+    cache: Arc<RwLock<HashMap<Uuid, CachedRevocation>>>,
+    last_purge: Arc<Mutex<Option<Instant>>>,
+}
+
+#[derive(Clone, Copy)]
+struct CachedRevocation {
+    user_id: i32,
+    issued: NaiveDateTime,
+    revoked: bool,
+    exp: usize,
+    checked_at: Instant,
+}
+
+impl RevocationStore {
+    const CACHE_TTL: Duration = Duration::from_secs(30);
+    /// Number of cached tokens above which the entries of expired tokens are dropped.
+    const MAX_CACHED: usize = 10_000;
+    const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(repository: ArcRevokedTokenStore) -> Self {
+        Self {
+            repository,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            last_purge: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Fails with `AuthError::ServiceUnavailable` if the revocations can't be read.
+    pub async fn is_revoked(&self, claims: &JwtClaims) -> Result<bool, AuthError> {
+        let Some(user_id) = claims.user_id() else {
+            return Ok(true);
+        };
+
+        if let Some(entry) = self.cached(&claims.jti)
+            && (entry.revoked || entry.checked_at.elapsed() < Self::CACHE_TTL)
+        {
+            return Ok(entry.revoked);
+        }
+
+        let revoked = self
+            .repository
+            .is_revoked(&claims.jti, user_id, claims.issued_at())
+            .await
+            .map_err(|e| {
+                error!("Unable to check revocation status of token {}: {e}", claims.jti);
+                AuthError::ServiceUnavailable
+            })?;
+
+        self.cache(claims, user_id, revoked);
+        Ok(revoked)
+    }
+
+    pub async fn revoke(&self, claims: &JwtClaims) -> Result<(), AuthError> {
+        let Some(user_id) = claims.user_id() else {
+            return Err(AuthError::InvalidToken);
+        };
+
+        info!("Revoking token {} for user {user_id}", claims.jti);
+
+        self.repository
+            .revoke(&claims.jti, user_id, to_timestamp(claims.exp))
+            .await
+            .map_err(|e| {
+                error!("Unable to revoke token {}: {e}", claims.jti);
+                AuthError::ServiceUnavailable
+            })?;
+        self.cache(claims, user_id, true);
+
+        if self.purge_due()
+            && let Err(e) = self.repository.purge_expired().await
+        {
+            warn!("Unable to purge expired token revocations: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Whether `PURGE_INTERVAL` has passed since the last purge, marking a new one as started.
+    fn purge_due(&self) -> bool {
+        let Ok(mut last_purge) = self.last_purge.lock() else {
+            return false;
+        };
+
+        if last_purge.is_some_and(|purged_at| purged_at.elapsed() < Self::PURGE_INTERVAL) {
+            return false;
+        }
+
+        *last_purge = Some(Instant::now());
+        true
+    }
+
+    /// Revokes every token of the user issued up to now, the same way the store does.
+    pub async fn revoke_user(&self, user_id: i32) -> Result<(), AuthError> {
+        info!("Revoking all sessions for user {user_id}");
+
+        // the store keeps microseconds, so cut the rest off for the cache to agree with it
+        let revoked_before = Utc::now().naive_utc().trunc_subsecs(6);
+
+        self.repository
+            .revoke_user_sessions(user_id, revoked_before)
+            .await
+            .map_err(|e| {
+                error!("Unable to revoke the sessions of user {user_id}: {e}");
+                AuthError::ServiceUnavailable
+            })?;
+
+        if let Ok(mut cache) = self.cache.write() {
+            cache
+                .values_mut()
+                .filter(|entry| entry.user_id == user_id && entry.issued <= revoked_before)
+                .for_each(|entry| entry.revoked = true);
+        }
+
+        Ok(())
+    }
+
+    fn cached(&self, jti: &Uuid) -> Option<CachedRevocation> {
+        self.cache.read().ok()?.get(jti).copied()
+    }
+
+    fn cache(&self, claims: &JwtClaims, user_id: i32, revoked: bool) {
+        let Ok(mut cache) = self.cache.write() else {
+            return;
+        };
+
+        if cache.len() >= Self::MAX_CACHED && !cache.contains_key(&claims.jti) {
+            let now = util::now_epoch();
+            cache.retain(|_, entry| entry.exp > now);
+        }
+
+        cache.insert(claims.jti, CachedRevocation {
+            user_id,
+            issued: claims.issued_at(),
+            revoked,
+            exp: claims.exp,
+            checked_at: Instant::now(),
+        });
+    }
+}
+
+fn to_timestamp(epoch: usize) -> NaiveDateTime {
+    DateTime::from_timestamp(epoch as i64, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{InMemoryRevokedTokenStore, RevokedTokenStore};
+
+    fn expired_claims() -> JwtClaims {
+        JwtClaims {
+            sub: "1".to_string(),
+            exp: util::now_epoch() - 60,
+            iat: util::now_epoch() - 120,
+            iat_us: None,
+            jti: Uuid::new_v4(),
+            sid: Uuid::new_v4(),
+            roles: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purge_once_per_interval() {
+        let repository = Arc::new(InMemoryRevokedTokenStore::default());
+        let store = RevocationStore::new(repository.clone());
+
+        store.revoke(&expired_claims()).await.unwrap();
+        store.revoke(&expired_claims()).await.unwrap();
+
+        // the first revocation purged itself, the second one is left for the next interval
+        assert_eq!(repository.purge_expired().await, Ok(1));
+    }
+}
@@ -1,45 +1,141 @@
 mod users_api;
 
-use crate::services::AuthService;
+use crate::config::authentication::Keys;
+use crate::config::settings::{DatabaseSettings, Settings};
+use crate::repository::{
+    ArcAuditRecorder, AuditRepository, InMemoryRepository,
+    InMemoryRevokedTokenStore, RevokedTokenRepository,
+};
+use crate::services::{
+    AccessControl, AuthService, HealthService, LoginThrottle, Metrics, RateLimiter,
+    RevocationStore,
+};
 pub(crate) use crate::state::users_api::UsersApi;
 use axum::extract::FromRef;
-use log::info;
+use log::{info, warn};
 use sqlx::postgres::PgPoolOptions;
+use sqlx::migrate::Migrator;
 use sqlx::{migrate, PgPool};
-use std::env;
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+pub static MIGRATOR: Migrator = migrate!("./migrations");
 
 #[derive(Clone, FromRef)]
 pub struct AppState {
     pub users_api: UsersApi,
     pub auth_service: AuthService,
+    pub login_throttle: LoginThrottle,
+    pub revocation_store: RevocationStore,
+    pub access_control: AccessControl,
+    pub health_service: HealthService,
+    pub metrics: Metrics,
+    pub rate_limiter: RateLimiter,
+    /// `None` when running on in-memory repositories.
+    pub pool: Option<PgPool>,
+    pub keys: Keys,
+    pub settings: Arc<Settings>,
+}
+
+/// Repositories the services of the app are built on.
+struct Repositories {
+    users_api: UsersApi,
+    revocation_store: RevocationStore,
+    audit: ArcAuditRecorder,
 }
 
 impl AppState {
-    pub async fn new(pool: PgPool) -> Self {
+    pub async fn new(settings: Settings, pool: PgPool, keys: Keys) -> Self {
         info!("Running database migrations...");
-        let _ = migrate!("./migrations")
+        let _ = MIGRATOR
             .run(&pool)
             .await
             .map_err(Error::other);
         info!("Done!");
 
-        let users_api = UsersApi::new(&pool);
-        let auth_service = AuthService::new();
+        let metrics = Metrics::new();
+        let revocation_store = RevocationStore::new(Arc::new(RevokedTokenRepository::new(&pool)));
+        let repositories = Repositories {
+            users_api: UsersApi::new(&pool, &revocation_store, &metrics),
+            revocation_store,
+            audit: Arc::new(AuditRepository::new(&pool)),
+        };
+
+        Self::with_repositories(settings, Some(pool), keys, metrics, repositories).await
+    }
+
+    /// Keeps every entity in memory instead of in Postgres, so nothing survives a restart.
+    pub async fn in_memory(settings: Settings, keys: Keys) -> Self {
+        warn!("Running on in-memory repositories, data is lost when the app stops");
+
+        let metrics = Metrics::new();
+        let audit = InMemoryRepository::new();
+        let revocation_store = RevocationStore::new(Arc::new(InMemoryRevokedTokenStore::default()));
+        let repositories = Repositories {
+            users_api: UsersApi::in_memory(&audit, &revocation_store, &metrics),
+            revocation_store,
+            audit: Arc::new(audit),
+        };
+
+        Self::with_repositories(settings, None, keys, metrics, repositories).await
+    }
+
+    async fn with_repositories(
+        settings: Settings,
+        pool: Option<PgPool>,
+        keys: Keys,
+        metrics: Metrics,
+        repositories: Repositories,
+    ) -> Self {
+        let users_api = repositories.users_api;
+        let revocation_store = repositories.revocation_store;
+
+        if let (Some(user_name), Some(password)) =
+            (&settings.auth.admin_user_name, &settings.auth.admin_password)
+        {
+            users_api.bootstrap_admin(user_name, password).await;
+        }
+
+        let access_control = AccessControl::new(users_api.role_permission_repository.clone());
+        let health_service = HealthService::new(pool.clone(), keys.clone());
+        let rate_limiter = RateLimiter::in_memory(settings.rate_limit.clone());
+        let login_throttle = LoginThrottle::new(
+            users_api.login_failure_repository.clone(),
+            repositories.audit,
+            &settings.lockout,
+        );
+        let auth_service = AuthService::new(
+            &users_api,
+            revocation_store.clone(),
+            login_throttle.clone(),
+            keys.clone(),
+            &settings.auth,
+        );
 
         Self {
             users_api,
-            auth_service
+            auth_service,
+            login_throttle,
+            revocation_store,
+            access_control,
+            health_service,
+            metrics,
+            rate_limiter,
+            pool,
+            keys,
+            settings: Arc::new(settings),
         }
     }
 
-    pub async fn get_pool() -> Result<PgPool, Error> {
-        let db_url = env::var("DATABASE_URL")
-            .map_err(|_| Error::new(ErrorKind::NotConnected, "DATABASE_URL not set"))?;
+    pub async fn get_pool(settings: &DatabaseSettings) -> Result<PgPool, Error> {
+        let db_url = settings
+            .url
+            .as_deref()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "DATABASE_URL not set"))?;
 
         PgPoolOptions::new()
-            .max_connections(5)
-            .connect(&db_url)
+            .max_connections(settings.max_connections)
+            .connect(db_url)
             .await
             .map_err(Error::other)
     }
@@ -1,23 +1,129 @@
 use crate::manager::UserManager;
-use crate::repository::UserRepository;
+use crate::model::audit::AuditEntry;
+use crate::services::{Metrics, RevocationStore};
+use crate::repository::repository_traits::{ArcRepository, Repository};
+use crate::repository::{
+    ArcLoginFailureStore, ArcRefreshTokenStore, ArcRolePermissionRepository,
+    ArcUnitOfWorkFactory, ArcUserNameRepository, ArcUserRoleRepository, InMemoryLoginFailureStore,
+    InMemoryRepository, InMemoryRoleRepository, InMemoryUnitOfWorkFactory, LoginFailureRepository,
+    PgUnitOfWorkFactory, RefreshTokenRepository, RolePermissionRepository, RoleRepository,
+    UserNameRepository, UserRepository, UserRoleRepository,
+};
+use crate::model::user::{User, UserDto};
+use crate::repository::RepositoryError;
 use axum::extract::FromRef;
+use log::{error, info};
 use sqlx::PgPool;
 use std::sync::Arc;
 
 #[derive(Clone, FromRef)]
 pub struct UsersApi {
-    pub user_repository: Arc<UserRepository>,
+    pub user_repository: ArcRepository<User, i32>,
+    pub user_name_repository: ArcUserNameRepository,
+    pub role_repository: ArcUserRoleRepository,
+    pub role_permission_repository: ArcRolePermissionRepository,
+    pub refresh_token_repository: ArcRefreshTokenStore,
+    pub login_failure_repository: ArcLoginFailureStore,
     pub user_manager: UserManager,
 }
 
 impl UsersApi {
-    pub fn new(pool: &PgPool) -> Self {
-        let user_repository = Arc::new(UserRepository::new(pool));
-        let user_manager = UserManager::new(user_repository.clone());
+    pub fn new(pool: &PgPool, revocation_store: &RevocationStore, metrics: &Metrics) -> Self {
+        Self::with_repositories(
+            Arc::new(UserRepository::new(pool)),
+            Arc::new(RoleRepository::new(pool)),
+            Arc::new(RefreshTokenRepository::new(pool)),
+            Arc::new(LoginFailureRepository::new(pool)),
+            Arc::new(PgUnitOfWorkFactory::new(pool)),
+            revocation_store,
+            metrics,
+        )
+    }
+
+    /// Keeps users and their roles in memory, recording audit entries to `audit`.
+    pub fn in_memory(
+        audit: &InMemoryRepository<AuditEntry, i32>,
+        revocation_store: &RevocationStore,
+        metrics: &Metrics,
+    ) -> Self {
+        let user_repository = InMemoryRepository::new();
+        let role_repository = InMemoryRoleRepository::new(&user_repository);
+        let unit_of_work =
+            InMemoryUnitOfWorkFactory::new(&user_repository, &role_repository, audit);
+
+        Self::with_repositories(
+            Arc::new(user_repository),
+            Arc::new(role_repository),
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(InMemoryLoginFailureStore::default()),
+            Arc::new(unit_of_work),
+            revocation_store,
+            metrics,
+        )
+    }
+
+    fn with_repositories<U, R>(
+        user_repository: Arc<U>,
+        role_repository: Arc<R>,
+        refresh_token_repository: ArcRefreshTokenStore,
+        login_failure_repository: ArcLoginFailureStore,
+        unit_of_work: ArcUnitOfWorkFactory,
+        revocation_store: &RevocationStore,
+        metrics: &Metrics,
+    ) -> Self
+    where
+        U: Repository<User, i32> + UserNameRepository + Send + Sync + 'static,
+        R: UserRoleRepository + RolePermissionRepository + Send + Sync + 'static,
+    {
+        let user_manager = UserManager::new(
+            user_repository.clone(),
+            user_repository.clone(),
+            refresh_token_repository.clone(),
+            login_failure_repository.clone(),
+            unit_of_work,
+            revocation_store.clone(),
+            metrics.clone(),
+        );
 
         Self {
-            user_repository,
-            user_manager
+            user_repository: user_repository.clone(),
+            user_name_repository: user_repository,
+            role_repository: role_repository.clone(),
+            role_permission_repository: role_repository,
+            refresh_token_repository,
+            login_failure_repository,
+            user_manager,
+        }
+    }
+
+    pub async fn bootstrap_admin(&self, user_name: &str, password: &str) {
+        let user = match self.user_name_repository.find_by_user_name(user_name).await {
+            Ok(user) => user.id,
+            Err(RepositoryError::NotFound) => {
+                info!("Creating bootstrap admin user: {user_name}");
+
+                let payload = UserDto {
+                    id: None,
+                    user_name: Some(user_name.to_string()),
+                    password: Some(password.to_string()),
+                    ..UserDto::default()
+                };
+
+                self.user_manager.create_user(&payload).await.ok().and_then(|u| u.id)
+            }
+            Err(e) => {
+                error!("Unable to look up bootstrap admin user {user_name}: {e}");
+                None
+            }
+        };
+
+        let assigned = match user {
+            Some(id) => self.role_repository.assign_role(&id, "admin").await.is_ok(),
+            None => false,
+        };
+
+        if !assigned {
+            error!("Unable to bootstrap admin user: {user_name}");
         }
     }
 }
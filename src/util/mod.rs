@@ -1,3 +1,7 @@
+pub mod password;
+pub mod token;
+pub mod validation;
+
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait AsDtoEnabled<T> {
@@ -0,0 +1,64 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{Error, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::sync::LazyLock;
+use tokio::task::spawn_blocking;
+
+/// Hash checked when there is no stored hash, so that a missing user costs as much as a wrong
+/// password.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("dummy password").expect("Dummy password could not be hashed"));
+
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+/// Runs `hash_password` on the blocking thread pool, keeping Argon2 off the async workers.
+pub async fn hash_password_blocking(password: &str) -> Result<String, Error> {
+    let password = password.to_string();
+
+    spawn_blocking(move || hash_password(&password))
+        .await
+        .unwrap_or(Err(Error::Crypto))
+}
+
+/// Runs `verify_password` on the blocking thread pool. Without a stored hash the password is
+/// checked against a dummy hash and rejected, taking as long as a wrong password would.
+pub async fn verify_password_blocking(password: &str, password_hash: Option<&str>) -> bool {
+    let password = password.to_string();
+    let stored = password_hash.map(str::to_string);
+
+    spawn_blocking(move || match stored {
+        Some(hash) => verify_password(&password, &hash),
+        None => {
+            verify_password(&password, &DUMMY_HASH);
+            false
+        }
+    })
+    .await
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_password_blocking() {
+        let hash = hash_password_blocking("bar").await.unwrap();
+
+        assert!(verify_password_blocking("bar", Some(&hash)).await);
+        assert!(!verify_password_blocking("baz", Some(&hash)).await);
+        assert!(!verify_password_blocking("dummy password", None).await);
+    }
+}
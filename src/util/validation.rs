@@ -0,0 +1,89 @@
+use crate::model::api_response::FieldError;
+use serde_json::Value;
+use std::borrow::Cow;
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+/// Operation a payload is sent for, taken from the request method: `POST` creates while `PUT`
+/// and `PATCH` update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Create,
+    Update,
+}
+
+/// Declares which fields a payload must provide for each operation, on top of the constraints
+/// checked by its `Validate` implementation.
+pub trait RequiredFields {
+    const REQUIRED_ON_CREATE: &'static [&'static str] = &[];
+    const REQUIRED_ON_UPDATE: &'static [&'static str] = &[];
+}
+
+/// Flattens validation errors into one entry per failing rule, using paths such as
+/// `users[1].email` for nested fields.
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut result = Vec::new();
+    collect_field_errors(errors, "", &mut result);
+    result.sort_by(|a, b| a.field.cmp(&b.field));
+    result
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, result: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = match field.as_ref() {
+            "__all__" if prefix.is_empty() => String::new(),
+            "__all__" => prefix.to_string(),
+            field if prefix.is_empty() => field.to_string(),
+            field => format!("{prefix}.{field}"),
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => result.extend(errors.iter().map(|error| {
+                FieldError {
+                    field: path.clone(),
+                    code: error.code.to_string(),
+                    message: error
+                        .message
+                        .clone()
+                        .unwrap_or(Cow::Borrowed("is invalid"))
+                        .to_string(),
+                }
+            })),
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, result),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(errors, &format!("{path}[{index}]"), result);
+                }
+            }
+        }
+    }
+}
+
+pub fn missing_fields<T: RequiredFields>(value: &Value, operation: Operation) -> Vec<FieldError> {
+    let required = match operation {
+        Operation::Create => T::REQUIRED_ON_CREATE,
+        Operation::Update => T::REQUIRED_ON_UPDATE,
+    };
+
+    required
+        .iter()
+        .filter(|field| value.get(field).is_none_or(Value::is_null))
+        .map(|field| FieldError {
+            field: field.to_string(),
+            code: "required".to_string(),
+            message: "is required".to_string(),
+        })
+        .collect()
+}
+
+/// Checks `payload`, parsed from `value`, for missing required fields and broken constraints,
+/// returning every failing field.
+pub fn validate<T: Validate + RequiredFields>(value: &Value, payload: &T, operation: Operation) -> Vec<FieldError> {
+    let mut errors = missing_fields::<T>(value, operation);
+
+    if let Err(e) = payload.validate() {
+        errors.extend(field_errors(&e));
+    }
+
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+    errors
+}